@@ -18,7 +18,7 @@ use std::time::{Duration, Instant};
 use eframe::egui;
 use eframe::epaint::Color32;
 use image::with_image;
-use node::{Node, Population, Pos, Quadrant, Rect};
+use node::{Node, Population, Pos, Quadrant, Rect, Rule};
 use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::EnvFilter;
 
@@ -72,6 +72,7 @@ enum CursorMode {
 
 struct Board {
     node: Node,
+    rule: Rule,
 
     generation: u64,
     step_size: NonZeroU64,
@@ -101,8 +102,10 @@ impl Board {
     const FOOTER_HEIGHT: f32 = 10.0;
 
     pub fn new_centered(node: Node, clipboard: Node) -> Self {
+        let center = Board::bounding_center(&node);
         Self {
             node,
+            rule: Rule::LIFE,
 
             generation: 0,
             step_size: NonZeroU64::new(1).unwrap(),
@@ -110,8 +113,7 @@ impl Board {
             last_time: Instant::now(),
             play: true,
 
-            // TODO infer default center based on node bounding box
-            center: Pos { x: 0, y: 0 },
+            center,
             center_fine: egui::vec2(0.0, 0.0),
             // TODO infer default zoom based on ui rect and node bounding box
             zoom_power: 2,
@@ -121,6 +123,16 @@ impl Board {
         }
     }
 
+    /// the center of the live cells, or the origin if there aren't any
+    fn bounding_center(node: &Node) -> Pos {
+        let rect = node.bounding_rect();
+        if rect.is_empty() {
+            Pos { x: 0, y: 0 }
+        } else {
+            rect.cc()
+        }
+    }
+
     fn slow_play_delay(&self) -> Duration {
         Duration::from_nanos(Board::MIN_PLAY_NANOS / (1 << (self.play_power - Board::MIN_PLAY)))
     }
@@ -196,11 +208,8 @@ impl egui::Widget for &mut Board {
             if input.key_pressed(egui::Key::C) || input.key_pressed(egui::Key::X) {
                 if let CursorMode::Select(_, rect) = self.cursor {
                     if !rect.is_empty() {
-                        let node = self.node.clip(rect);
-                        // TODO using offset_norm to center isn't really ideal
-                        // but is better than nothing
-                        let (_, node) = node.offset_norm();
-                        self.clipboard = node;
+                        let (_, node) = self.node.clip(rect).offset_norm();
+                        self.clipboard = node.offset(-Board::bounding_center(&node));
                     }
                 }
             }
@@ -304,7 +313,7 @@ impl egui::Widget for &mut Board {
         if let Some(steps) = NonZeroU64::new(steps) {
             self.last_time = now;
             self.generation += steps.get();
-            self.node = self.node.step_non_zero(steps);
+            self.node = self.node.step_non_zero(&self.rule, steps);
             if self.play {
                 if self.play_power >= 0 {
                     ui.ctx().request_repaint();
@@ -0,0 +1,37 @@
+//! `cargo +nightly bench -p node`, compares stepping through the isotropic lookup table with the bitwise totalistic step
+#![feature(test)]
+extern crate test;
+
+use std::cell::Cell;
+
+use node::{Rule, SoupSearch};
+use test::Bencher;
+
+/// a new soup each iteration, so the step cache doesn't already have the result
+fn bench_soups(b: &mut Bencher, rule: &Rule) {
+    let search = SoupSearch::new("bench");
+    let index = Cell::new(0);
+    b.iter(|| {
+        index.set(index.get() + 1);
+        search.soup(index.get()).step(rule, 256)
+    });
+}
+
+#[bench]
+fn totalistic_life(b: &mut Bencher) {
+    bench_soups(b, &Rule::LIFE);
+}
+#[bench]
+fn totalistic_highlife(b: &mut Bencher) {
+    // through the count masks instead of the step that only does life
+    bench_soups(b, &"B36/S23".parse().unwrap());
+}
+#[bench]
+fn isotropic_almost_life(b: &mut Bencher) {
+    // 1 letter away from `B3/S23` so soups evolve about the same, but go through the lookup table
+    bench_soups(b, &"B3/S23-a".parse().unwrap());
+}
+#[bench]
+fn isotropic_tlife(b: &mut Bencher) {
+    bench_soups(b, &"B3/S2-i34q".parse().unwrap());
+}
@@ -0,0 +1,43 @@
+//! `soup_search <seed> [soups] [rule]` runs soups `0..soups` of `seed` and prints the census to stdout
+
+use std::process::ExitCode;
+
+use node::{Census, Rule, SoupSearch};
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let Some(seed) = args.next() else {
+        eprintln!("usage: soup_search <seed> [soups] [rule]");
+        return ExitCode::FAILURE;
+    };
+    let soups = match args.next().map(|soups| soups.parse::<u64>()) {
+        None => 1000,
+        Some(Ok(soups)) => soups,
+        Some(Err(err)) => {
+            eprintln!("invalid number of soups: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let rule = match args.next().map(|rule| rule.parse::<Rule>()) {
+        None => Rule::LIFE,
+        Some(Ok(rule)) if rule.states() != 2 => {
+            eprintln!("soups can only be searched with two-state rules");
+            return ExitCode::FAILURE;
+        }
+        Some(Ok(rule)) => rule,
+        Some(Err(err)) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut census = Census::new();
+    SoupSearch::new(seed)
+        .with_rule(rule)
+        .search(0..soups, &mut census);
+    if let Err(err) = census.write_text(std::io::stdout().lock()) {
+        eprintln!("{err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
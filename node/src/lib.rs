@@ -22,33 +22,94 @@
 )]
 
 mod block;
+mod multi_node;
 mod node;
+mod pattern;
 mod pos;
 mod quad;
 mod rect;
+mod rules {
+    mod hensel;
+    mod ltl;
+    mod margolus;
+    mod rule;
+    mod rule_file;
+    mod topology;
+
+    pub(crate) use ltl::{Ltl, LtlNeighbourhood};
+    pub use margolus::MargolusRule;
+
+    pub use rule::*;
+    pub(crate) use rule::{Neighbourhood, Transition};
+    pub use rule_file::{RuleFileError, RuleFileErrorKind};
+    pub use topology::*;
+}
 mod ops {
+    #[cfg(feature = "animation")]
+    mod animation_format;
+    mod any_format;
+    mod apgcode;
     mod bit;
+    mod blocks;
+    mod bounded;
+    mod bounding_rect;
+    mod cells_format;
     mod center;
     mod children;
     mod clip;
+    mod components;
+    mod find;
     mod get;
+    #[cfg(feature = "image")]
+    mod image_format;
+    mod life_format;
+    mod margolus;
     mod mc_format;
     mod offset;
     mod offset_norm;
+    mod periodicity;
     mod population;
     mod reduce;
+    mod rle_format;
     mod rotate;
+    #[cfg(feature = "snapshot")]
+    mod snapshot_format;
+    #[cfg(feature = "soup-search")]
+    mod soup_search;
     mod step;
+    mod svg_format;
     mod test_format;
 
+    #[cfg(feature = "animation")]
+    pub use animation_format::*;
+    pub use any_format::*;
+    pub use apgcode::*;
+    pub use cells_format::*;
+    pub use components::*;
+    pub use find::*;
+    #[cfg(feature = "image")]
+    pub use image_format::*;
+    pub use life_format::*;
     pub use mc_format::*;
+    pub use periodicity::*;
     pub use population::*;
+    pub use rle_format::*;
+    pub use rotate::*;
+    #[cfg(feature = "snapshot")]
+    pub use snapshot_format::*;
+    #[cfg(feature = "soup-search")]
+    pub use soup_search::*;
+    pub use svg_format::*;
     pub use test_format::*;
 }
 
 pub use crate::node::*;
 pub use block::*;
+pub use multi_node::*;
+pub use num_bigint::BigUint;
 pub use ops::*;
+pub use pattern::*;
 pub use pos::*;
 pub use quad::*;
 pub use rect::*;
+pub use rules::*;
@@ -0,0 +1,225 @@
+//! multi-state cells stored as bit-planes of two-state nodes
+//!
+//! the state of a cell is binary encoded across the planes, plane `i` holds bit `i` of every state
+
+use std::rc::Rc;
+
+use crate::{Block, DepthQuad, Node, Population, Quad};
+
+/// the bit-planes of an 8x8 area of multi-state cells
+pub(crate) type BlockPlanes = Rc<[Block]>;
+
+/// A multi-state node, shares the hash-consed `Node`s of each of its bit-planes
+///
+/// always has at least 1 plane, and never has trailing empty planes
+/// so equal patterns are always equal `MultiNode`s
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct MultiNode {
+    planes: Rc<[Node]>,
+}
+
+impl MultiNode {
+    /// planes can have different depths, and will be centered at the largest depth
+    pub fn new(planes: Vec<Node>) -> Self {
+        let depth = planes.iter().map(Node::depth).max().unwrap_or(0);
+        let mut planes: Vec<Node> = planes
+            .into_iter()
+            .map(|plane| plane.center_at_depth(depth))
+            .collect();
+        while planes.len() > 1 && planes.last().is_some_and(Node::is_empty) {
+            planes.pop();
+        }
+        if planes.is_empty() {
+            planes.push(Node::empty(depth));
+        }
+        Self {
+            planes: planes.into(),
+        }
+    }
+    pub fn empty(depth: u8) -> Self {
+        Self::new(vec![Node::empty(depth)])
+    }
+    pub(crate) fn new_leaf(data: &Quad<BlockPlanes>) -> Self {
+        let len = data.iter().map(|planes| planes.len()).max().unwrap_or(0);
+        let plane = |i: usize| {
+            data.as_ref()
+                .map(|planes| planes.get(i).copied().unwrap_or_else(Block::empty))
+        };
+        Self::new((0..len).map(|i| Node::from(plane(i))).collect())
+    }
+    pub fn new_inner(data: Quad<MultiNode>) -> Self {
+        let len = data.iter().map(|node| node.planes.len()).max().unwrap_or(0);
+        let empty = Node::empty(data.nw.depth());
+        let data = data.map(|node| node.planes);
+        let plane = |i: usize| {
+            data.as_ref()
+                .map(|planes| planes.get(i).unwrap_or(&empty).clone())
+        };
+        Self::new((0..len).map(|i| Node::from(plane(i))).collect())
+    }
+
+    pub fn planes(&self) -> &[Node] {
+        &self.planes
+    }
+    /// plane `i`, which is empty past the last plane
+    pub(crate) fn plane(&self, i: usize) -> Node {
+        self.planes
+            .get(i)
+            .cloned()
+            .unwrap_or_else(|| Node::empty(self.depth()))
+    }
+    pub fn depth(&self) -> u8 {
+        self.planes[0].depth()
+    }
+    pub fn width(&self) -> u64 {
+        self.planes[0].width()
+    }
+    pub fn half_width(&self) -> i64 {
+        self.planes[0].half_width()
+    }
+    pub fn width_log2(&self) -> u8 {
+        self.planes[0].width_log2()
+    }
+    /// the two-state node of every cell that isn't in state 0
+    pub fn occupied(&self) -> Node {
+        self.planes
+            .iter()
+            .fold(Node::empty(self.depth()), |occupied, plane| {
+                occupied.or(plane)
+            })
+    }
+    pub(crate) fn children(&self) -> DepthQuad<BlockPlanes, MultiNode> {
+        match self.planes[0].depth_quad() {
+            DepthQuad::Leaf(_) => DepthQuad::Leaf(zip_planes(
+                self.planes
+                    .iter()
+                    .map(|plane| plane.leaf().expect("consistent plane depth")),
+            )),
+            DepthQuad::Inner(depth, _) => DepthQuad::Inner(
+                *depth,
+                zip_planes::<_, Vec<Node>>(
+                    self.planes
+                        .iter()
+                        .map(|plane| plane.inner().expect("consistent plane depth")),
+                )
+                .map(MultiNode::new),
+            ),
+        }
+    }
+    pub(crate) fn center_at_depth(&self, depth: u8) -> MultiNode {
+        MultiNode::new(
+            self.planes
+                .iter()
+                .map(|plane| plane.center_at_depth(depth))
+                .collect(),
+        )
+    }
+}
+impl From<Node> for MultiNode {
+    fn from(node: Node) -> Self {
+        Self::new(vec![node])
+    }
+}
+impl From<Quad<MultiNode>> for MultiNode {
+    fn from(data: Quad<MultiNode>) -> Self {
+        Self::new_inner(data)
+    }
+}
+
+impl Population for MultiNode {
+    /// number of cells that aren't in state 0
+    fn population(&self) -> u64 {
+        self.occupied().population()
+    }
+    fn is_empty(&self) -> bool {
+        self.planes.iter().all(Node::is_empty)
+    }
+}
+
+/// converts planes of quads into a quad of planes
+pub(crate) fn zip_planes<'q, T, P>(planes: impl Iterator<Item = &'q Quad<T>> + Clone) -> Quad<P>
+where
+    T: Clone + 'q,
+    P: FromIterator<T>,
+{
+    Quad {
+        nw: planes.clone().map(|quad| quad.nw.clone()).collect(),
+        ne: planes.clone().map(|quad| quad.ne.clone()).collect(),
+        sw: planes.clone().map(|quad| quad.sw.clone()).collect(),
+        se: planes.map(|quad| quad.se.clone()).collect(),
+    }
+}
+
+/// the state of each cell in row-major order
+pub(crate) fn states_from_planes(planes: &[Block]) -> [[u8; 8]; 8] {
+    let mut states = [[0; 8]; 8];
+    for (i, plane) in planes.iter().enumerate() {
+        for (y, row) in plane.to_rows_array().into_iter().enumerate() {
+            for (x, state) in states[y].iter_mut().enumerate() {
+                *state |= ((row >> (7 - x)) & 1) << i;
+            }
+        }
+    }
+    states
+}
+/// inverse of `states_from_planes`, without any trailing empty planes
+pub(crate) fn planes_from_states(states: &[[u8; 8]; 8]) -> BlockPlanes {
+    let max = states.iter().flatten().fold(0, |max, &state| max | state);
+    (0..u8::BITS - max.leading_zeros())
+        .map(|i| {
+            Block::from_rows_array(states.map(|row| {
+                row.iter()
+                    .fold(0, |bits, &state| (bits << 1) | ((state >> i) & 1))
+            }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Block, Node, Population, Pos};
+
+    use super::{planes_from_states, states_from_planes, MultiNode};
+
+    #[test]
+    fn states_round_trip() {
+        let mut states = [[0; 8]; 8];
+        states[0][0] = 1;
+        states[3][5] = 200;
+        states[7][7] = 3;
+        let planes = planes_from_states(&states);
+        assert_eq!(planes.len(), 8);
+        assert_eq!(planes[0], Block::from_rows(0x80_00_00_00_00_00_00_01));
+        assert_eq!(states_from_planes(&planes), states);
+        assert_eq!(planes_from_states(&[[0; 8]; 8]).len(), 0);
+    }
+
+    #[test]
+    fn get_set() {
+        let node = MultiNode::empty(0);
+        assert_eq!(node.planes().len(), 1);
+        let node = node.set(Pos { x: 3, y: -2 }, 2);
+        let node = node.set(Pos { x: -100, y: 5 }, 7);
+        let node = node.set(Pos { x: 0, y: 0 }, 1);
+        assert_eq!(node.planes().len(), 3);
+        assert_eq!(node.get(Pos { x: 3, y: -2 }), 2);
+        assert_eq!(node.get(Pos { x: -100, y: 5 }), 7);
+        assert_eq!(node.get(Pos { x: 0, y: 0 }), 1);
+        assert_eq!(node.get(Pos { x: 1, y: 0 }), 0);
+        assert_eq!(node.get(Pos { x: 1000, y: 0 }), 0);
+        assert_eq!(node.population(), 3);
+
+        // clearing the only cells with higher states removes their planes
+        let node = node.set(Pos { x: -100, y: 5 }, 0);
+        let node = node.set(Pos { x: 3, y: -2 }, 1);
+        assert_eq!(node.planes().len(), 1);
+        assert_eq!(node.population(), 2);
+        let two_state = Node::empty(0)
+            .set(Pos { x: 3, y: -2 }, true)
+            .set(Pos { x: 0, y: 0 }, true);
+        assert_eq!(
+            node,
+            MultiNode::from(two_state).center_at_depth(node.depth())
+        );
+    }
+}
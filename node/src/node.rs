@@ -7,13 +7,14 @@ use weak_table::WeakHashSet;
 use crate::block::Block;
 use crate::ops::Population;
 use crate::quad::{DepthQuad, Quad};
+use crate::rect::Rect;
 
 thread_local! {
     static NODE_CACHE: RefCell<WeakHashSet<WeakNode>> = RefCell::new(WeakHashSet::new());
     static EMPTY_NODES: Vec<Node> = gen_empty_nodes();
 }
 
-type NodeData = (DepthQuad<Block, Node>, u64);
+type NodeData = (DepthQuad<Block, Node>, u64, Rect);
 #[derive(Clone, Eq)]
 pub struct Node(Rc<NodeData>);
 
@@ -29,7 +30,8 @@ impl Node {
             node_cache.get(&data).unwrap_or_else(|| {
                 data.validate_depth();
                 let population = data.population();
-                let node = Self(Rc::new((data, population)));
+                let bounding_rect = data.bounding_rect();
+                let node = Self(Rc::new((data, population, bounding_rect)));
                 node_cache.insert(node.clone());
                 node
             })
@@ -131,6 +133,14 @@ impl Population for Node {
     }
 }
 
+// not in bounding_rect.rs since it is cached internally
+impl Node {
+    /// the smallest rect containing every live cell, or `Rect::NOTHING` if there aren't any
+    pub fn bounding_rect(&self) -> Rect {
+        self.as_ref().2
+    }
+}
+
 impl DepthQuad<Block, Node> {
     fn validate_depth(&self) {
         if let Self::Inner(depth, inner) = self {
@@ -0,0 +1,275 @@
+//! steps a node and renders each step as a frame of an animated GIF or APNG
+//!
+//! the steps go through `Node::step`, so hyper stepping a large pattern by a large step size is as cheap as it is in the GUI
+
+use std::borrow::Cow;
+use std::io::{Error as IoError, ErrorKind, Write};
+
+use gif::{EncodingError, Frame, Repeat};
+use png::{BitDepth, ColorType};
+
+use super::image_format::png_error;
+use crate::{Image, Node, Pos, Rect, Rule, Shading};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+/// which area to render, and how many frames to render it for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AnimationOptions {
+    pub rect: Rect,
+    pub frames: usize,
+    /// generations between each frame
+    pub step_size: u64,
+    pub zoom_log2: u8,
+    pub shading: Shading,
+    /// moves `rect` along with the center of the live cells, so spaceships stay in frame
+    pub follow: bool,
+    pub delay_ms: u16,
+}
+impl AnimationOptions {
+    pub fn new(rect: Rect, frames: usize) -> Self {
+        Self {
+            rect,
+            frames,
+            step_size: 1,
+            zoom_log2: 0,
+            shading: Shading::Any,
+            follow: false,
+            delay_ms: 100,
+        }
+    }
+    pub fn with_step_size(self, step_size: u64) -> Self {
+        Self { step_size, ..self }
+    }
+    /// see `Node::render`
+    pub fn with_zoom(self, zoom_log2: u8, shading: Shading) -> Self {
+        Self {
+            zoom_log2,
+            shading,
+            ..self
+        }
+    }
+    pub fn following(self) -> Self {
+        Self {
+            follow: true,
+            ..self
+        }
+    }
+    pub fn with_delay_ms(self, delay_ms: u16) -> Self {
+        Self { delay_ms, ..self }
+    }
+}
+
+impl Node {
+    /// the first frame is `self`, and each frame after is `step_size` generations after the frame before it
+    ///
+    /// frames where a B0 rule has turned the background alive are rendered with a live background
+    pub fn animate(&self, rule: &Rule, options: &AnimationOptions) -> Vec<Image> {
+        let start = live_center(self);
+        let mut node = self.clone();
+        let mut background = false;
+        let mut frames = Vec::with_capacity(options.frames);
+        for i in 0..options.frames {
+            if i > 0 {
+                (node, background) = node.step_with_background(rule, options.step_size, background);
+            }
+            let mut rect = options.rect;
+            if options.follow {
+                if let (Some(start), Some(center)) = (start, live_center(&node)) {
+                    rect.offset(center - start);
+                }
+            }
+            frames.push(node.render_with_background(
+                rect,
+                options.zoom_log2,
+                options.shading,
+                background,
+            ));
+        }
+        frames
+    }
+    /// loops forever
+    pub fn write_animation(
+        &self,
+        rule: &Rule,
+        options: &AnimationOptions,
+        format: AnimationFormat,
+        write: impl Write,
+    ) -> Result<(), IoError> {
+        let frames = self.animate(rule, options);
+        match format {
+            AnimationFormat::Gif => write_gif(&frames, options.delay_ms, write),
+            AnimationFormat::Apng => write_apng(&frames, options.delay_ms, write),
+        }
+    }
+}
+
+/// the center of the live cells, or None if there aren't any
+fn live_center(node: &Node) -> Option<Pos> {
+    let bounds = node.bounding_rect();
+    (!bounds.is_empty()).then(|| bounds.cc())
+}
+
+/// the frames only use grays, so every frame shares a palette of every gray
+fn write_gif(frames: &[Image], delay_ms: u16, write: impl Write) -> Result<(), IoError> {
+    let first = frames
+        .first()
+        .map_or((0, 0), |image| (image.width(), image.height()));
+    let size = |len: usize| {
+        u16::try_from(len).map_err(|_| IoError::new(ErrorKind::InvalidInput, "image too large"))
+    };
+    let (width, height) = (size(first.0)?, size(first.1)?);
+    let palette: Vec<u8> = (0..=255).flat_map(|gray| [gray; 3]).collect();
+    let mut encoder = gif::Encoder::new(write, width, height, &palette).map_err(gif_error)?;
+    encoder.set_repeat(Repeat::Infinite).map_err(gif_error)?;
+    for image in frames {
+        let grays: Vec<u8> = image
+            .pixels()
+            .chunks_exact(4)
+            .map(|pixel| pixel[0])
+            .collect();
+        let frame = Frame {
+            width,
+            height,
+            // in hundredths of a second
+            delay: delay_ms / 10,
+            buffer: Cow::Owned(grays),
+            ..Frame::default()
+        };
+        encoder.write_frame(&frame).map_err(gif_error)?;
+    }
+    Ok(())
+}
+fn gif_error(err: EncodingError) -> IoError {
+    match err {
+        EncodingError::Io(err) => err,
+        EncodingError::Format(err) => IoError::new(ErrorKind::InvalidInput, err),
+    }
+}
+
+fn write_apng(frames: &[Image], delay_ms: u16, write: impl Write) -> Result<(), IoError> {
+    let first = frames
+        .first()
+        .map_or((0, 0), |image| (image.width(), image.height()));
+    let size = |len: usize| {
+        u32::try_from(len).map_err(|_| IoError::new(ErrorKind::InvalidInput, "image too large"))
+    };
+    let frame_count = u32::try_from(frames.len())
+        .map_err(|_| IoError::new(ErrorKind::InvalidInput, "too many frames"))?;
+    let mut encoder = png::Encoder::new(write, size(first.0)?, size(first.1)?);
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    // 0 plays loops forever
+    encoder.set_animated(frame_count, 0).map_err(png_error)?;
+    encoder.set_frame_delay(delay_ms, 1000).map_err(png_error)?;
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    for image in frames {
+        writer.write_image_data(image.pixels()).map_err(png_error)?;
+    }
+    writer.finish().map_err(png_error)
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use super::{AnimationFormat, AnimationOptions};
+    use crate::{test_node, Node, Pos, Rect, Rule};
+
+    fn glider() -> Node {
+        test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "}
+    }
+
+    fn live_cells(image: &crate::Image) -> usize {
+        image
+            .pixels()
+            .chunks_exact(4)
+            .filter(|pixel| pixel[0] == 255)
+            .count()
+    }
+
+    #[test]
+    fn follow() {
+        let rect = Rect::min_max(Pos::new(-1, -1), Pos::new(3, 3));
+        // a glider moves 1 cell diagonally every 4 generations, so it leaves a rect that stays still
+        let still = AnimationOptions::new(rect, 3).with_step_size(8);
+        let frames = glider().animate(&Rule::LIFE, &still);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames.iter().map(live_cells).collect::<Vec<_>>(), [5, 1, 0]);
+        // but is the same in every frame when followed
+        let frames = glider().animate(&Rule::LIFE, &still.following());
+        assert!(frames.iter().all(|frame| frame == &frames[0]));
+        // hyper stepping is cheap
+        let far = AnimationOptions::new(rect, 2)
+            .with_step_size(1 << 40)
+            .following();
+        let frames = glider().animate(&Rule::LIFE, &far);
+        assert_eq!(frames[0], frames[1]);
+    }
+
+    #[test]
+    fn b0() {
+        // every cell changes every generation, so every other frame is the glider's cells inverted
+        let rect = Rect::min_max(Pos::new(-1, -1), Pos::new(3, 3));
+        let flip = "B012345678/S".parse().unwrap();
+        let frames = glider().animate(&flip, &AnimationOptions::new(rect, 3));
+        assert_eq!(
+            frames.iter().map(live_cells).collect::<Vec<_>>(),
+            [5, 25 - 5, 5]
+        );
+    }
+
+    #[test]
+    fn write() {
+        let rect = Rect::min_max(Pos::new(0, 0), Pos::new(3, 3));
+        let options = AnimationOptions::new(rect, 4).with_delay_ms(50);
+        let expected = glider().animate(&Rule::LIFE, &options);
+
+        let mut gif = Vec::new();
+        glider()
+            .write_animation(&Rule::LIFE, &options, AnimationFormat::Gif, &mut gif)
+            .unwrap();
+        let mut decoder = gif::DecodeOptions::new();
+        decoder.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = decoder.read_info(&gif[..]).unwrap();
+        for image in &expected {
+            let frame = decoder.read_next_frame().unwrap().unwrap();
+            assert_eq!(frame.delay, 5);
+            assert_eq!(&frame.buffer[..], image.pixels());
+        }
+        assert!(decoder.read_next_frame().unwrap().is_none());
+
+        let mut apng = Vec::new();
+        glider()
+            .write_animation(&Rule::LIFE, &options, AnimationFormat::Apng, &mut apng)
+            .unwrap();
+        let mut reader = png::Decoder::new(&apng[..]).read_info().unwrap();
+        assert_eq!(reader.info().animation_control().unwrap().num_frames, 4);
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        for image in &expected {
+            reader.next_frame(&mut pixels).unwrap();
+            assert_eq!(pixels, image.pixels());
+        }
+    }
+}
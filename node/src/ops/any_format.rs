@@ -0,0 +1,397 @@
+//! reads any of the supported formats by looking at the start of the file, and handles gzipped files for all of them
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::Display;
+#[cfg(feature = "gzip")]
+use std::io::BufReader;
+use std::io::{BufRead, Error as IoError, Read, Write};
+
+use either::Either;
+#[cfg(feature = "gzip")]
+use flate2::{bufread::MultiGzDecoder, write::GzEncoder, Compression};
+
+use crate::{CellsError, LifeError, MacrocellError, Node, Pattern, RleError, Rule};
+
+/// the first bytes of a gzipped file
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// the supported pattern formats
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PatternFormat {
+    /// `[M2]`, see `Node::write_to`
+    Macrocell,
+    /// `x = 3, y = 3`, see `Node::write_rle`
+    Rle,
+    /// plaintext `.cells` with `!` comments, see `Node::write_cells`
+    Cells,
+    /// `#Life 1.05` with `#P` blocks, see `Node::write_life105`
+    Life105,
+    /// `#Life 1.06` coordinate lists, see `Node::write_life106`
+    Life106,
+}
+impl PatternFormat {
+    /// guesses the format from the start of a file that isn't gzipped
+    ///
+    /// looks at the first line that isn't a comment, unless a header or a `#` line says which format it is
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        for line in bytes.split(|&b| b == b'\n' || b == b'\r') {
+            let line = trim_start(line);
+            let format = match line {
+                [] => continue,
+                _ if line.starts_with(b"[M2]") => PatternFormat::Macrocell,
+                _ if line.starts_with(b"#Life 1.05") => PatternFormat::Life105,
+                _ if line.starts_with(b"#Life 1.06") => PatternFormat::Life106,
+                // only Life 1.05 has descriptions and blocks
+                [b'#', b'D' | b'P', ..] => PatternFormat::Life105,
+                [b'#', ..] => continue,
+                [b'!', ..] => PatternFormat::Cells,
+                [b'x', rest @ ..] if trim_start(rest).starts_with(b"=") => PatternFormat::Rle,
+                // a headerless RLE can also start with a digit, such as `2o$2o!`
+                _ if is_coordinates(line) => PatternFormat::Life106,
+                _ if line
+                    .iter()
+                    .all(|b| matches!(b, b'.' | b'O' | b'o' | b'*' | b' ' | b'\t')) =>
+                {
+                    PatternFormat::Cells
+                }
+                // an RLE file without a header
+                _ if line
+                    .iter()
+                    .all(|b| b.is_ascii_alphanumeric() || b"$!.".contains(b)) =>
+                {
+                    PatternFormat::Rle
+                }
+                _ => return None,
+            };
+            return Some(format);
+        }
+        None
+    }
+}
+/// two integers separated by whitespace, like `-1 5`
+fn is_coordinates(line: &[u8]) -> bool {
+    let mut tokens = line
+        .split(u8::is_ascii_whitespace)
+        .filter(|token| !token.is_empty());
+    let is_integer = |token: &[u8]| {
+        let digits = token.strip_prefix(b"-").unwrap_or(token);
+        !digits.is_empty() && digits.iter().all(u8::is_ascii_digit)
+    };
+    matches!(
+        (tokens.next(), tokens.next(), tokens.next()),
+        (Some(x), Some(y), None) if is_integer(x) && is_integer(y)
+    )
+}
+fn trim_start(mut line: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = line {
+        line = rest;
+    }
+    line
+}
+
+impl Node {
+    /// reads any of the supported formats, after decompressing it if it is gzipped
+    ///
+    /// ignores any metadata, see `Pattern::read_any` to also read it
+    pub fn read_any(bytes: &[u8]) -> Result<Self, PatternError> {
+        let bytes = decompress_bytes(bytes).map_err(PatternError::Gzip)?;
+        let bytes = bytes.as_ref();
+        match PatternFormat::detect(bytes).ok_or(PatternError::UnknownFormat)? {
+            PatternFormat::Macrocell => Node::read_from_bytes(bytes)
+                .map_err(|err| PatternError::Macrocell(err.into_owned())),
+            PatternFormat::Rle => {
+                Node::read_rle(bytes).map_err(|err| PatternError::Rle(err.into_owned()))
+            }
+            PatternFormat::Cells => {
+                Node::read_cells(bytes).map_err(|err| PatternError::Cells(err.into_owned()))
+            }
+            PatternFormat::Life105 => {
+                Node::read_life105(bytes).map_err(|err| PatternError::Life(err.into_owned()))
+            }
+            PatternFormat::Life106 => {
+                Node::read_life106(bytes).map_err(|err| PatternError::Life(err.into_owned()))
+            }
+        }
+    }
+    /// writes the pattern with the rule for Life in formats that have a rule, see `Pattern::write_any` to write other metadata
+    pub fn write_any(
+        &self,
+        format: PatternFormat,
+        gzip: bool,
+        write: impl Write,
+    ) -> Result<(), IoError> {
+        Pattern::new(self.clone())
+            .with_rule(Rule::LIFE)
+            .write_any(format, gzip, write)
+    }
+}
+impl Pattern<Node> {
+    /// reads any of the supported formats along with their metadata, after decompressing it if it is gzipped
+    pub fn read_any(bytes: &[u8]) -> Result<Self, PatternError> {
+        let bytes = decompress_bytes(bytes).map_err(PatternError::Gzip)?;
+        let bytes = bytes.as_ref();
+        match PatternFormat::detect(bytes).ok_or(PatternError::UnknownFormat)? {
+            PatternFormat::Macrocell => Pattern::<Node>::read_from_bytes(bytes)
+                .map_err(|err| PatternError::Macrocell(err.into_owned())),
+            PatternFormat::Rle => {
+                Pattern::<Node>::read_rle(bytes).map_err(|err| PatternError::Rle(err.into_owned()))
+            }
+            PatternFormat::Cells => Pattern::<Node>::read_cells(bytes)
+                .map_err(|err| PatternError::Cells(err.into_owned())),
+            PatternFormat::Life105 => Pattern::<Node>::read_life105(bytes)
+                .map_err(|err| PatternError::Life(err.into_owned())),
+            PatternFormat::Life106 => Node::read_life106(bytes)
+                .map(Pattern::new)
+                .map_err(|err| PatternError::Life(err.into_owned())),
+        }
+    }
+    /// writes as much of the metadata as the format has room for, and gzips it if asked to
+    pub fn write_any(
+        &self,
+        format: PatternFormat,
+        gzip: bool,
+        write: impl Write,
+    ) -> Result<(), IoError> {
+        if gzip {
+            self.write_gzipped(format, write)
+        } else {
+            self.write_format(format, write)
+        }
+    }
+    #[cfg(feature = "gzip")]
+    fn write_gzipped(&self, format: PatternFormat, write: impl Write) -> Result<(), IoError> {
+        let mut encoder = GzEncoder::new(write, Compression::default());
+        self.write_format(format, &mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    }
+    #[cfg(not(feature = "gzip"))]
+    #[allow(clippy::unused_self)] // the same signature as with the gzip feature
+    fn write_gzipped(&self, _format: PatternFormat, _write: impl Write) -> Result<(), IoError> {
+        Err(gzip_disabled())
+    }
+    fn write_format(&self, format: PatternFormat, write: impl Write) -> Result<(), IoError> {
+        match format {
+            PatternFormat::Macrocell => self.write_to(write),
+            PatternFormat::Rle => self.write_rle(write),
+            PatternFormat::Cells => self.write_cells(write),
+            PatternFormat::Life105 => self.write_life105(write),
+            PatternFormat::Life106 => self.node.write_life106(write),
+        }
+    }
+}
+
+/// decompresses `read` as it is read if it starts like a gzipped file
+pub(crate) fn decompress(mut read: impl BufRead) -> Result<impl BufRead, IoError> {
+    if read.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Either::Right(gunzip(read)?))
+    } else {
+        Ok(Either::Left(read))
+    }
+}
+fn decompress_bytes(bytes: &[u8]) -> Result<Cow<'_, [u8]>, IoError> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut out = Vec::new();
+        gunzip(bytes)?.read_to_end(&mut out)?;
+        Ok(Cow::Owned(out))
+    } else {
+        Ok(Cow::Borrowed(bytes))
+    }
+}
+#[cfg(feature = "gzip")]
+#[allow(clippy::unnecessary_wraps)] // fails without the gzip feature
+fn gunzip<R: BufRead>(read: R) -> Result<impl BufRead, IoError> {
+    Ok(BufReader::new(MultiGzDecoder::new(read)))
+}
+#[cfg(not(feature = "gzip"))]
+fn gunzip<R: BufRead>(_read: R) -> Result<R, IoError> {
+    Err(gzip_disabled())
+}
+#[cfg(not(feature = "gzip"))]
+fn gzip_disabled() -> IoError {
+    IoError::new(
+        std::io::ErrorKind::Unsupported,
+        "gzipped patterns need the gzip feature",
+    )
+}
+
+/// an error from reading a file in any format, with the error from the format the file was in
+#[derive(Debug)]
+pub enum PatternError {
+    UnknownFormat,
+    /// the file started like a gzipped file, but couldn't be decompressed
+    Gzip(IoError),
+    Macrocell(MacrocellError<Vec<u8>>),
+    Rle(RleError<Vec<u8>>),
+    Cells(CellsError<Vec<u8>>),
+    Life(LifeError<Vec<u8>>),
+}
+impl Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternError::UnknownFormat => writeln!(
+                f,
+                "Unknown pattern format, expected macrocell, RLE, plaintext, Life 1.05 or Life 1.06"
+            ),
+            PatternError::Gzip(err) => writeln!(f, "Failed to decompress gzipped pattern: {err}"),
+            PatternError::Macrocell(err) => Display::fmt(err, f),
+            PatternError::Rle(err) => Display::fmt(err, f),
+            PatternError::Cells(err) => Display::fmt(err, f),
+            PatternError::Life(err) => Display::fmt(err, f),
+        }
+    }
+}
+impl Error for PatternError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PatternError::UnknownFormat => None,
+            PatternError::Gzip(err) => Some(err),
+            PatternError::Macrocell(err) => Some(err),
+            PatternError::Rle(err) => Some(err),
+            PatternError::Cells(err) => Some(err),
+            PatternError::Life(err) => Some(err),
+        }
+    }
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use super::{PatternError, PatternFormat};
+    use crate::{test_node, Node, Pattern, Population};
+
+    fn glider() -> Node {
+        test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "}
+    }
+
+    #[test]
+    fn detect() {
+        let detect = |s: &str| PatternFormat::detect(s.as_bytes());
+        assert_eq!(detect("[M2] (golly 4.2)\n"), Some(PatternFormat::Macrocell));
+        assert_eq!(
+            detect("#N Glider\n#C a comment\nx = 3, y = 3\n"),
+            Some(PatternFormat::Rle)
+        );
+        assert_eq!(detect("bo$2bo$3o!"), Some(PatternFormat::Rle));
+        assert_eq!(detect("!Name: Glider\n.O\n"), Some(PatternFormat::Cells));
+        assert_eq!(detect("\r\n..O\nOOO\n"), Some(PatternFormat::Cells));
+        assert_eq!(detect("#Life 1.05\n#N\n"), Some(PatternFormat::Life105));
+        assert_eq!(detect("#D a\n#P 0 0\n*\n"), Some(PatternFormat::Life105));
+        assert_eq!(detect("#Life 1.06\n0 0\n"), Some(PatternFormat::Life106));
+        assert_eq!(detect("-1 5\n"), Some(PatternFormat::Life106));
+        assert_eq!(detect("2o$2o!"), Some(PatternFormat::Rle));
+        assert_eq!(detect("3o\n"), Some(PatternFormat::Rle));
+        assert_eq!(detect(""), None);
+        assert_eq!(detect("{\"cells\": []}"), None);
+    }
+
+    #[test]
+    fn read_any() {
+        let pattern = Pattern::new(glider())
+            .with_rule("B36/S23".parse().unwrap())
+            .with_comment("a glider");
+        let formats = [
+            PatternFormat::Macrocell,
+            PatternFormat::Rle,
+            PatternFormat::Cells,
+            PatternFormat::Life105,
+            PatternFormat::Life106,
+        ];
+        for format in formats {
+            for gzip in [false, cfg!(feature = "gzip")] {
+                let mut out = Vec::new();
+                pattern.write_any(format, gzip, &mut out).unwrap();
+                assert_eq!(out.starts_with(&super::GZIP_MAGIC), gzip);
+                let read = Pattern::<Node>::read_any(&out).unwrap();
+                // formats without positions or rules don't keep them
+                let expected = match format {
+                    PatternFormat::Macrocell | PatternFormat::Rle | PatternFormat::Life105 => {
+                        pattern.clone()
+                    }
+                    PatternFormat::Cells => Pattern {
+                        rule: None,
+                        ..pattern.clone()
+                    },
+                    PatternFormat::Life106 => Pattern::new(glider()),
+                };
+                assert_eq!(read, expected, "{format:?} {gzip}");
+            }
+        }
+        // rules that can't be parsed don't stop the pattern from being read
+        let history = b"x = 2, y = 1, rule = LifeHistory\n2A!";
+        assert_eq!(
+            Node::read_any(history).unwrap(),
+            Node::read_rle(history).unwrap()
+        );
+        assert_eq!(
+            Pattern::<Node>::read_any(history).unwrap().rule,
+            Some(Err("LifeHistory".to_owned()))
+        );
+        // a headerless RLE that starts with a count
+        let block = Node::read_any(b"2o$2o!").unwrap();
+        assert_eq!(block, Node::read_rle(b"2o$2o!").unwrap());
+        assert_eq!(block.population(), 4);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn gzip_stream() {
+        let mut out = Vec::new();
+        glider()
+            .write_any(PatternFormat::Macrocell, true, &mut out)
+            .unwrap();
+        assert_eq!(Node::read_from(&out[..]).unwrap().unwrap(), glider());
+        assert_eq!(
+            crate::MultiNode::read_from(&out[..]).unwrap().unwrap(),
+            crate::MultiNode::from(glider())
+        );
+        // a gzipped file that isn't finished
+        assert!(Node::read_from(&out[..out.len() - 4]).is_err());
+        assert!(matches!(
+            Node::read_any(&out[..out.len() - 4]),
+            Err(PatternError::Gzip(_))
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            Node::read_any(b"hello there"),
+            Err(PatternError::UnknownFormat)
+        ));
+        let err = Node::read_any(b"x = 3, y = 3\nbo$2bo$3q!").unwrap_err();
+        assert!(matches!(err, PatternError::Rle(_)));
+        assert!(err.to_string().contains("line 2"));
+        assert!(matches!(
+            Node::read_any(b"[M2]\n4 0 0 0\n"),
+            Err(PatternError::Macrocell(_))
+        ));
+        assert!(matches!(
+            Node::read_any(b"!Name: x\n.O.\n.#.\n"),
+            Err(PatternError::Cells(_))
+        ));
+        assert!(matches!(
+            Node::read_any(b"#Life 1.06\n1 2 3\n"),
+            Err(PatternError::Life(_))
+        ));
+    }
+}
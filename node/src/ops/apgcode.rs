@@ -0,0 +1,425 @@
+//! apgcodes, which is how Catagolue names objects, such as `xs4_33` for a block or `xq4_153` for a glider
+//!
+//! the prefix is the kind of object and the cells are in the extended Wechsler format,
+//! which splits the cells into strips of 5 rows and writes each column of a strip as one of `0-9a-v`
+//! with the north row as the least significant bit, `w`, `x` and `y_` for runs of empty columns, and `z` between strips
+
+use std::error::Error;
+use std::fmt::Display;
+
+use super::blocks::BlockSet;
+use crate::{Node, Periodicity, Population, Pos, Rule};
+
+const DIGITS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+const STRIP_HEIGHT: i64 = 5;
+
+impl Node {
+    /// `xs{population}_` for still lifes, `xp{period}_` for oscillators, and `xq{period}_` for spaceships,
+    /// followed by the shortest and then alphabetically first encoding over every phase, rotation, and reflection
+    ///
+    /// returns None if the node is empty or doesn't repeat within `max_period` generations of `rule`
+    pub fn to_apgcode(&self, rule: &Rule, max_period: u64) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let (prefix, period) = match self.periodicity(rule, max_period) {
+            Periodicity::StillLife => (format!("xs{}", self.population()), 1),
+            Periodicity::Oscillator { period } => (format!("xp{period}"), period),
+            Periodicity::Spaceship { period, .. } => (format!("xq{period}"), period),
+            Periodicity::Unknown => return None,
+        };
+        // with B0 rules the phases with a live background have the dead cells instead, so they are skipped
+        let code = std::iter::successors(Some((self.clone(), false)), |(node, background)| {
+            Some(node.step_with_background(rule, 1, *background))
+        })
+        .take(usize::try_from(period).expect("period fits in memory"))
+        .filter(|(_, background)| !background)
+        .map(|(phase, _)| canonical_wechsler(&phase))
+        .min_by(shortest_first)
+        .expect("at least 1 phase");
+        Some(format!("{prefix}_{code}"))
+    }
+    /// the north west corner of the cells is at the origin, only `xs`, `xp` and `xq` codes can be read
+    pub fn from_apgcode(code: &str) -> Result<Self, ApgcodeError> {
+        let start =
+            prefix_len(code).ok_or(ApgcodeError::new(0, ApgcodeErrorKind::InvalidPrefix))?;
+        let bytes = code.as_bytes();
+        let mut cells = BlockSet::default();
+        let mut x = 0;
+        let mut y = 0;
+        let mut chars = bytes.iter().enumerate().skip(start);
+        while let Some((i, &c)) = chars.next() {
+            match c {
+                b'w' => x += 2,
+                b'x' => x += 3,
+                b'y' => {
+                    let zeros = chars.next().and_then(|(_, &c)| digit(c));
+                    x +=
+                        4 + zeros.ok_or(ApgcodeError::new(i + 1, ApgcodeErrorKind::InvalidChar))?;
+                }
+                b'z' => {
+                    x = 0;
+                    y += STRIP_HEIGHT;
+                }
+                _ => {
+                    let column = digit(c)
+                        .filter(|&column| column < 32)
+                        .ok_or(ApgcodeError::new(i, ApgcodeErrorKind::InvalidChar))?;
+                    for row in 0..STRIP_HEIGHT {
+                        if column & (1 << row) != 0 {
+                            cells.set(Pos::new(x, y + row));
+                        }
+                    }
+                    x += 1;
+                }
+            }
+        }
+        Ok(cells.into_node())
+    }
+}
+
+/// the north west corner of the cells, and the runs relative to it
+fn normalized_runs(node: &Node) -> (Pos, Vec<(i64, i64, i64)>) {
+    let runs = node.runs();
+    let west = runs.iter().map(|run| run.pos.x).min().unwrap_or(0);
+    let north = runs.first().map_or(0, |run| run.pos.y);
+    let runs = runs
+        .iter()
+        .map(|run| (run.pos.x - west, run.pos.y - north, run.len))
+        .collect();
+    (Pos::new(west, north), runs)
+}
+
+fn shortest_first(a: &String, b: &String) -> std::cmp::Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// the best encoding over the 8 rotations and reflections
+fn canonical_wechsler(node: &Node) -> String {
+    let (_, node) = node.offset_norm();
+    let rotations: [fn(&Node) -> Node; 4] = [
+        Node::clone,
+        Node::rotate_cw,
+        Node::rotate_180,
+        Node::rotate_ccw,
+    ];
+    rotations
+        .iter()
+        .flat_map(|rotate| {
+            let rotated = rotate(&node);
+            [wechsler(&rotated.flip_h()), wechsler(&rotated)]
+        })
+        .min_by(shortest_first)
+        .expect("8 encodings")
+}
+
+/// the cells in the extended Wechsler format without a prefix, starting at the north west corner of the cells
+fn wechsler(node: &Node) -> String {
+    let (_, runs) = normalized_runs(node);
+    let mut strips: Vec<Vec<u8>> = Vec::new();
+    for (x, y, len) in runs {
+        let strip = usize::try_from(y / STRIP_HEIGHT).expect("strips fit in memory");
+        let row = y % STRIP_HEIGHT;
+        let start = usize::try_from(x).expect("strips fit in memory");
+        let end = start + usize::try_from(len).expect("strips fit in memory");
+        if strips.len() <= strip {
+            strips.resize(strip + 1, Vec::new());
+        }
+        let columns = &mut strips[strip];
+        if columns.len() < end {
+            columns.resize(end, 0);
+        }
+        for column in &mut columns[start..end] {
+            *column |= 1 << row;
+        }
+    }
+    let mut code = String::new();
+    for (i, columns) in strips.iter().enumerate() {
+        if i > 0 {
+            code.push('z');
+        }
+        // empty columns are only written before a column with cells, so trailing ones are left out
+        let mut zeros = 0;
+        for &column in columns {
+            if column == 0 {
+                zeros += 1;
+            } else {
+                push_zeros(&mut code, zeros);
+                zeros = 0;
+                code.push(char::from(DIGITS[usize::from(column)]));
+            }
+        }
+    }
+    code
+}
+fn push_zeros(code: &mut String, mut zeros: usize) {
+    // `yz` is the most that fit in 1 run
+    while zeros >= 40 {
+        code.push_str("yz");
+        zeros -= 39;
+    }
+    match zeros {
+        0 => {}
+        1 => code.push('0'),
+        2 => code.push('w'),
+        3 => code.push('x'),
+        _ => {
+            code.push('y');
+            code.push(char::from(DIGITS[zeros - 4]));
+        }
+    }
+}
+
+// parsing
+
+/// the length of the prefix including the `_`
+fn prefix_len(code: &str) -> Option<usize> {
+    let (prefix, _) = code.split_once('_')?;
+    let period = ["xs", "xp", "xq"]
+        .iter()
+        .find_map(|kind| prefix.strip_prefix(kind))?;
+    let valid = !period.is_empty() && period.bytes().all(|b| b.is_ascii_digit());
+    valid.then_some(prefix.len() + 1)
+}
+fn digit(c: u8) -> Option<i64> {
+    DIGITS
+        .iter()
+        .position(|&d| d == c)
+        .map(|digit| i64::try_from(digit).expect("less than 36"))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApgcodeErrorKind {
+    InvalidPrefix,
+    InvalidChar,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ApgcodeError {
+    offset: usize,
+    kind: ApgcodeErrorKind,
+}
+impl ApgcodeError {
+    fn new(offset: usize, kind: ApgcodeErrorKind) -> Self {
+        Self { offset, kind }
+    }
+    pub fn kind(&self) -> ApgcodeErrorKind {
+        self.kind
+    }
+    /// where the error is in the code, in bytes
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    pub fn hint(&self) -> &'static str {
+        match self.kind {
+            ApgcodeErrorKind::InvalidPrefix => {
+                "Expected a still life, oscillator, or spaceship such as 'xs4_33', 'xp2_7' or 'xq4_153'"
+            }
+            ApgcodeErrorKind::InvalidChar => {
+                "Columns are '0' to 'v', 'w', 'x', and 'y' followed by '0' to 'z' skip columns, and 'z' starts the next strip"
+            }
+        }
+    }
+}
+impl Display for ApgcodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let offset = self.offset;
+        let hint = self.hint();
+        writeln!(f, "Failed to parse apgcode at byte {offset}:\n{hint}\n")
+    }
+}
+impl Error for ApgcodeError {}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use super::{ApgcodeErrorKind, STRIP_HEIGHT};
+    use crate::{test_block, test_node, Block, Node, Pos, Rule};
+
+    /// a node with the cells of `block` south east of the origin
+    fn object(block: Block) -> Node {
+        Node::new(Block::empty(), Block::empty(), Block::empty(), block)
+    }
+    fn assert_code(code: &str, node: &Node) {
+        assert_eq!(node.to_apgcode(&Rule::LIFE, 8).as_deref(), Some(code));
+        // every orientation and phase has the same code
+        let turned = node.rotate_cw().flip_v().step(&Rule::LIFE, 1);
+        assert_eq!(turned.to_apgcode(&Rule::LIFE, 8).as_deref(), Some(code));
+        let read = Node::from_apgcode(code).unwrap();
+        assert_eq!(read.to_apgcode(&Rule::LIFE, 8).as_deref(), Some(code));
+    }
+
+    #[test]
+    fn still_lifes() {
+        let block = object(test_block! {"
+            oo......
+            oo......
+            ........
+            ........
+            ........
+            ........
+            ........
+            ........
+            "});
+        assert_code("xs4_33", &block);
+        // the block is written in the same orientation it is read
+        assert_eq!(Node::from_apgcode("xs4_33").unwrap(), block);
+        assert_code(
+            "xs4_252",
+            &object(test_block! {"
+                .o......
+                o.o.....
+                .o......
+                ........
+                ........
+                ........
+                ........
+                ........
+                "}),
+        );
+        assert_code(
+            "xs5_253",
+            &object(test_block! {"
+                oo......
+                o.o.....
+                .o......
+                ........
+                ........
+                ........
+                ........
+                ........
+                "}),
+        );
+        assert_code(
+            "xs6_696",
+            &object(test_block! {"
+                .oo.....
+                o..o....
+                .oo.....
+                ........
+                ........
+                ........
+                ........
+                ........
+                "}),
+        );
+        assert_code(
+            "xs7_2596",
+            &object(test_block! {"
+                .oo.....
+                o..o....
+                .o.o....
+                ..o.....
+                ........
+                ........
+                ........
+                ........
+                "}),
+        );
+    }
+
+    #[test]
+    fn oscillators_and_spaceships() {
+        assert_code(
+            "xp2_7",
+            &object(test_block! {"
+                ........
+                ooo.....
+                ........
+                ........
+                ........
+                ........
+                ........
+                ........
+                "}),
+        );
+        assert_code(
+            "xq4_153",
+            &object(test_block! {"
+                .o......
+                ..o.....
+                ooo.....
+                ........
+                ........
+                ........
+                ........
+                ........
+                "}),
+        );
+        assert_eq!(Node::empty(0).to_apgcode(&Rule::LIFE, 8), None);
+        // the r-pentomino doesn't settle down in time
+        let r_pentomino = object(test_block! {"
+            .oo.....
+            oo......
+            .o......
+            ........
+            ........
+            ........
+            ........
+            ........
+            "});
+        assert_eq!(r_pentomino.to_apgcode(&Rule::LIFE, 30), None);
+    }
+
+    #[test]
+    fn b0() {
+        // every cell changes every generation, so only the phase with a dead background is encoded
+        let flip = "B012345678/S".parse().unwrap();
+        let glider = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        let code = super::canonical_wechsler(&glider);
+        assert_eq!(glider.to_apgcode(&flip, 8), Some(format!("xp2_{code}")));
+    }
+
+    #[test]
+    fn zero_runs() {
+        // two blocks far enough apart to need every kind of gap, and an empty strip between them
+        for gap in [1, 2, 3, 4, 39, 40, 100] {
+            let far = Node::empty(0)
+                .set(Pos::new(0, 0), true)
+                .set(Pos::new(gap + 1, 0), true)
+                .set(Pos::new(0, 3 * STRIP_HEIGHT), true);
+            let code = super::wechsler(&far);
+            let read = Node::from_apgcode(&format!("xs3_{code}")).unwrap();
+            assert_eq!(read, far, "{code}");
+        }
+        let far = Node::empty(0)
+            .set(Pos::new(0, 0), true)
+            .set(Pos::new(45, 0), true)
+            .set(Pos::new(0, 10), true);
+        assert_eq!(super::wechsler(&far), "1yzy11zz1");
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |code: &str| {
+            let err = Node::from_apgcode(code).unwrap_err();
+            (err.offset(), err.kind())
+        };
+        assert_eq!(error("33"), (0, ApgcodeErrorKind::InvalidPrefix));
+        assert_eq!(
+            error("yl144_1_16_afb5f3db909e60548f086e22ee3353ac"),
+            (0, ApgcodeErrorKind::InvalidPrefix)
+        );
+        assert_eq!(error("xs_33"), (0, ApgcodeErrorKind::InvalidPrefix));
+        assert_eq!(error("xs4_3#"), (5, ApgcodeErrorKind::InvalidChar));
+        assert_eq!(error("xp2_7y"), (6, ApgcodeErrorKind::InvalidChar));
+        assert_eq!(error("xq4_15_3"), (6, ApgcodeErrorKind::InvalidChar));
+    }
+}
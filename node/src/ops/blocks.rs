@@ -0,0 +1,355 @@
+//! converts between nodes and the blocks of cells in them, for formats that list cells by position
+//!
+//! blocks are keyed by their position in blocks, so the north west cell of block `pos` is at `pos * 8`
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use itertools::Itertools;
+
+use crate::multi_node::states_from_planes;
+use crate::{Block, DepthQuad, MultiNode, Node, Population, Pos, Quad};
+
+const BLOCK_WIDTH: i64 = 2 * Block::HALF_WIDTH;
+
+/// live cells gathered into the blocks that contain them, so a node can be built without setting each cell
+#[derive(Default)]
+pub(crate) struct BlockSet {
+    blocks: HashMap<Pos, Block>,
+    /// nodes with 1 full row, by depth, for the parts of long rows that fill whole nodes
+    rows: Vec<HashMap<Pos, Node>>,
+}
+impl BlockSet {
+    pub(crate) fn set(&mut self, pos: Pos) {
+        self.set_row(pos, 1);
+    }
+    /// sets `len` cells going east from `pos`
+    pub(crate) fn set_row(&mut self, pos: Pos, len: i64) {
+        let end = pos.x + len;
+        let first_block = (pos.x + BLOCK_WIDTH - 1).div_euclid(BLOCK_WIDTH);
+        let end_block = end.div_euclid(BLOCK_WIDTH);
+        if first_block >= end_block {
+            self.set_row_in_blocks(pos, end);
+            return;
+        }
+        self.set_row_in_blocks(pos, first_block * BLOCK_WIDTH);
+        self.fill_blocks(pos.y, first_block, end_block);
+        self.set_row_in_blocks(Pos::new(end_block * BLOCK_WIDTH, pos.y), end);
+    }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // columns are in 0..8
+    fn set_row_in_blocks(&mut self, pos: Pos, end: i64) {
+        let block_y = pos.y.div_euclid(BLOCK_WIDTH);
+        let row = pos.y.rem_euclid(BLOCK_WIDTH);
+        let mut x = pos.x;
+        while x < end {
+            let column = x.rem_euclid(BLOCK_WIDTH);
+            let count = (BLOCK_WIDTH - column).min(end - x);
+            // the first column is the most significant bit of the row
+            let bits = (0xff_u16 >> column) & !(0xff_u16 >> (column + count));
+            let mask = u64::from(bits as u8) << (8 * (7 - row));
+            self.or_block(Pos::new(x.div_euclid(BLOCK_WIDTH), block_y), mask);
+            x += count;
+        }
+    }
+    /// fills row `y` of the blocks from `block` up to `end_block`, with the largest aligned nodes that fit,
+    /// so a long row only takes a few nodes at each depth
+    fn fill_blocks(&mut self, y: i64, mut block: i64, end_block: i64) {
+        while block < end_block {
+            let mut level = 0;
+            while level <= Node::MAX_DEPTH
+                && block & ((2 << level) - 1) == 0
+                && block + (2 << level) <= end_block
+            {
+                level += 1;
+            }
+            let width = BLOCK_WIDTH << level;
+            let pos = Pos::new(block >> level, y.div_euclid(width));
+            let row = y.rem_euclid(width);
+            if level == 0 {
+                self.or_block(pos, 0xff << (8 * (7 - row)));
+            } else {
+                let depth = usize::from(level - 1);
+                if self.rows.len() <= depth {
+                    self.rows.resize_with(depth + 1, HashMap::new);
+                }
+                or_node(&mut self.rows[depth], pos, full_row(level - 1, row));
+            }
+            block += 1 << level;
+        }
+    }
+    fn or_block(&mut self, pos: Pos, mask: u64) {
+        let block = self.blocks.entry(pos).or_insert_with(Block::empty);
+        *block = Block::from_rows(block.to_rows() | mask);
+    }
+    pub(crate) fn into_node(self) -> Node {
+        if self.blocks.keys().all(is_around_origin) && self.rows.is_empty() {
+            return Node::from(around_origin(&self.blocks, Block::empty()));
+        }
+        let mut nodes = parents(self.blocks, Block::empty());
+        let mut rows = self.rows.into_iter();
+        let mut depth = 0;
+        loop {
+            for (pos, row) in rows.next().into_iter().flatten() {
+                or_node(&mut nodes, pos, row);
+            }
+            if rows.len() == 0 && nodes.keys().all(is_around_origin) {
+                break;
+            }
+            nodes = parents(nodes, Node::empty(depth));
+            depth += 1;
+        }
+        Node::from(around_origin(&nodes, Node::empty(depth)))
+    }
+}
+/// a node at `depth` with every cell in `row` alive
+fn full_row(depth: u8, row: i64) -> Node {
+    let half_width = BLOCK_WIDTH << depth;
+    let south = row >= half_width;
+    let row = row.rem_euclid(half_width);
+    if depth == 0 {
+        let (full, empty) = (Block::from_rows(0xff << (8 * (7 - row))), Block::empty());
+        if south {
+            Node::new(empty, empty, full, full)
+        } else {
+            Node::new(full, full, empty, empty)
+        }
+    } else {
+        let (full, empty) = (full_row(depth - 1, row), Node::empty(depth - 1));
+        if south {
+            Node::new(empty.clone(), empty, full.clone(), full)
+        } else {
+            Node::new(full.clone(), full, empty.clone(), empty)
+        }
+    }
+}
+fn or_node(nodes: &mut HashMap<Pos, Node>, pos: Pos, node: Node) {
+    match nodes.entry(pos) {
+        Entry::Occupied(mut entry) => {
+            let merged = entry.get().or(&node);
+            entry.insert(merged);
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(node);
+        }
+    }
+}
+/// if `pos` is one of the 4 children of a node centered on the origin
+fn is_around_origin(pos: &Pos) -> bool {
+    (-1..=0).contains(&pos.x) && (-1..=0).contains(&pos.y)
+}
+fn around_origin<T: Clone>(children: &HashMap<Pos, T>, empty: T) -> Quad<T> {
+    let child = |x: i64, y: i64| children.get(&Pos::new(x, y)).unwrap_or(&empty).clone();
+    Quad {
+        nw: child(-1, -1),
+        ne: child(0, -1),
+        sw: child(-1, 0),
+        se: child(0, 0),
+    }
+}
+/// groups each 2x2 area of children into their parent nodes
+fn parents<T: Clone>(children: HashMap<Pos, T>, empty: T) -> HashMap<Pos, Node>
+where
+    Node: From<Quad<T>>,
+{
+    let mut quads: HashMap<Pos, Quad<T>> = HashMap::new();
+    for (pos, child) in children {
+        let parent = Pos::new(pos.x.div_euclid(2), pos.y.div_euclid(2));
+        let quad = quads.entry(parent).or_insert_with(|| Quad {
+            nw: empty.clone(),
+            ne: empty.clone(),
+            sw: empty.clone(),
+            se: empty.clone(),
+        });
+        match (pos.x.rem_euclid(2), pos.y.rem_euclid(2)) {
+            (0, 0) => quad.nw = child,
+            (1, 0) => quad.ne = child,
+            (0, _) => quad.sw = child,
+            _ => quad.se = child,
+        }
+    }
+    quads
+        .into_iter()
+        .map(|(pos, quad)| (pos, Node::from(quad)))
+        .collect()
+}
+
+impl Node {
+    /// every block with a live cell
+    pub(crate) fn blocks(&self) -> HashMap<Pos, Block> {
+        fn add_blocks(node: &Node, nw: Pos, blocks: &mut HashMap<Pos, Block>) {
+            if node.is_empty() {
+                return;
+            }
+            let child_width = node.half_width() / BLOCK_WIDTH;
+            let at = |x: i64, y: i64| Pos::new(nw.x + x * child_width, nw.y + y * child_width);
+            let offsets = Quad {
+                nw: at(0, 0),
+                ne: at(1, 0),
+                sw: at(0, 1),
+                se: at(1, 1),
+            };
+            match node.depth_quad() {
+                DepthQuad::Leaf(leaf) => {
+                    for (pos, block) in offsets.into_iter().zip(leaf.iter()) {
+                        if !block.is_empty() {
+                            blocks.insert(pos, *block);
+                        }
+                    }
+                }
+                DepthQuad::Inner(_, inner) => {
+                    for (pos, child) in offsets.into_iter().zip(inner.iter()) {
+                        add_blocks(child, pos, blocks);
+                    }
+                }
+            }
+        }
+        let mut blocks = HashMap::new();
+        let half_width = self.half_width() / BLOCK_WIDTH;
+        add_blocks(self, Pos::new(-half_width, -half_width), &mut blocks);
+        blocks
+    }
+    pub(crate) fn runs(&self) -> Vec<Run> {
+        runs(
+            self.blocks()
+                .into_iter()
+                .map(|(pos, block)| (pos, states_from_planes(&[block]))),
+        )
+    }
+}
+impl MultiNode {
+    /// the state of each cell in every block with a cell that isn't in state 0
+    pub(crate) fn block_states(&self) -> HashMap<Pos, [[u8; 8]; 8]> {
+        let len = self.planes().len();
+        let mut planes: HashMap<Pos, Vec<Block>> = HashMap::new();
+        for (i, plane) in self.planes().iter().enumerate() {
+            for (pos, block) in plane.blocks() {
+                planes
+                    .entry(pos)
+                    .or_insert_with(|| vec![Block::empty(); len])[i] = block;
+            }
+        }
+        planes
+            .into_iter()
+            .map(|(pos, planes)| (pos, states_from_planes(&planes)))
+            .collect()
+    }
+    pub(crate) fn runs(&self) -> Vec<Run> {
+        runs(self.block_states())
+    }
+}
+
+/// cells next to each other in a row that have the same state
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct Run {
+    /// the west most cell
+    pub(crate) pos: Pos,
+    pub(crate) len: i64,
+    pub(crate) state: u8,
+}
+/// the runs of cells that aren't in state 0, sorted by row and then by column
+fn runs(blocks: impl IntoIterator<Item = (Pos, [[u8; 8]; 8])>) -> Vec<Run> {
+    let mut blocks: Vec<_> = blocks.into_iter().collect();
+    blocks.sort_unstable_by_key(|(pos, _)| (pos.y, pos.x));
+    let mut runs: Vec<Run> = Vec::new();
+    for (block_y, row_blocks) in &blocks.iter().group_by(|(pos, _)| pos.y) {
+        let row_blocks: Vec<_> = row_blocks.collect();
+        for (row, y) in (0..8).zip(block_y * BLOCK_WIDTH..) {
+            for (pos, states) in &row_blocks {
+                for (&state, x) in states[row].iter().zip(pos.x * BLOCK_WIDTH..) {
+                    if state == 0 {
+                        continue;
+                    }
+                    match runs.last_mut() {
+                        Some(run)
+                            if run.pos.y == y && run.pos.x + run.len == x && run.state == state =>
+                        {
+                            run.len += 1;
+                        }
+                        _ => runs.push(Run {
+                            pos: Pos::new(x, y),
+                            len: 1,
+                            state,
+                        }),
+                    }
+                }
+            }
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BlockSet, Run};
+    use crate::{MultiNode, Node, Pos};
+
+    #[test]
+    fn block_set() {
+        let cells = [(0, 0), (-1, -1), (7, 3), (8, 3), (-100, 37), (1 << 40, -5)];
+        let mut blocks = BlockSet::default();
+        let mut expected = Node::empty(0);
+        for (x, y) in cells {
+            blocks.set(Pos::new(x, y));
+            expected = expected.set(Pos::new(x, y), true);
+        }
+        // a row across several blocks
+        blocks.set_row(Pos::new(-13, 20), 30);
+        for x in -13..17 {
+            expected = expected.set(Pos::new(x, 20), true);
+        }
+        assert_eq!(blocks.into_node(), expected);
+        assert_eq!(BlockSet::default().into_node(), Node::empty(0));
+        assert_eq!(expected.blocks().len(), 10);
+    }
+
+    #[test]
+    fn long_rows() {
+        let rows = [
+            (-1003, 21, 2500),
+            (-1003, 22, 2500),
+            (40, 21, 700),
+            (-5, -300, 1 << 12),
+        ];
+        let mut blocks = BlockSet::default();
+        let mut cells = BlockSet::default();
+        for (x, y, len) in rows {
+            blocks.set_row(Pos::new(x, y), len);
+            for x in x..x + len {
+                cells.set(Pos::new(x, y));
+            }
+        }
+        assert_eq!(blocks.into_node(), cells.into_node());
+    }
+
+    #[test]
+    fn runs() {
+        let node = Node::empty(0)
+            .set(Pos::new(-9, -20), true)
+            .set(Pos::new(-8, -20), true)
+            .set(Pos::new(-7, -20), true)
+            .set(Pos::new(-5, -20), true)
+            .set(Pos::new(3, 4), true);
+        let run = |x: i64, y: i64, len: i64| Run {
+            pos: Pos::new(x, y),
+            len,
+            state: 1,
+        };
+        assert_eq!(
+            node.runs(),
+            [run(-9, -20, 3), run(-5, -20, 1), run(3, 4, 1)]
+        );
+        let multi = MultiNode::from(node)
+            .set(Pos::new(-6, -20), 3)
+            .set(Pos::new(-4, -20), 3);
+        let runs = multi.runs();
+        assert_eq!(runs.len(), 5);
+        assert_eq!(
+            runs[1],
+            Run {
+                pos: Pos::new(-6, -20),
+                len: 1,
+                state: 3
+            }
+        );
+    }
+}
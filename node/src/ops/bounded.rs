@@ -0,0 +1,461 @@
+//! steps patterns inside of a bounded grid
+//!
+//! the edges of a torus or Klein bottle are joined by surrounding the grid with copies of itself,
+//! so the grid can be stepped for as long as its cells can only see the copies next to it
+
+use crate::{Block, MultiNode, Node, Pos, Rect, Rule, Topology, TwistedEdges};
+
+impl Node {
+    /// steps the cells inside of `rect`, with its edges joined according to `topology` every generation
+    ///
+    /// cells outside of `rect` are ignored, and `rect` is used instead of any grid the rule has
+    ///
+    /// takes at most as long as the grid takes to repeat, however large `steps` is
+    pub fn step_bounded(&self, rule: &Rule, steps: u64, topology: Topology, rect: Rect) -> Node {
+        step_bounded(self, rule, steps, topology, rect)
+    }
+}
+impl MultiNode {
+    /// the same as `Node::step_bounded`
+    pub fn step_bounded(
+        &self,
+        rule: &Rule,
+        steps: u64,
+        topology: Topology,
+        rect: Rect,
+    ) -> MultiNode {
+        step_bounded(self, rule, steps, topology, rect)
+    }
+}
+
+/// the parts of a node needed to step it in a bounded grid
+trait Bounded: Clone + PartialEq {
+    fn depth(&self) -> u8;
+    /// applies `f` to every bit-plane
+    fn map_planes(&self, f: impl Fn(&Node) -> Node) -> Self;
+    fn or(&self, other: &Self) -> Self;
+    /// steps without a grid, with every cell inside of `rect` correct as long as it has a dead background
+    fn step_unbounded(&self, rule: &Rule, steps: u64, rect: Rect) -> Self;
+}
+impl Bounded for Node {
+    fn depth(&self) -> u8 {
+        Node::depth(self)
+    }
+    fn map_planes(&self, f: impl Fn(&Node) -> Node) -> Self {
+        f(self)
+    }
+    fn or(&self, other: &Self) -> Self {
+        Node::or(self, other)
+    }
+    fn step_unbounded(&self, rule: &Rule, steps: u64, rect: Rect) -> Self {
+        let (node, background) = self.step_with_background(rule, steps, false);
+        if background {
+            // a live background means the node has the dead cells instead
+            let node = node.center_at_depth(node.depth().max(depth_containing(rect)));
+            filled(node.depth()).xor(&node)
+        } else {
+            node
+        }
+    }
+}
+impl Bounded for MultiNode {
+    fn depth(&self) -> u8 {
+        MultiNode::depth(self)
+    }
+    fn map_planes(&self, f: impl Fn(&Node) -> Node) -> Self {
+        MultiNode::new(self.planes().iter().map(f).collect())
+    }
+    fn or(&self, other: &Self) -> Self {
+        let len = self.planes().len().max(other.planes().len());
+        MultiNode::new(
+            (0..len)
+                .map(|i| self.plane(i).or(&other.plane(i)))
+                .collect(),
+        )
+    }
+    fn step_unbounded(&self, rule: &Rule, steps: u64, rect: Rect) -> Self {
+        if rule.states() == 2 {
+            // the only rules that can have a live background
+            MultiNode::from(self.plane(0).step_unbounded(rule, steps, rect))
+        } else {
+            self.step(rule, steps)
+        }
+    }
+}
+
+fn step_bounded<N: Bounded>(
+    node: &N,
+    rule: &Rule,
+    steps: u64,
+    topology: Topology,
+    rect: Rect,
+) -> N {
+    assert!(!rect.is_empty(), "bounded grids can't be empty");
+    let rule = rule.clone().with_grid(None);
+    let size = (
+        side(rect.west(), rect.east()),
+        side(rect.north(), rect.south()),
+    );
+    let range = u64::from(rule.range());
+    let mut node = node.map_planes(|plane| plane.clip(rect));
+    let mut remaining = steps;
+    // a grid only has so many states, so it eventually repeats and the steps of each cycle can be skipped,
+    // found by comparing with a saved state that is replaced after twice as many chunks each time
+    let mut saved = node.clone();
+    let (mut since_saved, mut chunks_since_saved, mut chunks_until_saved) = (0, 0, 1);
+    while remaining > 0 {
+        let chunk = if topology == Topology::Plane {
+            // cells that leave the grid have to be removed every generation
+            1
+        } else {
+            // as many steps as keeps the cells that the grid can see within the copies next to it,
+            // rounded down to a power of 2 so that each chunk shares the step cache
+            let most = (size.0.min(size.1) / range).max(1);
+            (1 << most.ilog2()).min(remaining)
+        };
+        let copies = (
+            (chunk * range).div_ceil(size.0),
+            (chunk * range).div_ceil(size.1),
+        );
+        node = surround(&node, topology, rect, copies)
+            .step_unbounded(&rule, chunk, rect)
+            .map_planes(|plane| plane.clip(rect));
+        remaining -= chunk;
+        since_saved += chunk;
+        chunks_since_saved += 1;
+        if same(&node, &saved) {
+            remaining %= since_saved;
+        }
+        if chunks_since_saved == chunks_until_saved {
+            saved = node.clone();
+            (since_saved, chunks_since_saved) = (0, 0);
+            chunks_until_saved *= 2;
+        }
+    }
+    node
+}
+
+/// if `a` and `b` have the same cells, even if they have different depths
+fn same<N: Bounded>(a: &N, b: &N) -> bool {
+    let depth = a.depth().max(b.depth());
+    a.map_planes(|plane| plane.center_at_depth(depth))
+        == b.map_planes(|plane| plane.center_at_depth(depth))
+}
+
+/// surrounds the grid with `copies` of itself in each direction
+#[allow(clippy::cast_possible_wrap)] // grids are much smaller than i64::MAX
+fn surround<N: Bounded>(node: &N, topology: Topology, rect: Rect, copies: (u64, u64)) -> N {
+    if topology == Topology::Plane {
+        return node.clone();
+    }
+    let (width, height) = (
+        side(rect.west(), rect.east()) as i64,
+        side(rect.north(), rect.south()) as i64,
+    );
+    let copies = (copies.0 as i64, copies.1 as i64);
+    let outer = Rect::min_max(
+        Pos::new(
+            rect.west() - copies.0 * width,
+            rect.north() - copies.1 * height,
+        ),
+        Pos::new(
+            rect.east() + copies.0 * width,
+            rect.south() + copies.1 * height,
+        ),
+    );
+    let depth = depth_containing(outer);
+    let node = node.map_planes(|plane| plane.center_at_depth(depth));
+    // the grid mirrored in place, for the copies across a twisted edge
+    let mirrored = match topology {
+        Topology::KleinBottle(TwistedEdges::Horizontal) => node.map_planes(|plane| {
+            plane
+                .flip_h()
+                .offset(Pos::new(rect.west() + rect.east() + 1, 0))
+        }),
+        Topology::KleinBottle(TwistedEdges::Vertical) => node.map_planes(|plane| {
+            plane
+                .flip_v()
+                .offset(Pos::new(0, rect.north() + rect.south() + 1))
+        }),
+        _ => node.clone(),
+    };
+    let mut surrounded = node.clone();
+    for y in -copies.1..=copies.1 {
+        for x in -copies.0..=copies.0 {
+            if (x, y) == (0, 0) {
+                continue;
+            }
+            let twisted = match topology {
+                Topology::KleinBottle(TwistedEdges::Horizontal) => y & 1 == 1,
+                Topology::KleinBottle(TwistedEdges::Vertical) => x & 1 == 1,
+                _ => false,
+            };
+            let copy = if twisted { &mirrored } else { &node };
+            let copy = copy.map_planes(|plane| plane.offset(Pos::new(x * width, y * height)));
+            surrounded = surrounded.or(&copy);
+        }
+    }
+    surrounded
+}
+
+/// number of cells from `min` to `max` inclusive
+fn side(min: i64, max: i64) -> u64 {
+    max.abs_diff(min) + 1
+}
+
+/// the smallest depth of a node centered on the origin that contains `rect`
+fn depth_containing(rect: Rect) -> u8 {
+    (0..=Node::MAX_DEPTH)
+        .find(|&depth| {
+            let half_width = Block::HALF_WIDTH << (depth + 1);
+            rect.west() >= -half_width
+                && rect.north() >= -half_width
+                && rect.east() < half_width
+                && rect.south() < half_width
+        })
+        .expect("rect to fit in the largest node")
+}
+
+/// a node with every cell alive
+fn filled(depth: u8) -> Node {
+    let block = Block::from_rows(!0);
+    let mut node = Node::new(block, block, block, block);
+    for _ in 0..depth {
+        node = Node::new(node.clone(), node.clone(), node.clone(), node);
+    }
+    node
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BoundedGrid;
+
+    fn pattern(origin: Pos, rows: &str) -> MultiNode {
+        let mut node = MultiNode::empty(0);
+        for (y, row) in (origin.y..).zip(rows.split_whitespace()) {
+            for (x, c) in (origin.x..).zip(row.bytes()) {
+                if c != b'.' {
+                    node = node.set(Pos { x, y }, c - b'0');
+                }
+            }
+        }
+        node
+    }
+    fn assert_same(a: &MultiNode, b: &MultiNode) {
+        let depth = a.depth().max(b.depth());
+        assert_eq!(a.center_at_depth(depth), b.center_at_depth(depth));
+    }
+
+    const GLIDER: &str = "
+        .1.
+        ..1
+        111
+        ";
+
+    #[test]
+    fn torus() {
+        let rule = "B3/S23:T16,16".parse().unwrap();
+        let glider = pattern(Pos::new(0, 0), GLIDER);
+        // the glider goes around the torus every 64 generations
+        for steps in [64, 128, 64 * 200] {
+            assert_same(&glider.step(&rule, steps), &glider);
+        }
+        let moved = pattern(Pos::new(1, 1), GLIDER);
+        assert_same(&glider.step(&rule, 64 * 200 + 4), &moved);
+        // without stepping every cycle
+        assert_same(&glider.step(&rule, (1 << 60) + 4), &moved);
+        // wrapping from the south east corner to every other corner
+        let corner = pattern(Pos::new(5, 5), GLIDER);
+        let wrapped = pattern(Pos::new(-8, -8), "1.............11")
+            .set(Pos::new(7, 6), 1)
+            .set(Pos::new(-8, 7), 1);
+        assert_same(&corner.step(&rule, 4), &wrapped);
+    }
+
+    #[test]
+    fn klein_bottle() {
+        let glider = pattern(Pos::new(0, 0), GLIDER);
+        // crossing the twisted edge mirrors the glider around the center of the grid
+        let rule = "B3/S23:K16*,16".parse().unwrap();
+        let mirrored = pattern(
+            Pos::new(-3, 0),
+            "
+            .1.
+            1..
+            111
+            ",
+        );
+        assert_same(&glider.step(&rule, 64), &mirrored);
+        assert_same(&glider.step(&rule, 128), &glider);
+
+        let grid = BoundedGrid::new(Topology::KleinBottle(TwistedEdges::Vertical), 16, 12);
+        let split = pattern(
+            Pos::new(0, -6),
+            "
+            ..1
+            .1.
+            ...
+            ...
+            ...
+            ...
+            ...
+            ...
+            ...
+            ...
+            ...
+            111
+            ",
+        );
+        assert_same(
+            &glider.step_bounded(&Rule::LIFE, 64, grid.topology(), grid.rect()),
+            &split,
+        );
+    }
+
+    #[test]
+    fn plane() {
+        let rule = "B3/S23:P10,10".parse().unwrap();
+        let glider = pattern(Pos::new(0, 0), GLIDER);
+        // the glider turns into a block in the corner
+        let block = pattern(
+            Pos::new(3, 3),
+            "
+            11
+            11
+            ",
+        );
+        assert_same(&glider.step(&rule, 40), &block);
+        // cells outside of the grid are removed
+        let outside = glider.set(Pos::new(20, 0), 1);
+        assert_same(&outside.step(&rule, 40), &block);
+        assert_same(&glider.step(&rule, 1 << 60), &block);
+    }
+
+    #[test]
+    fn b0() {
+        let rule = "B03/S23:T10,8".parse().unwrap();
+        let soup = pattern(
+            Pos::new(-3, -2),
+            "
+            1.1...
+            1....1
+            1...1.
+            ...1..
+            ..11..
+            ",
+        );
+        let expected = [
+            (
+                1,
+                Pos::new(-5, -4),
+                "
+                1111111111
+                1.....1111
+                1..1.....1
+                111......1
+                1.....1..1
+                1...111.11
+                111.11.111
+                111....111
+                ",
+            ),
+            (
+                2,
+                Pos::new(-3, -4),
+                "
+                .111...
+                .......
+                1....1.
+                1.....1
+                ....11.
+                .11....
+                1.1....
+                ",
+            ),
+            (
+                7,
+                Pos::new(-5, -4),
+                "
+                .1.......1
+                ...1...1.1
+                .1.1.1.1..
+                .1....11..
+                1...1.1.1.
+                11111..1..
+                ..1.1..1..
+                .......1..
+                ",
+            ),
+        ];
+        for (steps, origin, rows) in expected {
+            assert_same(&soup.step(&rule, steps), &pattern(origin, rows));
+            let node = soup.occupied().step(&rule, steps);
+            assert_same(&MultiNode::from(node), &pattern(origin, rows));
+        }
+    }
+
+    #[test]
+    fn multi_state() {
+        let rule = "/2/3:T10,8".parse().unwrap();
+        let soup = pattern(
+            Pos::new(-3, -3),
+            "
+            1...1.
+            ...221
+            2211..
+            ..1212
+            1..2.1
+            .22..1
+            ",
+        );
+        let expected = [
+            (
+                5,
+                "
+                ..1..2....
+                ..211.....
+                .1.12.1...
+                2...12.2..
+                11...12.1.
+                .21...1.21
+                .2.11..22.
+                1.122111.2
+                ",
+            ),
+            (
+                13,
+                "
+                .1....1...
+                2.2..2....
+                1.2.1..1..
+                ...1212212
+                .....1.11.
+                ..........
+                ..........
+                .......2..
+                ",
+            ),
+        ];
+        for (steps, rows) in expected {
+            assert_same(&soup.step(&rule, steps), &pattern(Pos::new(-5, -4), rows));
+        }
+    }
+
+    #[test]
+    fn range_larger_than_grid() {
+        // cells see some cells more than once, through multiple copies of the grid
+        let rule = "R3,C0,M0,S18..26,B20..24,NM:T7,5".parse().unwrap();
+        let soup = pattern(
+            Pos::new(-3, -2),
+            "
+            ..11..1
+            1111.11
+            ..1..1.
+            .111..1
+            11.1111
+            ",
+        );
+        assert_same(&soup.step(&rule, 1), &pattern(Pos::new(-1, -2), "11..1"));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{Block, DepthQuad, Node, Pos, Quad, Rect};
+
+// computed once for each node when it is created, see `Node::bounding_rect`
+
+impl DepthQuad<Block, Node> {
+    pub(crate) fn bounding_rect(&self) -> Rect {
+        match self {
+            DepthQuad::Leaf(leaf) => join(leaf.map(Block::bounding_rect), Block::HALF_WIDTH),
+            DepthQuad::Inner(_, inner) => join(
+                inner.as_ref().map(Node::bounding_rect),
+                inner.nw.half_width(),
+            ),
+        }
+    }
+}
+
+impl Block {
+    /// centered on the block like `Node::get`, so the north west cell is at `-4, -4`
+    #[allow(clippy::cast_possible_truncation)] // the low byte has every column
+    fn bounding_rect(self) -> Rect {
+        let rows = self.to_rows();
+        if rows == 0 {
+            return Rect::NOTHING;
+        }
+        let columns = rows | (rows >> 32);
+        let columns = columns | (columns >> 16);
+        let columns = (columns | (columns >> 8)) as u8;
+        // the most significant bit is the north west cell
+        let min = Pos::new(
+            i64::from(columns.leading_zeros()),
+            i64::from(rows.leading_zeros() / 8),
+        );
+        let max = Pos::new(
+            i64::from(7 - columns.trailing_zeros()),
+            i64::from(7 - rows.trailing_zeros() / 8),
+        );
+        let half = Pos::new(Block::HALF_WIDTH, Block::HALF_WIDTH);
+        Rect::min_max(min - half, max - half)
+    }
+}
+
+/// the union of the children after moving them from their centers to the center of their parent
+fn join(children: Quad<Rect>, child_half_width: i64) -> Rect {
+    let centers = Quad {
+        nw: Pos::new(-child_half_width, -child_half_width),
+        ne: Pos::new(child_half_width, -child_half_width),
+        sw: Pos::new(-child_half_width, child_half_width),
+        se: Pos::new(child_half_width, child_half_width),
+    };
+    let mut rect = Rect::NOTHING;
+    for (mut child, center) in children.into_iter().zip(centers) {
+        // offsetting an empty rect would overflow
+        if !child.is_empty() {
+            child.offset(center);
+            rect.union(child);
+        }
+    }
+    rect
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use crate::{test_node, Node, Pos, Rect};
+
+    #[test]
+    fn small() {
+        let glider = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        assert_eq!(
+            glider.bounding_rect(),
+            Rect::min_max(Pos::new(0, 0), Pos::new(2, 2))
+        );
+        // across every quadrant and block edge
+        let corners = test_node! {"
+            ........o.......
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ...............o
+            ................
+            ................
+            ................
+            o...............
+            ................
+            ................
+            ................
+            .......o........
+            "};
+        assert_eq!(
+            corners.bounding_rect(),
+            Rect::min_max(Pos::new(-8, -8), Pos::new(7, 7))
+        );
+        assert_eq!(
+            Node::empty(0).set(Pos::new(-3, 5), true).bounding_rect(),
+            Rect::just(Pos::new(-3, 5))
+        );
+        assert!(Node::empty(4).bounding_rect().is_empty());
+        // every cell
+        let full = crate::Block::from_rows(u64::MAX);
+        let full = Node::new(full, full, full, full);
+        assert_eq!(
+            full.bounding_rect(),
+            Rect::min_max(Pos::new(-8, -8), Pos::new(7, 7))
+        );
+    }
+
+    #[test]
+    fn huge() {
+        let max = (1 << 62) - 1;
+        let node = Node::empty(0)
+            .set(Pos::new(-(1 << 62), 5), true)
+            .set(Pos::new(max, -7), true)
+            .set(Pos::new(3, max), true);
+        assert_eq!(node.depth(), Node::MAX_DEPTH);
+        assert_eq!(
+            node.bounding_rect(),
+            Rect::min_max(Pos::new(-(1 << 62), -7), Pos::new(max, max))
+        );
+        // moving the cells keeps the same size
+        let moved = Node::empty(0)
+            .set(Pos::new(20, 30), true)
+            .set(Pos::new(-40, 3), true)
+            .offset(Pos::new(-5, 5));
+        assert_eq!(
+            moved.bounding_rect(),
+            Rect::min_max(Pos::new(-45, 8), Pos::new(15, 35))
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! the plaintext format, with `!` comment lines and rows of `.` for dead cells and `O` for live cells
+//!
+//! rows can have different lengths, trailing dead cells are usually left out
+
+use std::error::Error;
+use std::fmt::{Debug, Display};
+use std::io::{Error as IoError, Write};
+
+use super::blocks::{BlockSet, Run};
+use super::mc_format::LineInfo;
+use crate::{Node, Pattern, Pos};
+
+impl Node {
+    /// writes the pattern without any comments, see `Pattern::write_cells` to write them
+    pub fn write_cells(&self, write: impl Write) -> Result<(), IoError> {
+        Pattern::new(self.clone()).write_cells(write)
+    }
+    pub fn write_cells_to_string(&self) -> String {
+        let mut out = Vec::new();
+        self.write_cells(&mut out).expect("valid bytes");
+        String::from_utf8(out).expect("valid string")
+    }
+    /// ignores any comments, see `Pattern::read_cells` to also read them
+    pub fn read_cells(bytes: &[u8]) -> Result<Self, CellsError<&[u8]>> {
+        Pattern::<Node>::read_cells(bytes).map(|pattern| pattern.node)
+    }
+}
+impl Pattern<Node> {
+    /// writes the name and author in `!Name:` and `!Author:` lines, and each line of the comments after a `!`
+    ///
+    /// the file has no position, so the rows start at the north most live cell and the columns at the west most live cell
+    pub fn write_cells(&self, mut write: impl Write) -> Result<(), IoError> {
+        if let Some(name) = &self.name {
+            writeln!(write, "!Name: {name}")?;
+        }
+        if let Some(author) = &self.author {
+            writeln!(write, "!Author: {author}")?;
+        }
+        for line in self.comments.iter().flat_map(|comment| comment.split('\n')) {
+            writeln!(write, "!{}", line.trim_end_matches('\r'))?;
+        }
+
+        let runs = self.node.runs();
+        let west = runs.iter().map(|run| run.pos.x).min().unwrap_or(0);
+        write_rows(&mut write, &runs, west, b'O')
+    }
+    /// the north west corner of the rows is at the origin, so the first cell of the first row is at `Pos::new(0, 0)`
+    ///
+    /// live cells can also be written as `o` or `*`, and the `!Name:` and `!Author:` lines are kept as the name and author
+    pub fn read_cells(bytes: &[u8]) -> Result<Self, CellsError<&[u8]>> {
+        let mut pattern = Pattern::new(());
+        let mut cells = BlockSet::default();
+        let mut y = 0;
+        let mut line_at = 0;
+        for line in bytes.split_inclusive(|&b| b == b'\n') {
+            let at = line_at;
+            line_at += line.len();
+            let mut line = line.strip_suffix(b"\n").unwrap_or(line);
+            while let [rest @ .., b'\r' | b' ' | b'\t'] = line {
+                line = rest;
+            }
+            if let Some(text) = line.strip_prefix(b"!") {
+                let text = match std::str::from_utf8(text) {
+                    Ok(text) => text,
+                    Err(err) => return fail_at(bytes, at + 1 + err.valid_up_to()),
+                };
+                let value = |text: &str| text.strip_prefix(' ').unwrap_or(text).to_owned();
+                if let Some(name) = text.strip_prefix("Name:") {
+                    pattern.name = Some(value(name));
+                } else if let Some(author) = text.strip_prefix("Author:") {
+                    pattern.author = Some(value(author));
+                } else {
+                    pattern.comments.push(text.to_owned());
+                }
+                continue;
+            }
+            let mut run_start = None;
+            // the extra dead cell ends a run at the end of the line
+            for ((x, i), &b) in (0..).zip(at..).zip(line.iter().chain(b".")) {
+                match b {
+                    b'O' | b'o' | b'*' => {
+                        run_start.get_or_insert(x);
+                    }
+                    b'.' => {
+                        if let Some(start) = run_start.take() {
+                            cells.set_row(Pos::new(start, y), x - start);
+                        }
+                    }
+                    _ => return fail_at(bytes, i),
+                }
+            }
+            y += 1;
+        }
+        Ok(pattern.map(|()| cells.into_node()))
+    }
+}
+/// writes a line for each row from the first run to the last, with `alive` for the cells in a run and `.` for the rest
+///
+/// `runs` are sorted by row and then by column, and `west` is the first column of each line
+pub(crate) fn write_rows(
+    mut write: impl Write,
+    runs: &[Run],
+    west: i64,
+    alive: u8,
+) -> Result<(), IoError> {
+    let mut y = runs.first().map_or(0, |run| run.pos.y);
+    let mut row = Vec::new();
+    for run in runs {
+        while run.pos.y > y {
+            write_row(&mut write, &mut row)?;
+            y += 1;
+        }
+        let start = usize::try_from(run.pos.x - west).expect("row fits in memory");
+        let end = start + usize::try_from(run.len).expect("row fits in memory");
+        row.resize(start, b'.');
+        row.resize(end, alive);
+    }
+    if !runs.is_empty() {
+        write_row(&mut write, &mut row)?;
+    }
+    Ok(())
+}
+/// writes and clears `row`, with a `.` for rows without any live cells
+fn write_row(write: &mut impl Write, row: &mut Vec<u8>) -> Result<(), IoError> {
+    if row.is_empty() {
+        row.push(b'.');
+    }
+    row.push(b'\n');
+    write.write_all(row)?;
+    row.clear();
+    Ok(())
+}
+
+// parsing
+
+#[derive(Clone)]
+pub struct CellsError<S>(Box<LineInfo<S>>);
+impl<S> CellsError<S> {
+    pub fn line_src(&self) -> &S {
+        &self.0.line_src
+    }
+    /// 0 based line index
+    pub fn line(&self) -> usize {
+        self.0.line
+    }
+    /// 0 based column index
+    pub fn column(&self) -> usize {
+        self.0.column
+    }
+    pub fn hint(&self) -> &'static str {
+        "Cells are '.' for dead and 'O' for alive, and comments start with '!'"
+    }
+}
+impl CellsError<&[u8]> {
+    pub fn into_owned(self) -> CellsError<Vec<u8>> {
+        CellsError(Box::new(self.0.into_owned()))
+    }
+}
+impl<S: AsRef<[u8]>> Debug for CellsError<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CellsError")
+            .field("line", &self.line())
+            .field("column", &self.column())
+            .field(
+                "line_src",
+                &String::from_utf8_lossy(self.line_src().as_ref()),
+            )
+            .finish()
+    }
+}
+impl<S: AsRef<[u8]>> Display for CellsError<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let line = self.line() + 1;
+        let line_src = String::from_utf8_lossy(self.line_src().as_ref());
+        let column = self.column() + 1;
+        let mark = "^";
+        let hint = self.hint();
+        writeln!(
+            f,
+            "Failed to parse plaintext on line {line}:\n{line_src}\n{mark:>column$}\n{hint}\n"
+        )
+    }
+}
+impl<S: AsRef<[u8]>> Error for CellsError<S> {}
+
+fn fail_at<T>(src: &[u8], at: usize) -> Result<T, CellsError<&[u8]>> {
+    Err(CellsError(Box::new(LineInfo::from_src_offset(src, at))))
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use unindent::unindent;
+
+    use crate::{test_node, Node, Pattern, Pos};
+
+    #[test]
+    fn glider() {
+        let glider = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        let fmt = unindent(
+            "
+            .O
+            ..O
+            OOO
+            ",
+        );
+        assert_eq!(glider.write_cells_to_string(), fmt);
+        assert_eq!(Node::read_cells(fmt.as_bytes()).unwrap(), glider);
+        // padded rows, windows line endings, and other live cells are the same
+        let padded = "!Name: Glider\r\n.O..\r\n..*.  \r\nooo.\r\n";
+        assert_eq!(Node::read_cells(padded.as_bytes()).unwrap(), glider);
+    }
+
+    #[test]
+    fn origin() {
+        // the written rows start at the pattern, but are read back from the origin
+        let node = Node::empty(0)
+            .set(Pos::new(-5, -3), true)
+            .set(Pos::new(-2, 4), true);
+        let fmt = node.write_cells_to_string();
+        assert_eq!(fmt, "O\n.\n.\n.\n.\n.\n.\n...O\n");
+        assert_eq!(
+            Node::read_cells(fmt.as_bytes()).unwrap(),
+            Node::empty(0)
+                .set(Pos::new(0, 0), true)
+                .set(Pos::new(3, 7), true)
+        );
+        assert_eq!(Node::empty(3).write_cells_to_string(), "");
+        assert_eq!(Node::read_cells(b"").unwrap(), Node::empty(0));
+    }
+
+    #[test]
+    fn comments() {
+        let fmt = unindent(
+            "
+            !Name: Blinker
+            !Author: John Conway
+            !The smallest oscillator.
+            !
+            !www.conwaylife.com/wiki/Blinker
+            OOO
+            ",
+        );
+        let read = Pattern::<Node>::read_cells(fmt.as_bytes()).unwrap();
+        let blinker = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        assert_eq!(read.node, blinker);
+        assert_eq!(read.name.as_deref(), Some("Blinker"));
+        assert_eq!(read.author.as_deref(), Some("John Conway"));
+        assert_eq!(
+            read.comments,
+            [
+                "The smallest oscillator.",
+                "",
+                "www.conwaylife.com/wiki/Blinker"
+            ]
+        );
+        let mut out = Vec::new();
+        read.write_cells(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), fmt);
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |s: &str| {
+            let err = Node::read_cells(s.as_bytes()).unwrap_err();
+            (err.line(), err.column())
+        };
+        assert_eq!(error("..O\n.x.\n"), (1, 1));
+        assert_eq!(error("!comment\nO O\n"), (1, 1));
+        assert_eq!(error("OOO\n#C rle comment\n"), (1, 0));
+    }
+}
@@ -0,0 +1,187 @@
+//! splits a node into its separate objects
+//!
+//! works on the runs of live cells, so empty space costs nothing no matter how far apart the objects are
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::blocks::{BlockSet, Run};
+use crate::{Node, Pos};
+
+/// which cells are part of the same object
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Connectivity {
+    /// cells that touch, including diagonally
+    Adjacent,
+    /// cells with at most 1 dead cell between them, like Golly's object separation,
+    /// so pseudo objects and things like a pre-block next to a block stay together
+    WithinTwo,
+}
+impl Connectivity {
+    fn range(self) -> i64 {
+        match self {
+            Connectivity::Adjacent => 1,
+            Connectivity::WithinTwo => 2,
+        }
+    }
+}
+
+impl Node {
+    /// each object, with its node's 0,0 at the returned Pos in self's coordinate space, like `offset_norm`
+    ///
+    /// the north west corner of each object's cells is at 0,0, and the objects are sorted by their first cell in reading order
+    pub fn components(&self, connectivity: Connectivity) -> Vec<(Pos, Node)> {
+        let runs = self.runs();
+        let range = connectivity.range();
+        // the runs in each row
+        let mut rows: HashMap<i64, (usize, usize)> = HashMap::new();
+        for (i, run) in runs.iter().enumerate() {
+            rows.entry(run.pos.y).or_insert((i, i)).1 = i + 1;
+        }
+        let mut sets = DisjointSets::new(runs.len());
+        for (i, run) in runs.iter().enumerate() {
+            let west = run.pos.x - range;
+            let east = run.pos.x + run.len - 1 + range;
+            // only looks north and west, since the runs to the south and east will look back at this one
+            for y in run.pos.y - range..=run.pos.y {
+                let Some(&(start, end)) = rows.get(&y) else {
+                    continue;
+                };
+                let row = &runs[start..end.min(i)];
+                let first = row.partition_point(|other| other.pos.x + other.len - 1 < west);
+                for (j, other) in (start..).zip(row).skip(first) {
+                    if other.pos.x > east {
+                        break;
+                    }
+                    sets.join(i, j);
+                }
+            }
+        }
+
+        // each root is the first run of its component
+        let mut components: BTreeMap<usize, Vec<&Run>> = BTreeMap::new();
+        for (i, run) in runs.iter().enumerate() {
+            components.entry(sets.root(i)).or_default().push(run);
+        }
+        components
+            .into_values()
+            .map(|runs| {
+                let west = runs.iter().map(|run| run.pos.x).min().expect("not empty");
+                // runs are in reading order, so the first is the north most
+                let nw = Pos::new(west, runs[0].pos.y);
+                let mut cells = BlockSet::default();
+                for run in runs {
+                    cells.set_row(run.pos - nw, run.len);
+                }
+                (nw, cells.into_node())
+            })
+            .collect()
+    }
+}
+
+struct DisjointSets {
+    parents: Vec<usize>,
+}
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+        }
+    }
+    fn root(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            // halves the path each time it is walked
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+    fn join(&mut self, a: usize, b: usize) {
+        let a = self.root(a);
+        let b = self.root(b);
+        self.parents[a.max(b)] = a.min(b);
+    }
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use super::Connectivity;
+    use crate::{test_node, Node, Pos};
+
+    fn glider() -> Node {
+        test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "}
+    }
+    fn block() -> Node {
+        test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ........oo......
+            ........oo......
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            "}
+    }
+
+    #[test]
+    fn separate() {
+        let far = 1 << 50;
+        let universe = block()
+            .or(&glider().offset(Pos::new(-far, far)))
+            .set(Pos::new(far, 3), true);
+        let components = universe.components(Connectivity::Adjacent);
+        assert_eq!(
+            components,
+            [
+                (Pos::new(0, 0), block()),
+                (Pos::new(far, 3), Node::empty(0).set(Pos::new(0, 0), true)),
+                (Pos::new(-far, far), glider()),
+            ]
+        );
+        assert_eq!(Node::empty(5).components(Connectivity::Adjacent), []);
+    }
+
+    #[test]
+    fn connectivity() {
+        // a glider's cells only touch diagonally
+        assert_eq!(glider().components(Connectivity::Adjacent).len(), 1);
+        // 2 blocks with a gap of 1 cell, next to each other and diagonally
+        for (x, y) in [(3, 0), (3, 3), (0, 3), (-3, 3), (3, -1)] {
+            let blocks = block().or(&block().offset(Pos::new(x, y)));
+            assert_eq!(blocks.components(Connectivity::Adjacent).len(), 2);
+            let together = blocks.components(Connectivity::WithinTwo);
+            assert_eq!(together.len(), 1);
+            assert_eq!(together[0].1.components(Connectivity::Adjacent).len(), 2);
+        }
+        // but not with a gap of 2
+        let apart = block().or(&block().offset(Pos::new(4, 0)));
+        assert_eq!(apart.components(Connectivity::WithinTwo).len(), 2);
+    }
+}
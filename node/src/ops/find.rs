@@ -0,0 +1,361 @@
+//! finds every copy of a small pattern in a node
+//!
+//! every window the size of the pattern fits in one of the 9 half width squares of a node that are offset by a quarter width,
+//! so the matches in a node are the matches in those squares, which are nodes themselves and are only searched once each
+//! no matter how many times the same cells show up, and any square with fewer cells than the pattern is skipped
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::{DepthQuad, Node, Population, Pos, Quad, Rect, Transform};
+
+/// a copy of the pattern in `transform`'s orientation, with the north west corner of its cells at `pos`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Match {
+    pub pos: Pos,
+    pub transform: Transform,
+}
+
+impl Node {
+    /// every copy of `pattern` in any of the orientations in `symmetries`, sorted by `pos` in reading order
+    ///
+    /// the cells around a copy, within 1 cell of its bounding rect, have to be dead,
+    /// so a block isn't found in the middle of a larger square and a glider isn't found in part of a collision
+    ///
+    /// orientations that give the same cells as an earlier one in `symmetries` are skipped, so each copy is only found once
+    pub fn find(&self, pattern: &Node, symmetries: &[Transform]) -> Vec<Match> {
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+        // so copies at the edge have their dead cells inside the node, the largest node is searched as is
+        // so copies touching its edge are not found
+        let universe = if self.depth() < Node::MAX_DEPTH {
+            self.center_at_depth(self.depth() + 1)
+        } else {
+            self.clone()
+        };
+        let mut shapes = Vec::new();
+        let mut matches = Vec::new();
+        for &transform in symmetries {
+            let shape = Shape::new(&pattern.transform(transform));
+            if shapes.contains(&shape.cells) {
+                continue;
+            }
+            let mut finder = Finder {
+                shape: &shape,
+                none: Rc::new(Vec::new()),
+                memo: HashMap::new(),
+            };
+            matches.extend(
+                finder
+                    .find(&universe)
+                    .iter()
+                    .map(|&pos| Match { pos, transform }),
+            );
+            shapes.push(shape.cells);
+        }
+        matches.sort_by_key(|m| (m.pos.y, m.pos.x));
+        matches
+    }
+}
+
+/// the live cells of a pattern, relative to the north west corner of its bounding rect
+struct Shape {
+    cells: Vec<Pos>,
+    size: Pos,
+    population: u64,
+}
+impl Shape {
+    fn new(pattern: &Node) -> Self {
+        let rect = pattern.bounding_rect();
+        let nw = rect.nw();
+        let cells: Vec<Pos> = pattern
+            .runs()
+            .iter()
+            .flat_map(|run| (0..run.len).map(move |x| run.pos + Pos::new(x, 0)))
+            .map(|pos| pos - nw)
+            .collect();
+        Shape {
+            population: cells.len() as u64,
+            size: Pos::new(
+                rect.east() - rect.west() + 1,
+                rect.south() - rect.north() + 1,
+            ),
+            cells,
+        }
+    }
+    /// including the dead cells around it
+    fn window_width(&self) -> i64 {
+        self.size.x.max(self.size.y) + 2
+    }
+}
+
+struct Finder<'s> {
+    shape: &'s Shape,
+    none: Rc<Vec<Pos>>,
+    /// the matches in each node that has been searched, relative to its center
+    memo: HashMap<Node, Rc<Vec<Pos>>>,
+}
+impl Finder<'_> {
+    fn find(&mut self, node: &Node) -> Rc<Vec<Pos>> {
+        if node.population() < self.shape.population {
+            return self.none.clone();
+        }
+        if let Some(found) = self.memo.get(node) {
+            return found.clone();
+        }
+        let quarter = node.half_width() / 2;
+        let found = match node.depth_quad() {
+            DepthQuad::Inner(_, inner) if self.shape.window_width() <= quarter => {
+                match inner.as_ref().children() {
+                    DepthQuad::Inner(_, grandchildren) => self.find_squares(grandchildren, quarter),
+                    DepthQuad::Leaf(_) => self.find_small(node),
+                }
+            }
+            _ => self.find_small(node),
+        };
+        let found = Rc::new(found);
+        self.memo.insert(node.clone(), found.clone());
+        found
+    }
+    /// `quarter` is the half width of the squares, which is also how far apart their centers are
+    fn find_squares(&mut self, grandchildren: Quad<&Quad<Node>>, quarter: i64) -> Vec<Pos> {
+        let Quad { nw, ne, sw, se } = grandchildren;
+        let grid = [
+            [&nw.nw, &nw.ne, &ne.nw, &ne.ne],
+            [&nw.sw, &nw.se, &ne.sw, &ne.se],
+            [&sw.nw, &sw.ne, &se.nw, &se.ne],
+            [&sw.sw, &sw.se, &se.sw, &se.se],
+        ];
+        let centers = [-quarter, 0, quarter];
+        // a match can be in more than 1 square
+        let mut found = HashSet::new();
+        for (y, center_y) in centers.into_iter().enumerate() {
+            for (x, center_x) in centers.into_iter().enumerate() {
+                let square = Node::new(
+                    grid[y][x].clone(),
+                    grid[y][x + 1].clone(),
+                    grid[y + 1][x].clone(),
+                    grid[y + 1][x + 1].clone(),
+                );
+                let center = Pos::new(center_x, center_y);
+                found.extend(self.find(&square).iter().map(|&pos| pos + center));
+            }
+        }
+        found.into_iter().collect()
+    }
+    /// tries every position that puts the first cell of the shape on a live cell
+    fn find_small(&self, node: &Node) -> Vec<Pos> {
+        let half_width = node.half_width();
+        let bounds = Rect::symetric_min_max(-half_width, half_width - 1);
+        let first = self.shape.cells[0];
+        let mut found = Vec::new();
+        for run in node.runs() {
+            for x in 0..run.len {
+                let pos = run.pos + Pos::new(x, 0) - first;
+                let window = Rect::min_max(pos - Pos::new(1, 1), pos + self.shape.size);
+                let mut inside = window;
+                inside.intersection(bounds);
+                if inside == window
+                    && node.clip(window).population() == self.shape.population
+                    && self.shape.cells.iter().all(|&cell| node.get(pos + cell))
+                {
+                    found.push(pos);
+                }
+            }
+        }
+        found
+    }
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use super::Match;
+    use crate::{test_node, Node, Pos, Transform};
+
+    fn place(node: &Node, pattern: &Node, pos: Pos) -> Node {
+        node.or(&pattern.offset(pos))
+    }
+    fn block() -> Node {
+        test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ........oo......
+            ........oo......
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            "}
+    }
+
+    #[test]
+    fn orientations() {
+        let glider = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        let universe = place(&glider, &glider.rotate_180(), Pos::new(100, -50));
+        let universe = place(
+            &universe,
+            &glider.transform(Transform::FlipDiagonal),
+            Pos::new(-7, 3),
+        );
+        assert_eq!(
+            universe.find(&glider, &[Transform::Identity]),
+            [Match {
+                pos: Pos::new(0, 0),
+                transform: Transform::Identity
+            }]
+        );
+        let found = universe.find(&glider, &Transform::ALL);
+        assert_eq!(found.len(), 3);
+        // rotating is around the center of the node, so the cells are at -3..0
+        assert_eq!(found[0].pos, Pos::new(97, -53));
+        assert_eq!(
+            universe.find(&glider, &Transform::ALL[..4]).len(),
+            2,
+            "a reflected glider isn't a rotated glider in the same phase"
+        );
+        // swapping x and y
+        let ell = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ........ooo.....
+            ........o.......
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        assert_eq!(
+            ell.transform(Transform::FlipDiagonal),
+            test_node! {"
+                ................
+                ................
+                ................
+                ................
+                ................
+                ................
+                ................
+                ................
+                ........oo......
+                ........o.......
+                ........o.......
+                ................
+                ................
+                ................
+                ................
+                ................
+                "}
+        );
+    }
+
+    #[test]
+    fn margins() {
+        // a block touching a cell, a block 1 cell away from another cell, and a 3x3 square
+        let universe = block().set(Pos::new(2, 2), true);
+        let universe = place(&universe, &block(), Pos::new(10, 0));
+        let universe = universe.set(Pos::new(13, 0), true);
+        let square = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ........ooo.....
+            ........ooo.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        let universe = place(&universe, &square, Pos::new(0, 20));
+        let found = universe.find(&block(), &Transform::ALL);
+        assert_eq!(
+            found,
+            [Match {
+                pos: Pos::new(10, 0),
+                transform: Transform::Identity
+            }]
+        );
+        assert_eq!(universe.find(&Node::empty(0), &Transform::ALL), []);
+    }
+
+    #[test]
+    fn repeated() {
+        // 64x64 blocks, every 16 cells, with one missing
+        let mut blocks = block();
+        for _ in 0..6 {
+            blocks = Node::new(blocks.clone(), blocks.clone(), blocks.clone(), blocks);
+        }
+        let blocks = blocks.set(Pos::new(8, 8), false);
+        let found = blocks.find(&block(), &[Transform::Identity]);
+        assert_eq!(found.len(), 64 * 64 - 1);
+        assert_eq!(found[0].pos, Pos::new(-504, -504));
+        assert!(!found.iter().any(|m| m.pos == Pos::new(8, 8)));
+        assert!(found
+            .iter()
+            .all(|m| m.pos.x.rem_euclid(16) == 8 && m.pos.y.rem_euclid(16) == 8));
+        // at the edges of the largest node
+        // with room for the dead cells around them, since the largest node can't be expanded
+        let far = (1 << 62) - 3;
+        let corners = [
+            (0, 0),
+            (1, 0),
+            (0, 1),
+            (1, 1),
+            (far, far),
+            (far + 1, far),
+            (far, far + 1),
+            (far + 1, far + 1),
+            (-far, 0),
+            (1 - far, 0),
+            (-far, 1),
+            (1 - far, 1),
+        ]
+        .into_iter()
+        .fold(Node::empty(0), |node, (x, y)| {
+            node.set(Pos::new(x, y), true)
+        });
+        assert_eq!(corners.depth(), Node::MAX_DEPTH);
+        assert_eq!(corners.find(&block(), &Transform::ALL).len(), 3);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::pos::Pos;
-use crate::{Block, DepthQuad, Node, Quadrant};
+use crate::{Block, DepthQuad, MultiNode, Node, Quadrant};
 
 impl Node {
     pub fn get(&self, pos: Pos) -> bool {
@@ -54,6 +54,26 @@ impl Node {
         }
     }
 }
+impl MultiNode {
+    pub fn get(&self, pos: Pos) -> u8 {
+        self.planes()
+            .iter()
+            .enumerate()
+            .fold(0, |state, (i, plane)| state | u8::from(plane.get(pos)) << i)
+    }
+    pub fn set(&self, pos: Pos, state: u8) -> Self {
+        let len = self
+            .planes()
+            .len()
+            .max((u8::BITS - state.leading_zeros()) as usize);
+        MultiNode::new(
+            (0..len)
+                .map(|i| self.plane(i).set(pos, state & (1 << i) != 0))
+                .collect(),
+        )
+    }
+}
+
 fn block_pos_to_mask(pos: Pos) -> u64 {
     // pos is in [-4,3]x[-4,3], so linearized is [-36,27]
     // but bit order is reversed, so 27 - linearized
@@ -0,0 +1,328 @@
+//! renders an area of a node into an image, where each pixel is a square of cells, and writes it as a PNG or PPM
+//!
+//! unlike `reduce_by` the area doesn't need to line up with the node, and zoomed out pixels can be shaded by how many cells are alive
+
+use std::io::{Error as IoError, ErrorKind, Write};
+
+use png::{BitDepth, ColorType, EncodingError};
+
+use crate::{Block, DepthQuad, Node, Population, Pos, Quad, Rect};
+
+const ALIVE: [u8; 4] = [255, 255, 255, 255];
+const DEAD: [u8; 4] = [0, 0, 0, 255];
+
+/// how a pixel with more than 1 cell in it is colored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Shading {
+    /// alive if any of its cells are alive, which is what the GUI shows when zoomed out
+    Any,
+    /// gray by how many of its cells are alive, any live cells make it at least slightly lighter than dead
+    Density,
+}
+
+/// RGBA pixels in rows from north to south
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+impl Image {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    /// 4 bytes per pixel
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+    pub fn get(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = 4 * (y * self.width + x);
+        self.pixels[i..i + 4].try_into().expect("4 bytes")
+    }
+    pub fn write_png(&self, write: impl Write) -> Result<(), IoError> {
+        let size = |len: usize| {
+            u32::try_from(len).map_err(|_| IoError::new(ErrorKind::InvalidInput, "image too large"))
+        };
+        let mut encoder = png::Encoder::new(write, size(self.width)?, size(self.height)?);
+        encoder.set_color(ColorType::Rgba);
+        encoder.set_depth(BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(png_error)?;
+        writer.write_image_data(&self.pixels).map_err(png_error)?;
+        writer.finish().map_err(png_error)
+    }
+    /// binary PPM, which has no alpha
+    pub fn write_ppm(&self, mut write: impl Write) -> Result<(), IoError> {
+        write!(write, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let rgb: Vec<u8> = self
+            .pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| &pixel[..3])
+            .copied()
+            .collect();
+        write.write_all(&rgb)
+    }
+}
+pub(crate) fn png_error(err: EncodingError) -> IoError {
+    match err {
+        EncodingError::IoError(err) => err,
+        err => IoError::new(ErrorKind::InvalidInput, err),
+    }
+}
+
+impl Node {
+    /// renders `rect` with each pixel covering `2^zoom_log2` by `2^zoom_log2` cells, starting at the north west corner of `rect`
+    ///
+    /// the pixels on the south and east edges can hang over `rect`, but only count the cells in it.
+    /// only the parts of the node that have cells in `rect` are looked at, and parts that fit in a pixel just use their population
+    pub fn render(&self, rect: Rect, zoom_log2: u8, shading: Shading) -> Image {
+        self.render_with_background(rect, zoom_log2, shading, false)
+    }
+    /// like `render`, but the cells that are set are the cells that differ from `background`, see `Node::step_with_background`
+    pub fn render_with_background(
+        &self,
+        rect: Rect,
+        zoom_log2: u8,
+        shading: Shading,
+        background: bool,
+    ) -> Image {
+        assert!(zoom_log2 < 64, "zoom is too large");
+        let size = |min: i64, max: i64| {
+            let len = (i128::from(max) - i128::from(min) + 1).max(0);
+            usize::try_from((len + (1 << zoom_log2) - 1) >> zoom_log2)
+                .expect("image fits in memory")
+        };
+        let width = size(rect.west(), rect.east());
+        let height = size(rect.north(), rect.south());
+        let mut raster = Raster {
+            rect,
+            zoom_log2,
+            width,
+            counts: vec![0; width.checked_mul(height).expect("image fits in memory")],
+        };
+        if width > 0 && height > 0 {
+            let half_width = self.half_width();
+            raster.add_node(self, Pos::new(-half_width, -half_width));
+        }
+        if background {
+            for i in 0..raster.counts.len() {
+                raster.counts[i] = raster.cells_in_pixel(i) - raster.counts[i];
+            }
+        }
+        let cells = 1_u128 << (2 * u32::from(zoom_log2));
+        let pixels = raster
+            .counts
+            .iter()
+            .flat_map(|&count| match shading {
+                _ if count == 0 => DEAD,
+                Shading::Any => ALIVE,
+                Shading::Density => {
+                    // rounded up so that a single live cell can still be seen
+                    let gray = (u128::from(count) * 255).div_ceil(cells).min(255);
+                    let gray = u8::try_from(gray).expect("at most 255");
+                    [gray, gray, gray, 255]
+                }
+            })
+            .collect();
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// how many live cells are in each pixel
+struct Raster {
+    rect: Rect,
+    zoom_log2: u8,
+    width: usize,
+    counts: Vec<u64>,
+}
+impl Raster {
+    /// the pixel offset of `pos` along 1 side of the image
+    fn pixel_side(&self, pos: i64, min: i64) -> usize {
+        let offset = (i128::from(pos) - i128::from(min)) >> self.zoom_log2;
+        usize::try_from(offset).expect("pos is in the image")
+    }
+    fn pixel(&self, pos: Pos) -> usize {
+        self.pixel_side(pos.y, self.rect.north()) * self.width
+            + self.pixel_side(pos.x, self.rect.west())
+    }
+    /// how many cells of `rect` are in the pixel at index `i`, saturating like the counts
+    fn cells_in_pixel(&self, i: usize) -> u64 {
+        let side = |pixel: usize, min: i64, max: i64| {
+            let pixel = i128::try_from(pixel).expect("pixel fits in i128");
+            let start = i128::from(min) + (pixel << self.zoom_log2);
+            let end = i128::from(max).min(start + (1 << self.zoom_log2) - 1);
+            end - start + 1
+        };
+        let width = side(i % self.width, self.rect.west(), self.rect.east());
+        let height = side(i / self.width, self.rect.north(), self.rect.south());
+        u64::try_from(width.saturating_mul(height)).unwrap_or(u64::MAX)
+    }
+    fn add_node(&mut self, node: &Node, nw: Pos) {
+        if node.is_empty() {
+            return;
+        }
+        // written so the widest node doesn't overflow
+        let last = node.half_width() - 1 + node.half_width();
+        let se = nw + Pos::new(last, last);
+        let rect = self.rect;
+        if se.x < rect.west() || se.y < rect.north() || nw.x > rect.east() || nw.y > rect.south() {
+            return;
+        }
+        let inside = nw.x >= rect.west()
+            && nw.y >= rect.north()
+            && se.x <= rect.east()
+            && se.y <= rect.south();
+        if inside && self.pixel(nw) == self.pixel(se) {
+            let pixel = self.pixel(nw);
+            let count = &mut self.counts[pixel];
+            *count = count.saturating_add(node.population());
+            return;
+        }
+        let child_width = node.half_width();
+        let at = |x: i64, y: i64| Pos::new(nw.x + x * child_width, nw.y + y * child_width);
+        let offsets = Quad {
+            nw: at(0, 0),
+            ne: at(1, 0),
+            sw: at(0, 1),
+            se: at(1, 1),
+        };
+        match node.depth_quad() {
+            DepthQuad::Leaf(leaf) => {
+                for (pos, block) in offsets.into_iter().zip(leaf.iter()) {
+                    self.add_block(*block, pos);
+                }
+            }
+            DepthQuad::Inner(_, inner) => {
+                for (pos, child) in offsets.into_iter().zip(inner.iter()) {
+                    self.add_node(child, pos);
+                }
+            }
+        }
+    }
+    fn add_block(&mut self, block: Block, nw: Pos) {
+        let mut rows = block.to_rows();
+        while rows != 0 {
+            // the most significant bit is the north west cell
+            let i = rows.leading_zeros();
+            rows &= !(1 << (63 - i));
+            let pos = nw + Pos::new(i64::from(i % 8), i64::from(i / 8));
+            let rect = self.rect;
+            if pos.x >= rect.west()
+                && pos.y >= rect.north()
+                && pos.x <= rect.east()
+                && pos.y <= rect.south()
+            {
+                let pixel = self.pixel(pos);
+                self.counts[pixel] += 1;
+            }
+        }
+    }
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use super::{Shading, ALIVE, DEAD};
+    use crate::{test_node, Node, Pos, Rect};
+
+    fn glider() -> Node {
+        test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "}
+    }
+
+    #[test]
+    fn zoom_0() {
+        let rect = Rect::min_max(Pos::new(-1, 0), Pos::new(2, 2));
+        let image = glider().render(rect, 0, Shading::Any);
+        assert_eq!((image.width(), image.height()), (4, 3));
+        let rows: Vec<String> = (0..3)
+            .map(|y| {
+                (0..4)
+                    .map(|x| if image.get(x, y) == ALIVE { 'O' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(rows, ["..O.", "...O", ".OOO"]);
+        assert_eq!(glider().render(rect, 0, Shading::Density), image);
+    }
+
+    #[test]
+    fn zoomed_out() {
+        // 2x2 pixels over the glider, where the south and east pixels hang over the rect
+        let rect = Rect::min_max(Pos::new(0, 0), Pos::new(2, 2));
+        let any = glider().render(rect, 1, Shading::Any);
+        assert_eq!((any.width(), any.height()), (2, 2));
+        assert!([(0, 0), (1, 0), (0, 1), (1, 1)]
+            .into_iter()
+            .all(|(x, y)| any.get(x, y) == ALIVE));
+        let density = glider().render(rect, 1, Shading::Density);
+        let gray = |x, y| density.get(x, y)[0];
+        // 1, 1, 2 and 1 live cells out of 4
+        assert_eq!(
+            [gray(0, 0), gray(1, 0), gray(0, 1), gray(1, 1)],
+            [64, 64, 128, 64]
+        );
+        // with a live background the cells of the glider are dead, and the cells past the rect aren't counted
+        let inverted = glider().render_with_background(rect, 1, Shading::Density, true);
+        let gray = |x, y| inverted.get(x, y)[0];
+        assert_eq!(
+            [gray(0, 0), gray(1, 0), gray(0, 1), gray(1, 1)],
+            [192, 64, 0, 0]
+        );
+        // a pixel that covers most of the universe still finds a single cell
+        let far = Node::empty(0).set(Pos::new(1 << 40, -(1 << 40)), true);
+        let image = far.render(Rect::EVERYTHING, 62, Shading::Density);
+        assert_eq!((image.width(), image.height()), (4, 4));
+        assert_eq!(image.get(2, 1), [1, 1, 1, 255]);
+        assert_eq!(image.get(1, 1), DEAD);
+        assert_eq!(
+            Node::empty(0)
+                .render(Rect::NOTHING, 0, Shading::Any)
+                .pixels(),
+            &[]
+        );
+    }
+
+    #[test]
+    fn write() {
+        let rect = Rect::min_max(Pos::new(0, 0), Pos::new(2, 2));
+        let image = glider().render(rect, 0, Shading::Any);
+
+        let mut ppm = Vec::new();
+        image.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n3 3\n255\n"));
+        assert_eq!(ppm.len(), 11 + 3 * 9);
+        assert_eq!(&ppm[11..17], &[0, 0, 0, 255, 255, 255]);
+
+        let mut png = Vec::new();
+        image.write_png(&mut png).unwrap();
+        let mut reader = png::Decoder::new(&png[..]).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (3, 3));
+        assert_eq!(pixels, image.pixels());
+    }
+}
@@ -0,0 +1,557 @@
+//! the Life 1.05 and Life 1.06 formats
+//!
+//! Life 1.06 has the `x y` position of each live cell on its own line,
+//! and Life 1.05 has blocks of `.` and `*` rows that each start with a `#P x y` line for their north west cell
+//!
+//! positions are the same as in `Node::get` and `Node::set`
+
+use std::error::Error;
+use std::fmt::{Debug, Display};
+use std::io::{Error as IoError, Write};
+
+use super::blocks::{BlockSet, Run};
+use super::cells_format::write_rows;
+use super::mc_format::LineInfo;
+use crate::{Node, Pattern, Pos, Rule};
+
+/// positions have to fit in the largest node
+pub(crate) const MAX_COORD: i64 = 1 << (Node::MAX_WIDTH_LOG2 - 1);
+/// the most rows or columns of dead cells written in a `#P` block, larger gaps start a new block
+const MAX_GAP: i64 = 8;
+
+impl Node {
+    /// writes the live cells in order by row and then by column
+    pub fn write_life106(&self, mut write: impl Write) -> Result<(), IoError> {
+        writeln!(write, "#Life 1.06")?;
+        for run in self.runs() {
+            for x in run.pos.x..run.pos.x + run.len {
+                writeln!(write, "{x} {}", run.pos.y)?;
+            }
+        }
+        Ok(())
+    }
+    pub fn write_life106_to_string(&self) -> String {
+        let mut out = Vec::new();
+        self.write_life106(&mut out).expect("valid bytes");
+        String::from_utf8(out).expect("valid string")
+    }
+    /// other `#` lines than the header are ignored
+    pub fn read_life106(bytes: &[u8]) -> Result<Self, LifeError<&[u8]>> {
+        let mut cells = BlockSet::default();
+        for (at, line) in lines(bytes) {
+            if line.starts_with(b"#") {
+                check_version(bytes, at, line, b"1.06")?;
+                continue;
+            }
+            let mut tokens = tokens(at, line);
+            let Some(x) = tokens.next() else {
+                continue;
+            };
+            let pos = read_pos(bytes, x, tokens.next(), at + line.len())?;
+            if let Some((extra_at, _)) = tokens.next() {
+                return fail_at(bytes, extra_at, LifeErrorHint::InvalidCoordinate);
+            }
+            cells.set(pos);
+        }
+        Ok(cells.into_node())
+    }
+
+    /// writes the pattern with a `#N` line for Life, see `Pattern::write_life105` to write other metadata
+    pub fn write_life105(&self, write: impl Write) -> Result<(), IoError> {
+        Pattern::new(self.clone())
+            .with_rule(Rule::LIFE)
+            .write_life105(write)
+    }
+    pub fn write_life105_to_string(&self) -> String {
+        let mut out = Vec::new();
+        self.write_life105(&mut out).expect("valid bytes");
+        String::from_utf8(out).expect("valid string")
+    }
+    /// ignores any metadata, see `Pattern::read_life105` to also read it
+    pub fn read_life105(bytes: &[u8]) -> Result<Self, LifeError<&[u8]>> {
+        Pattern::<Node>::read_life105(bytes).map(|pattern| pattern.node)
+    }
+}
+impl Pattern<Node> {
+    /// writes the comments in `#D` lines and the rule in a `#N` line for Life or a `#R` line for other rules,
+    /// the format has nowhere to put the name, author or generation
+    ///
+    /// cells that are far apart are written in separate `#P` blocks, so sparse patterns stay small
+    pub fn write_life105(&self, mut write: impl Write) -> Result<(), IoError> {
+        writeln!(write, "#Life 1.05")?;
+        for line in self.comments.iter().flat_map(|comment| comment.split('\n')) {
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                writeln!(write, "#D")?;
+            } else {
+                writeln!(write, "#D {line}")?;
+            }
+        }
+        match &self.rule {
+            Some(Ok(rule)) if *rule == Rule::LIFE => writeln!(write, "#N")?,
+            Some(Ok(rule)) => writeln!(write, "#R {rule}")?,
+            Some(Err(rule)) => writeln!(write, "#R {rule}")?,
+            None => {}
+        }
+        for (west, runs) in p_blocks(&self.node.runs()) {
+            writeln!(write, "#P {west} {}", runs[0].pos.y)?;
+            write_rows(&mut write, &runs, west, b'*')?;
+        }
+        Ok(())
+    }
+    /// also reads the comments from `#D` lines and the rule from `#N` or `#R` lines, other `#` lines are ignored
+    ///
+    /// rows before the first `#P` line start at the origin
+    pub fn read_life105(bytes: &[u8]) -> Result<Self, LifeError<&[u8]>> {
+        let mut pattern = Pattern::new(());
+        let mut cells = BlockSet::default();
+        let mut origin = Pos::new(0, 0);
+        let mut row = 0;
+        for (at, mut line) in lines(bytes) {
+            while let [rest @ .., b' ' | b'\t'] = line {
+                line = rest;
+            }
+            if let Some(tag) = line.strip_prefix(b"#") {
+                let text_at = at + 2;
+                match tag.first() {
+                    Some(b'D') => {
+                        let text = &tag[1..];
+                        let text = text.strip_prefix(b" ").unwrap_or(text);
+                        match std::str::from_utf8(text) {
+                            Ok(text) => pattern.comments.push(text.to_owned()),
+                            Err(err) => {
+                                return fail_at(
+                                    bytes,
+                                    at + line.len() - text.len() + err.valid_up_to(),
+                                    LifeErrorHint::InvalidChar,
+                                )
+                            }
+                        }
+                    }
+                    Some(b'N') => pattern.rule = Some(Ok(Rule::LIFE)),
+                    Some(b'R') => match std::str::from_utf8(&tag[1..]) {
+                        // rules that can't be parsed are kept as text, see `Pattern::rule`
+                        Ok(rule) => {
+                            let rule = rule.trim();
+                            pattern.rule = Some(rule.parse().map_err(|_| rule.to_owned()));
+                        }
+                        Err(err) => {
+                            return fail_at(
+                                bytes,
+                                text_at + err.valid_up_to(),
+                                LifeErrorHint::InvalidChar,
+                            )
+                        }
+                    },
+                    Some(b'P') => {
+                        let mut tokens = tokens(text_at, &tag[1..]);
+                        let Some(x) = tokens.next() else {
+                            return fail_at(bytes, text_at, LifeErrorHint::InvalidCoordinate);
+                        };
+                        origin = read_pos(bytes, x, tokens.next(), at + line.len())?;
+                        if let Some((extra_at, _)) = tokens.next() {
+                            return fail_at(bytes, extra_at, LifeErrorHint::InvalidCoordinate);
+                        }
+                        row = 0;
+                    }
+                    _ => check_version(bytes, at, line, b"1.05")?,
+                }
+                continue;
+            }
+            let mut run_start = None;
+            // the extra dead cell ends a run at the end of the line
+            for ((x, i), &b) in (0..).zip(at..).zip(line.iter().chain(b".")) {
+                match b {
+                    b'*' | b'O' | b'o' => {
+                        run_start.get_or_insert((x, i));
+                    }
+                    b'.' => {
+                        if let Some((start, start_at)) = run_start.take() {
+                            // checking both ends checks every cell in the run
+                            let west = offset_pos(origin, start, row);
+                            let east = offset_pos(origin, x - 1, row);
+                            let Some((west, _)) = west.zip(east) else {
+                                return fail_at(bytes, start_at, LifeErrorHint::CoordinateTooLarge);
+                            };
+                            cells.set_row(west, x - start);
+                        }
+                    }
+                    _ => return fail_at(bytes, i, LifeErrorHint::InvalidChar),
+                }
+            }
+            row += 1;
+        }
+        Ok(pattern.map(|()| cells.into_node()))
+    }
+}
+
+/// splits the runs into the `#P` blocks to write, along with the west most column of each block
+fn p_blocks(runs: &[Run]) -> Vec<(i64, Vec<Run>)> {
+    let mut blocks = Vec::new();
+    let mut band_start = 0;
+    for i in 1..=runs.len() {
+        if i < runs.len() && runs[i].pos.y - runs[i - 1].pos.y <= MAX_GAP + 1 {
+            continue;
+        }
+        // rows that are close enough together, which are then split into columns that are close enough together
+        let band = &runs[band_start..i];
+        band_start = i;
+        let mut columns: Vec<_> = band
+            .iter()
+            .map(|run| (run.pos.x, run.pos.x + run.len))
+            .collect();
+        columns.sort_unstable();
+        let mut spans: Vec<(i64, i64)> = Vec::new();
+        for (start, end) in columns {
+            match spans.last_mut() {
+                Some(span) if start - span.1 <= MAX_GAP => span.1 = span.1.max(end),
+                _ => spans.push((start, end)),
+            }
+        }
+        let mut span_runs = vec![Vec::new(); spans.len()];
+        for run in band {
+            let span = spans.partition_point(|span| span.1 <= run.pos.x);
+            span_runs[span].push(*run);
+        }
+        blocks.extend(spans.iter().map(|span| span.0).zip(span_runs));
+    }
+    blocks
+}
+
+/// each line and where it starts, without the line terminator
+fn lines(bytes: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    let mut at = 0;
+    bytes.split_inclusive(|&b| b == b'\n').map(move |line| {
+        let line_at = at;
+        at += line.len();
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        (line_at, line.strip_suffix(b"\r").unwrap_or(line))
+    })
+}
+/// each word in `line` and where it starts, `line` starts at `at`
+fn tokens(at: usize, line: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        while let Some(b' ' | b'\t') = line.get(start) {
+            start += 1;
+        }
+        if start >= line.len() {
+            return None;
+        }
+        let len = line[start..]
+            .iter()
+            .position(|b| matches!(b, b' ' | b'\t'))
+            .unwrap_or(line.len() - start);
+        let token = (at + start, &line[start..start + len]);
+        start += len;
+        Some(token)
+    })
+}
+/// `end` is where the line ends, in case `y` is missing
+fn read_pos<'src>(
+    src: &'src [u8],
+    x: (usize, &[u8]),
+    y: Option<(usize, &[u8])>,
+    end: usize,
+) -> LifeResult<'src, Pos> {
+    let Some(y) = y else {
+        return fail_at(src, end, LifeErrorHint::InvalidCoordinate);
+    };
+    Ok(Pos::new(read_coord(src, x)?, read_coord(src, y)?))
+}
+fn read_coord<'src>(src: &'src [u8], (at, token): (usize, &[u8])) -> LifeResult<'src, i64> {
+    let digits = token.strip_prefix(b"-").unwrap_or(token);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return fail_at(src, at, LifeErrorHint::InvalidCoordinate);
+    }
+    let coord = std::str::from_utf8(token)
+        .ok()
+        .and_then(|token| token.parse().ok());
+    match coord {
+        Some(coord) if (-MAX_COORD..MAX_COORD).contains(&coord) => Ok(coord),
+        _ => fail_at(src, at, LifeErrorHint::CoordinateTooLarge),
+    }
+}
+/// `None` if the cell doesn't fit in the largest node
+pub(crate) fn offset_pos(origin: Pos, x: i64, y: i64) -> Option<Pos> {
+    let range = -MAX_COORD..MAX_COORD;
+    let x = origin.x.checked_add(x).filter(|x| range.contains(x))?;
+    let y = origin.y.checked_add(y).filter(|y| range.contains(y))?;
+    Some(Pos::new(x, y))
+}
+/// fails if `line` is a `#Life` header for a different version
+fn check_version<'src>(
+    src: &'src [u8],
+    at: usize,
+    line: &[u8],
+    version: &[u8],
+) -> LifeResult<'src, ()> {
+    let Some(rest) = line.strip_prefix(b"#Life") else {
+        return Ok(());
+    };
+    match tokens(at + 5, rest).next() {
+        Some((_, found)) if found == version => Ok(()),
+        Some((found_at, _)) => fail_at(src, found_at, LifeErrorHint::WrongVersion),
+        None => fail_at(src, at + line.len(), LifeErrorHint::WrongVersion),
+    }
+}
+
+// parsing
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LifeErrorKind {
+    InvalidHeader,
+    InvalidContent,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LifeErrorHint {
+    WrongVersion,
+    InvalidCoordinate,
+    CoordinateTooLarge,
+    InvalidChar,
+}
+
+#[derive(Clone, Copy)]
+struct LifeErrorData<S> {
+    line: LineInfo<S>,
+    hint: LifeErrorHint,
+}
+#[derive(Clone)]
+pub struct LifeError<S>(Box<LifeErrorData<S>>);
+impl<S> LifeError<S> {
+    fn new(line: LineInfo<S>, hint: LifeErrorHint) -> Self {
+        Self(Box::new(LifeErrorData { line, hint }))
+    }
+    pub fn kind(&self) -> LifeErrorKind {
+        match self.0.hint {
+            LifeErrorHint::WrongVersion => LifeErrorKind::InvalidHeader,
+            _ => LifeErrorKind::InvalidContent,
+        }
+    }
+    pub fn line_src(&self) -> &S {
+        &self.0.line.line_src
+    }
+    /// 0 based line index
+    pub fn line(&self) -> usize {
+        self.0.line.line
+    }
+    /// 0 based column index
+    pub fn column(&self) -> usize {
+        self.0.line.column
+    }
+    fn hint_code(&self) -> &LifeErrorHint {
+        &self.0.hint
+    }
+    pub fn hint(&self) -> &'static str {
+        match self.hint_code() {
+            LifeErrorHint::WrongVersion => {
+                "The header is for a different version of the Life format"
+            }
+            LifeErrorHint::InvalidCoordinate => "Positions must be two integers, such as -3 5",
+            LifeErrorHint::CoordinateTooLarge => {
+                "Positions must be at least -2^62 and less than 2^62"
+            }
+            LifeErrorHint::InvalidChar => "Cells are '.' for dead and '*' for alive",
+        }
+    }
+}
+impl LifeError<&[u8]> {
+    pub fn into_owned(self) -> LifeError<Vec<u8>> {
+        LifeError(Box::new(LifeErrorData {
+            line: self.0.line.into_owned(),
+            hint: self.0.hint,
+        }))
+    }
+}
+impl<S: AsRef<[u8]>> Debug for LifeError<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LifeError")
+            .field("line", &self.line())
+            .field("column", &self.column())
+            .field(
+                "line_src",
+                &String::from_utf8_lossy(self.line_src().as_ref()),
+            )
+            .field("hint", &self.hint_code())
+            .finish()
+    }
+}
+impl<S: AsRef<[u8]>> Display for LifeError<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let line = self.line() + 1;
+        let line_src = String::from_utf8_lossy(self.line_src().as_ref());
+        let column = self.column() + 1;
+        let mark = "^";
+        let hint = self.hint();
+        writeln!(
+            f,
+            "Failed to parse Life pattern on line {line}:\n{line_src}\n{mark:>column$}\n{hint}\n"
+        )
+    }
+}
+impl<S: AsRef<[u8]>> Error for LifeError<S> {}
+
+type LifeResult<'src, T> = Result<T, LifeError<&'src [u8]>>;
+
+fn fail_at<T>(src: &[u8], at: usize, hint: LifeErrorHint) -> LifeResult<'_, T> {
+    Err(LifeError::new(LineInfo::from_src_offset(src, at), hint))
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use unindent::unindent;
+
+    use super::LifeErrorKind::{InvalidContent, InvalidHeader};
+    use super::MAX_COORD;
+    use crate::{test_node, Node, Pattern, Population, Pos, Rule};
+
+    #[test]
+    fn life106() {
+        let glider = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        let fmt = unindent(
+            "
+            #Life 1.06
+            1 -1
+            2 0
+            0 1
+            1 1
+            2 1
+            ",
+        );
+        assert_eq!(glider.write_life106_to_string(), fmt);
+        assert_eq!(Node::read_life106(fmt.as_bytes()).unwrap(), glider);
+        // any order, extra spaces, and no header
+        let loose = "2 1\r\n\n  0\t1 \n1 1\n2 0\n1 -1\n1 -1\n";
+        assert_eq!(Node::read_life106(loose.as_bytes()).unwrap(), glider);
+    }
+
+    #[test]
+    fn huge_coordinates() {
+        let positions = [
+            (-MAX_COORD, -MAX_COORD),
+            (MAX_COORD - 1, MAX_COORD - 1),
+            (-MAX_COORD, MAX_COORD - 1),
+            (-1, 0),
+            (1 << 40, -(1 << 50)),
+        ];
+        let node = positions.iter().fold(Node::empty(0), |node, &(x, y)| {
+            node.set(Pos::new(x, y), true)
+        });
+        assert_eq!(
+            Node::read_life106(node.write_life106_to_string().as_bytes()).unwrap(),
+            node
+        );
+        assert_eq!(
+            Node::read_life105(node.write_life105_to_string().as_bytes()).unwrap(),
+            node
+        );
+        for (x, y) in positions {
+            let read = Node::read_life106(format!("{x} {y}").as_bytes()).unwrap();
+            assert!(read.get(Pos::new(x, y)));
+            assert_eq!(read.population(), 1);
+        }
+    }
+
+    #[test]
+    fn life105() {
+        let node = [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-2, 0),
+            (9, 0),
+            (200, 3),
+            (-3, 50),
+        ]
+        .into_iter()
+        .fold(Node::empty(0), |node, (x, y)| {
+            node.set(Pos::new(x, y), true)
+        });
+        let fmt = unindent(
+            "
+            #Life 1.05
+            #D a blinker
+            #D
+            #D and some cells
+            #N
+            #P -2 -1
+            .***
+            *..........*
+            #P 200 3
+            *
+            #P -3 50
+            *
+            ",
+        );
+        let read = Pattern::<Node>::read_life105(fmt.as_bytes()).unwrap();
+        assert_eq!(read.node, node);
+        assert_eq!(read.rule, Some(Ok(Rule::LIFE)));
+        assert_eq!(read.comments, ["a blinker", "", "and some cells"]);
+        let mut out = Vec::new();
+        read.write_life105(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), fmt);
+
+        let highlife = "#R 23/36\n***\n#P 10 -5\n.\n*\n";
+        let read = Pattern::<Node>::read_life105(highlife.as_bytes()).unwrap();
+        let expected = [(0, 0), (1, 0), (2, 0), (10, -4)]
+            .into_iter()
+            .fold(Node::empty(0), |node, (x, y)| {
+                node.set(Pos::new(x, y), true)
+            });
+        assert_eq!(read.node, expected);
+        assert_eq!(read.rule, Some(Ok("B36/S23".parse().unwrap())));
+        let unknown = Pattern::<Node>::read_life105(b"#R 23/9\n*\n").unwrap();
+        assert_eq!(unknown.rule, Some(Err("23/9".to_owned())));
+        assert_eq!(unknown.node, Node::empty(0).set(Pos::new(0, 0), true));
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |s: &str, life105: bool| {
+            let err = if life105 {
+                Node::read_life105(s.as_bytes()).unwrap_err()
+            } else {
+                Node::read_life106(s.as_bytes()).unwrap_err()
+            };
+            (err.kind(), err.line(), err.column())
+        };
+        assert_eq!(error("#Life 1.05\n", false), (InvalidHeader, 0, 6));
+        assert_eq!(error("#Life 1.06\n", true), (InvalidHeader, 0, 6));
+        assert_eq!(error("1 2\n3\n", false), (InvalidContent, 1, 1));
+        assert_eq!(error("1 2 3\n", false), (InvalidContent, 0, 4));
+        assert_eq!(error("1 x\n", false), (InvalidContent, 0, 2));
+        assert_eq!(
+            error(&format!("0 {MAX_COORD}\n"), false),
+            (InvalidContent, 0, 2)
+        );
+        assert_eq!(
+            error("0 99999999999999999999\n", false),
+            (InvalidContent, 0, 2)
+        );
+        assert_eq!(error("#P 1\n", true), (InvalidContent, 0, 4));
+        assert_eq!(error("#P 0 0\n.*x\n", true), (InvalidContent, 1, 2));
+        assert_eq!(
+            error(&format!("#P {} 0\n..**\n", MAX_COORD - 3), true),
+            (InvalidContent, 1, 2)
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! steps block rules, using the same memoised stepping as other rules
+
+use std::num::NonZeroU64;
+
+use crate::{MargolusRule, Node, Rule};
+
+impl Node {
+    /// steps a node that is at `generation` forwards by `steps` generations,
+    /// only the parity of `generation` is used, which decides where the blocks start
+    ///
+    /// rules where empty blocks become full such as `MargolusRule::CRITTERS` are handled the same as B0 rules,
+    /// so the cells that are set on odd generations are the cells that are dead instead
+    pub fn step_margolus(&self, rule: &MargolusRule, generation: u64, steps: u64) -> Node {
+        step_margolus(self, rule, false, generation & 1 == 1, steps)
+    }
+    /// steps a node that is at `generation` backwards by `steps` generations, undoing `step_margolus`
+    ///
+    /// panics if the rule isn't reversible
+    pub fn step_margolus_back(&self, rule: &MargolusRule, generation: u64, steps: u64) -> Node {
+        // the blocks of the previous generation
+        step_margolus(self, rule, true, generation & 1 == 0, steps)
+    }
+}
+
+fn step_margolus(node: &Node, rule: &MargolusRule, backwards: bool, odd: bool, steps: u64) -> Node {
+    match NonZeroU64::new(steps) {
+        None => node.clone(),
+        Some(steps) => {
+            let rule = Rule::margolus(*rule, backwards, false);
+            node.step_buffered(&rule, steps, odd).0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{test_node, MargolusRule, Node, Population, Pos};
+
+    fn assert_same(a: &Node, b: &Node) {
+        let depth = a.depth().max(b.depth());
+        assert_eq!(a.center_at_depth(depth), b.center_at_depth(depth));
+    }
+    /// a deterministic soup inside of a 20x20 square
+    fn soup(seed: u64) -> Node {
+        let mut state = seed;
+        let mut node = Node::empty(0);
+        for y in -10..10 {
+            for x in -10..10 {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                if state >> 63 == 1 {
+                    node = node.set(Pos::new(x, y), true);
+                }
+            }
+        }
+        node
+    }
+
+    #[test]
+    fn billiard_ball() {
+        let rule = MargolusRule::BILLIARD_BALL_MACHINE;
+        // a ball moves diagonally, 1 cell each generation
+        let ball = Node::empty(0).set(Pos::new(0, 0), true);
+        for steps in [1, 2, 7, 100, 1000] {
+            let at = i64::try_from(steps).unwrap();
+            assert_same(
+                &ball.step_margolus(&rule, 0, steps),
+                &Node::empty(0).set(Pos::new(at, at), true),
+            );
+        }
+        // the blocks start at odd coordinates on odd generations
+        assert_same(
+            &ball.step_margolus(&rule, 1, 1),
+            &Node::empty(0).set(Pos::new(-1, -1), true),
+        );
+        // 2 balls collide and bounce back the way they came
+        let balls = Node::empty(0)
+            .set(Pos::new(0, 0), true)
+            .set(Pos::new(3, 0), true);
+        assert_same(
+            &balls.step_margolus(&rule, 0, 8),
+            &Node::empty(0)
+                .set(Pos::new(-5, -5), true)
+                .set(Pos::new(8, -5), true),
+        );
+    }
+
+    #[test]
+    fn reverse() {
+        for rule in [
+            MargolusRule::BILLIARD_BALL_MACHINE,
+            MargolusRule::CRITTERS,
+            MargolusRule::TRON,
+        ] {
+            for (seed, generation, steps) in [(1, 0, 1), (2, 0, 50), (3, 1, 37), (4, 7, 64)] {
+                let start = soup(seed);
+                let stepped = start.step_margolus(&rule, generation, steps);
+                assert!(!stepped.is_empty());
+                let back = stepped.step_margolus_back(&rule, generation + steps, steps);
+                assert_same(&back, &start);
+            }
+        }
+    }
+
+    #[test]
+    fn critters() {
+        let rule = MargolusRule::CRITTERS;
+        // odd generations have a full background, so the cells that are set are dead
+        let cell = Node::empty(0).set(Pos::new(0, 0), true);
+        assert_same(&cell.step_margolus(&rule, 0, 1), &cell);
+        assert_same(
+            &cell.step_margolus(&rule, 0, 2),
+            &Node::empty(0).set(Pos::new(-1, -1), true),
+        );
+        let soup = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ........oo......
+            ................
+            ................
+            .............oo.
+            ..............o.
+            ................
+            ................
+            ................
+            "};
+        assert_same(
+            &soup.step_margolus(&rule, 0, 9),
+            &test_node! {"
+                ................
+                ................
+                ................
+                ................
+                ................
+                ................
+                ................
+                ................
+                ................
+                ........oo......
+                ................
+                .............oo.
+                ..............o.
+                ................
+                ................
+                ................
+                "},
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "only reversible rules can be stepped backwards")]
+    fn irreversible() {
+        let mut table = [15; 16];
+        table[0] = 0;
+        let rule = MargolusRule::new(table).unwrap();
+        Node::empty(0)
+            .set(Pos::new(0, 0), true)
+            .step_margolus_back(&rule, 1, 1);
+    }
+}
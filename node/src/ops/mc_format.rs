@@ -1,15 +1,21 @@
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display};
-use std::io::{Error as IoError, Write};
+use std::io::{BufRead, BufReader, Error as IoError, Read, Write};
 
 use either::Either;
+use num_bigint::BigUint;
 
-use crate::{Block, DepthQuad, Node, Population};
+use super::any_format::decompress;
+use crate::multi_node::{planes_from_states, states_from_planes};
+use crate::{Block, DepthQuad, MultiNode, Node, Pattern, Population, Pos, Quad, Rule};
 
 impl Node {
+    /// writes the pattern with a `#R B3/S23` line, see `Pattern::write_to` to write other metadata
     pub fn write_to(&self, write: impl Write) -> Result<(), IoError> {
-        McWriter::new(write).write(self)
+        Pattern::new(self.clone())
+            .with_rule(Rule::LIFE)
+            .write_to(write)
     }
     pub fn write_to_string(&self) -> String {
         String::from_utf8(self.write_to_bytes()).expect("valid string")
@@ -20,20 +26,92 @@ impl Node {
         out
     }
 
-    // pub fn read_from(
-    //     mut read: impl Read,
-    // ) -> Result<Result<Self, MacrocellError<Vec<u8>>>, IoError> {
-    //     // api setup such that far down the road this could avoid reading everything in up front
-    //     let mut buf = Vec::new();
-    //     read.read_to_end(&mut buf)?;
-    //     Ok(Node::read_from_bytes(&buf).map_err(|e| e.into_owned()))
-    // }
+    /// reads one line at a time, so only the nodes are kept in memory and not the whole file,
+    /// and decompresses it as it is read if it is gzipped
+    ///
+    /// ignores any metadata, see `Pattern::read_from` to also read it
+    pub fn read_from(read: impl Read) -> McStreamResult<Self> {
+        Node::read_from_buf(BufReader::new(read))
+    }
+    pub fn read_from_buf(read: impl BufRead) -> McStreamResult<Self> {
+        McStreamReader::<_, Either<Block, Node>>::new(decompress(read)?).read()
+    }
+    /// ignores any metadata, see `Pattern::read_from_bytes` to also read it
     pub fn read_from_bytes(bytes: &[u8]) -> Result<Self, MacrocellError<&[u8]>> {
-        McReader::new(bytes).read()
+        McReader::<Either<Block, Node>>::new(bytes).read()
     }
     // TODO could make error generic be &str, unsafe conversion is probably possible
     pub fn read_from_string(string: &str) -> Result<Self, MacrocellError<&[u8]>> {
-        McReader::new(string.as_bytes()).read()
+        McReader::<Either<Block, Node>>::new(string.as_bytes()).read()
+    }
+}
+impl MultiNode {
+    /// writes the multi-state form of macrocell, with `1 a b c d` lines for each 2x2 area of states
+    pub fn write_to(&self, write: impl Write) -> Result<(), IoError> {
+        Pattern::new(self.clone()).write_to(write)
+    }
+    pub fn write_to_string(&self) -> String {
+        String::from_utf8(self.write_to_bytes()).expect("valid string")
+    }
+    pub fn write_to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_to(&mut out).expect("valid bytes");
+        out
+    }
+
+    /// reads either the multi-state or two-state form of macrocell
+    pub fn read_from_bytes(bytes: &[u8]) -> Result<Self, MacrocellError<&[u8]>> {
+        McReader::<McMultiItem>::new(bytes).read()
+    }
+    /// reads one line at a time, like `Node::read_from`
+    pub fn read_from(read: impl Read) -> McStreamResult<Self> {
+        MultiNode::read_from_buf(BufReader::new(read))
+    }
+    pub fn read_from_buf(read: impl BufRead) -> McStreamResult<Self> {
+        McStreamReader::<_, McMultiItem>::new(decompress(read)?).read()
+    }
+    pub fn read_from_string(string: &str) -> Result<Self, MacrocellError<&[u8]>> {
+        McReader::<McMultiItem>::new(string.as_bytes()).read()
+    }
+}
+impl Pattern<Node> {
+    /// writes the metadata in `#R`, `#G`, `#N`, `#O` and `#C` lines before the nodes
+    pub fn write_to(&self, write: impl Write) -> Result<(), IoError> {
+        McWriter::new(write).write(self)
+    }
+    /// also reads the metadata from the `#R`, `#G`, `#N`, `#O` and `#C` lines, other `#` lines are ignored
+    pub fn read_from_bytes(bytes: &[u8]) -> Result<Self, MacrocellError<&[u8]>> {
+        McReader::<Either<Block, Node>>::new(bytes).read_pattern()
+    }
+    pub fn read_from_string(string: &str) -> Result<Self, MacrocellError<&[u8]>> {
+        McReader::<Either<Block, Node>>::new(string.as_bytes()).read_pattern()
+    }
+    /// reads one line at a time, like `Node::read_from`
+    pub fn read_from(read: impl Read) -> McStreamResult<Self> {
+        Pattern::<Node>::read_from_buf(BufReader::new(read))
+    }
+    pub fn read_from_buf(read: impl BufRead) -> McStreamResult<Self> {
+        McStreamReader::<_, Either<Block, Node>>::new(decompress(read)?).read_pattern()
+    }
+}
+impl Pattern<MultiNode> {
+    /// writes the multi-state form of macrocell, with the metadata before the nodes
+    pub fn write_to(&self, write: impl Write) -> Result<(), IoError> {
+        McMultiWriter::new(write).write(self)
+    }
+    /// reads either the multi-state or two-state form of macrocell, along with the metadata
+    pub fn read_from_bytes(bytes: &[u8]) -> Result<Self, MacrocellError<&[u8]>> {
+        McReader::<McMultiItem>::new(bytes).read_pattern()
+    }
+    pub fn read_from_string(string: &str) -> Result<Self, MacrocellError<&[u8]>> {
+        McReader::<McMultiItem>::new(string.as_bytes()).read_pattern()
+    }
+    /// reads one line at a time, like `Node::read_from`
+    pub fn read_from(read: impl Read) -> McStreamResult<Self> {
+        Pattern::<MultiNode>::read_from_buf(BufReader::new(read))
+    }
+    pub fn read_from_buf(read: impl BufRead) -> McStreamResult<Self> {
+        McStreamReader::<_, McMultiItem>::new(decompress(read)?).read_pattern()
     }
 }
 
@@ -54,14 +132,9 @@ impl<'n, W: Write> McWriter<'n, W> {
             last: 0,
         }
     }
-    fn write(mut self, node: &'n Node) -> Result<(), IoError> {
-        self.write_header()?;
-        self.write_node(node)
-    }
-    fn write_header(&mut self) -> Result<(), IoError> {
-        writeln!(self.write, "[M2] (metalife 1.0)")?;
-        writeln!(self.write, "#R B3/S23")?;
-        Ok(())
+    fn write(mut self, pattern: &'n Pattern<Node>) -> Result<(), IoError> {
+        write_header(&mut self.write, pattern)?;
+        self.write_node(&pattern.node)
     }
     fn write_node(&mut self, node: &'n Node) -> Result<(), IoError> {
         let size = node.width_log2();
@@ -130,6 +203,123 @@ impl<'n, W: Write> McWriter<'n, W> {
     }
 }
 
+struct McMultiWriter<W> {
+    write: W,
+    // children of a multi node are built on demand, so they can't be borrowed like `McWriter` does
+    nodes: HashMap<MultiNode, usize>,
+    /// areas smaller than a node, by their states in row-major order
+    cells: HashMap<Vec<u8>, usize>,
+    last: usize,
+}
+impl<W: Write> McMultiWriter<W> {
+    fn new(write: W) -> Self {
+        McMultiWriter {
+            write,
+            nodes: HashMap::new(),
+            cells: HashMap::new(),
+            last: 0,
+        }
+    }
+    fn write(mut self, pattern: &Pattern<MultiNode>) -> Result<(), IoError> {
+        write_header(&mut self.write, pattern)?;
+        self.write_node(&pattern.node)
+    }
+    fn write_node(&mut self, node: &MultiNode) -> Result<(), IoError> {
+        let size = node.width_log2();
+        let refs = match node.children() {
+            DepthQuad::Leaf(leaf) => leaf.map(|planes| {
+                let states = states_from_planes(&planes);
+                self.maybe_write_cells(&states.concat())
+            }),
+            DepthQuad::Inner(_depth, inner) => inner.map(|node| self.maybe_write_node(node)),
+        };
+        let nw = refs.nw?;
+        let ne = refs.ne?;
+        let sw = refs.sw?;
+        let se = refs.se?;
+        writeln!(self.write, "{size} {nw} {ne} {sw} {se}")
+    }
+    fn maybe_write_node(&mut self, node: MultiNode) -> Result<usize, IoError> {
+        match self.nodes.get(&node) {
+            Some(index) => Ok(*index),
+            None if node.is_empty() => Ok(0),
+            _ => {
+                self.write_node(&node)?;
+                self.last += 1;
+                self.nodes.insert(node, self.last);
+                Ok(self.last)
+            }
+        }
+    }
+    /// writes a square area of states, the size is `log2(width)`
+    fn maybe_write_cells(&mut self, states: &[u8]) -> Result<usize, IoError> {
+        match self.cells.get(states) {
+            Some(index) => Ok(*index),
+            None if states.iter().all(|&state| state == 0) => Ok(0),
+            _ => {
+                let width = cells_width(states);
+                let half = width / 2;
+                if width == 2 {
+                    let [nw, ne, sw, se] = states else {
+                        unreachable!("2x2 area")
+                    };
+                    writeln!(self.write, "1 {nw} {ne} {sw} {se}")?;
+                } else {
+                    let quadrant = |x0: usize, y0: usize| -> Vec<u8> {
+                        states
+                            .chunks(width)
+                            .skip(y0)
+                            .take(half)
+                            .flat_map(|row| &row[x0..x0 + half])
+                            .copied()
+                            .collect()
+                    };
+                    let nw = self.maybe_write_cells(&quadrant(0, 0))?;
+                    let ne = self.maybe_write_cells(&quadrant(half, 0))?;
+                    let sw = self.maybe_write_cells(&quadrant(0, half))?;
+                    let se = self.maybe_write_cells(&quadrant(half, half))?;
+                    let size = width.ilog2();
+                    writeln!(self.write, "{size} {nw} {ne} {sw} {se}")?;
+                }
+                self.last += 1;
+                self.cells.insert(states.to_vec(), self.last);
+                Ok(self.last)
+            }
+        }
+    }
+}
+
+/// the `[M2]` line and the metadata, which is the same for both forms of macrocell
+fn write_header<N>(write: &mut impl Write, pattern: &Pattern<N>) -> Result<(), IoError> {
+    writeln!(write, "[M2] (metalife 1.0)")?;
+    if let Some(rule) = pattern.rule_name() {
+        writeln!(write, "#R {rule}")?;
+    }
+    if pattern.generation != BigUint::default() {
+        writeln!(write, "#G {}", pattern.generation)?;
+    }
+    if let Some(name) = &pattern.name {
+        writeln!(write, "#N {name}")?;
+    }
+    if let Some(author) = &pattern.author {
+        writeln!(write, "#O {author}")?;
+    }
+    // comments with several lines are written as several comments
+    for line in pattern
+        .comments
+        .iter()
+        .flat_map(|comment| comment.split('\n'))
+    {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            writeln!(write, "#C")?;
+        } else {
+            writeln!(write, "#C {line}")?;
+        }
+    }
+    Ok(())
+}
+
 // parsing
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -143,6 +333,8 @@ enum MacrocellErrorHint {
     TooManyBlockRows,
     TooManyBlockBits,
     InvalidTwoStateDepth,
+    InvalidMultiStateDepth,
+    StateTooLarge,
     SizeTooLarge,
     InvalidForwardRef,
     InvalidRefDepth,
@@ -152,6 +344,7 @@ enum MacrocellErrorHint {
     InvalidNumberAfterNumber,
     InvalidEolAfterNumber,
     InvalidChar,
+    InvalidGeneration,
 }
 
 #[derive(Clone, Copy)]
@@ -195,6 +388,10 @@ impl<S> MacrocellError<S> {
             MacrocellErrorHint::InvalidTwoStateDepth => {
                 "Only handles two-state Macrocell files, use '.'s, '*'s, and '$' for 8x8 leaf nodes"
             }
+            MacrocellErrorHint::InvalidMultiStateDepth => {
+                "Multi-state nodes have a size of at least 1, which is a 2x2 area of states"
+            }
+            MacrocellErrorHint::StateTooLarge => "States must be less than 256",
             MacrocellErrorHint::SizeTooLarge => "Node is too large to be handled",
             MacrocellErrorHint::InvalidForwardRef => {
                 "Child nodes must be declared before parent nodes"
@@ -214,6 +411,9 @@ impl<S> MacrocellError<S> {
             }
             MacrocellErrorHint::InvalidEolAfterNumber => "Need exactly 4 child nodes, got too few",
             MacrocellErrorHint::InvalidChar => "Invalid character",
+            MacrocellErrorHint::InvalidGeneration => {
+                "The #G line must be a generation number, such as #G 1024"
+            }
         }
     }
 }
@@ -255,14 +455,15 @@ impl<S: AsRef<[u8]>> Display for MacrocellError<S> {
 }
 impl<S: AsRef<[u8]>> Error for MacrocellError<S> {}
 
+/// where an error is in the source, shared by the readers of each format
 #[derive(Clone, Copy, Debug)]
-struct LineInfo<S> {
-    line: usize,
-    column: usize,
-    line_src: S,
+pub(crate) struct LineInfo<S> {
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    pub(crate) line_src: S,
 }
 impl<'src> LineInfo<&'src [u8]> {
-    fn from_src_offset(src: &'src [u8], offset: usize) -> Self {
+    pub(crate) fn from_src_offset(src: &'src [u8], offset: usize) -> Self {
         let mut cr = false;
         let trim_eol = move |line: &'src [u8]| match line {
             [b'\n'] if cr => {
@@ -297,7 +498,7 @@ impl<'src> LineInfo<&'src [u8]> {
             .map(|(i, line_src)| (i, offset_of(src, line_src), line_src))
             .filter(|(_i, line_offset, _line_src)| line_offset <= &offset)
             .last()
-            .expect("split to always produce an element");
+            .unwrap_or((0, 0, &src[..0]));
         let column = offset - line_offset;
         LineInfo {
             line,
@@ -305,7 +506,7 @@ impl<'src> LineInfo<&'src [u8]> {
             line_src,
         }
     }
-    fn into_owned(self) -> LineInfo<Vec<u8>> {
+    pub(crate) fn into_owned(self) -> LineInfo<Vec<u8>> {
         LineInfo {
             line: self.line,
             column: self.column,
@@ -323,26 +524,257 @@ enum Token {
     Eof,
 }
 
-struct McReader<'src> {
+/// what the reader builds from each line
+/// so that the same parser can build two-state nodes or multi-state nodes
+trait McItem: Sized {
+    type Output;
+    /// smallest size of a numbered line, and the hint if it is smaller
+    const MIN_SIZE: (u8, MacrocellErrorHint);
+
+    fn from_block(block: Block) -> Self;
+    /// largest value of the 4 numbers after a size, and the hint if one is larger
+    fn max_child(size: u8, items: &[Self]) -> (usize, MacrocellErrorHint);
+    /// the index of the first child that is a reference to the wrong size if it fails
+    fn from_children(size: u8, children: [usize; 4], items: &[Self]) -> Result<Self, usize>;
+    fn into_output(last: Option<Self>) -> Self::Output;
+}
+impl McItem for Either<Block, Node> {
+    type Output = Node;
+    const MIN_SIZE: (u8, MacrocellErrorHint) = (
+        Node::MIN_WIDTH_LOG2,
+        MacrocellErrorHint::InvalidTwoStateDepth,
+    );
+
+    fn from_block(block: Block) -> Self {
+        Either::Left(block)
+    }
+    fn max_child(_size: u8, items: &[Self]) -> (usize, MacrocellErrorHint) {
+        (items.len(), MacrocellErrorHint::InvalidForwardRef)
+    }
+    fn from_children(size: u8, children: [usize; 4], items: &[Self]) -> Result<Self, usize> {
+        if size == Node::MIN_WIDTH_LOG2 {
+            let [nw, ne, sw, se] = children_of(children, items, Block::empty, |r| match r {
+                Either::Left(block) => Some(*block),
+                Either::Right(_) => None,
+            })?;
+            Ok(Either::Right(Node::new(nw, ne, sw, se)))
+        } else {
+            let child_depth = size - Node::MIN_WIDTH_LOG2 - 1;
+            let [nw, ne, sw, se] = children_of(
+                children,
+                items,
+                || Node::empty(child_depth),
+                |r| match r {
+                    Either::Right(node) if node.depth() == child_depth => Some(node.clone()),
+                    _ => None,
+                },
+            )?;
+            Ok(Either::Right(Node::new(nw, ne, sw, se)))
+        }
+    }
+    fn into_output(last: Option<Self>) -> Node {
+        match last {
+            Some(Either::Right(node)) => node,
+            // normalize smaller than node patterns into the smallest node
+            Some(Either::Left(block)) => block.expand().into(),
+            None => Node::empty(0),
+        }
+    }
+}
+
+enum McMultiItem {
+    /// a square area smaller than a node, by its states in row-major order
+    Cells(Vec<u8>),
+    Node(MultiNode),
+}
+impl McItem for McMultiItem {
+    type Output = MultiNode;
+    const MIN_SIZE: (u8, MacrocellErrorHint) = (1, MacrocellErrorHint::InvalidMultiStateDepth);
+
+    fn from_block(block: Block) -> Self {
+        McMultiItem::Cells(states_from_planes(&[block]).concat())
+    }
+    fn max_child(size: u8, items: &[Self]) -> (usize, MacrocellErrorHint) {
+        if size == 1 {
+            (u8::MAX.into(), MacrocellErrorHint::StateTooLarge)
+        } else {
+            (items.len(), MacrocellErrorHint::InvalidForwardRef)
+        }
+    }
+    fn from_children(size: u8, children: [usize; 4], items: &[Self]) -> Result<Self, usize> {
+        #[allow(clippy::cast_possible_truncation)] // max state checked
+        if size == 1 {
+            return Ok(McMultiItem::Cells(
+                children.map(|state| state as u8).to_vec(),
+            ));
+        }
+        if size > Block::WIDTH_LOG2 + 1 {
+            let child_depth = size - Node::MIN_WIDTH_LOG2 - 1;
+            let [nw, ne, sw, se] = children_of(
+                children,
+                items,
+                || MultiNode::empty(child_depth),
+                |r| match r {
+                    McMultiItem::Node(node) if node.depth() == child_depth => Some(node.clone()),
+                    _ => None,
+                },
+            )?;
+            return Ok(McMultiItem::Node(MultiNode::new_inner(Quad {
+                nw,
+                ne,
+                sw,
+                se,
+            })));
+        }
+        let half = 1 << (size - 1);
+        let [nw, ne, sw, se] = children_of(
+            children,
+            items,
+            || vec![0; half * half],
+            |r| match r {
+                McMultiItem::Cells(states) if states.len() == half * half => Some(states.clone()),
+                _ => None,
+            },
+        )?;
+        // interleave the rows of each pair of children
+        let states: Vec<u8> = nw
+            .chunks(half)
+            .zip(ne.chunks(half))
+            .chain(sw.chunks(half).zip(se.chunks(half)))
+            .flat_map(|(w, e)| w.iter().chain(e))
+            .copied()
+            .collect();
+        if size == Node::MIN_WIDTH_LOG2 {
+            let block_planes = |x0: usize, y0: usize| {
+                planes_from_states(&std::array::from_fn(|y| {
+                    std::array::from_fn(|x| states[(y0 + y) * 16 + x0 + x])
+                }))
+            };
+            Ok(McMultiItem::Node(MultiNode::new_leaf(&Quad {
+                nw: block_planes(0, 0),
+                ne: block_planes(8, 0),
+                sw: block_planes(0, 8),
+                se: block_planes(8, 8),
+            })))
+        } else {
+            Ok(McMultiItem::Cells(states))
+        }
+    }
+    fn into_output(last: Option<Self>) -> MultiNode {
+        match last {
+            Some(McMultiItem::Node(node)) => node,
+            // normalize smaller than node patterns into the smallest node, centered the same as a block
+            Some(McMultiItem::Cells(states)) => {
+                let width = cells_width(&states);
+                let offset = (16 - width) / 2;
+                let mut node = MultiNode::empty(0);
+                for (i, &state) in states.iter().enumerate() {
+                    #[allow(clippy::cast_possible_wrap)] // less than 16
+                    let pos = Pos {
+                        x: (offset + i % width) as i64 - 8,
+                        y: (offset + i / width) as i64 - 8,
+                    };
+                    node = node.set(pos, state);
+                }
+                node
+            }
+            None => MultiNode::empty(0),
+        }
+    }
+}
+/// the text of a `#N`, `#O` or `#C` line, which is separated from the tag by a space
+fn text_after_tag(text: &str) -> &str {
+    text.strip_prefix(' ').unwrap_or(text)
+}
+/// reads the metadata in a `#` line, which doesn't include the line terminator
+///
+/// fails with the column and hint if the line is invalid
+fn read_metadata<N>(
+    pattern: &mut Pattern<N>,
+    line: &[u8],
+) -> Result<(), (usize, MacrocellErrorHint)> {
+    let Some(&tag @ (b'R' | b'G' | b'N' | b'O' | b'C')) = line.get(1) else {
+        return Ok(());
+    };
+    let text = match std::str::from_utf8(&line[2..]) {
+        Ok(text) => text,
+        Err(err) => return Err((2 + err.valid_up_to(), MacrocellErrorHint::InvalidChar)),
+    };
+    match tag {
+        b'R' => {
+            let rule = text.trim();
+            pattern.rule = Some(rule.parse().map_err(|_| rule.to_owned()));
+        }
+        b'G' => {
+            let digits = text.trim_start();
+            let start = 2 + text.len() - digits.len();
+            let digits = digits.trim_end();
+            if let Some(i) = digits.bytes().position(|b| !b.is_ascii_digit()) {
+                return Err((start + i, MacrocellErrorHint::InvalidGeneration));
+            }
+            match BigUint::parse_bytes(digits.as_bytes(), 10) {
+                Some(generation) => pattern.generation = generation,
+                None => return Err((start, MacrocellErrorHint::InvalidGeneration)),
+            }
+        }
+        b'N' => pattern.name = Some(text_after_tag(text).to_owned()),
+        b'O' => pattern.author = Some(text_after_tag(text).to_owned()),
+        _ => pattern.comments.push(text_after_tag(text).to_owned()),
+    }
+    Ok(())
+}
+/// looks up 4 children that were given as 1 based indices, where 0 is empty
+fn children_of<I, T>(
+    children: [usize; 4],
+    items: &[I],
+    empty: impl Fn() -> T,
+    filter: impl Fn(&I) -> Option<T>,
+) -> Result<[T; 4], usize> {
+    let child = |i: usize| match children[i] {
+        0 => Ok(empty()),
+        index => filter(&items[index - 1]).ok_or(i),
+    };
+    Ok([child(0)?, child(1)?, child(2)?, child(3)?])
+}
+/// width of a square area of states
+fn cells_width(states: &[u8]) -> usize {
+    1 << (states.len().ilog2() / 2)
+}
+
+struct McReader<'src, I> {
     src: &'src [u8],
     at: usize,
-    nodes: Vec<Either<Block, Node>>,
+    nodes: Vec<I>,
+    /// start of each `#` line, only parsed if the metadata is asked for
+    metadata: Vec<usize>,
 }
-impl<'src> McReader<'src> {
+impl<'src, I: McItem> McReader<'src, I> {
     fn new(src: &'src [u8]) -> Self {
         Self {
             src,
             at: 0,
             nodes: Vec::new(),
+            metadata: Vec::new(),
         }
     }
 
-    fn read(mut self) -> MacrocellResult<'src, Node> {
+    fn read(mut self) -> MacrocellResult<'src, I::Output> {
         self.read_header()?;
         self.read_body()
     }
+    fn read_pattern(mut self) -> MacrocellResult<'src, Pattern<I::Output>> {
+        self.read_header()?;
+        let mut pattern = Pattern::new(self.read_body()?);
+        for &line_at in &self.metadata {
+            let line = &self.src[line_at..self.line_end(line_at)];
+            if let Err((column, hint)) = read_metadata(&mut pattern, line) {
+                return self.fail_at(line_at + column, hint);
+            }
+        }
+        Ok(pattern)
+    }
     fn read_header(&mut self) -> MacrocellResult<'src, ()> {
-        if &self.src[self.at..][..4] == b"[M2]" {
+        if self.src[self.at..].starts_with(b"[M2]") {
             self.at += 4;
             self.consume_line();
             Ok(())
@@ -350,55 +782,60 @@ impl<'src> McReader<'src> {
             self.fail(MacrocellErrorHint::InvalidHeader)
         }
     }
-    fn read_body(&mut self) -> MacrocellResult<'src, Node> {
+    fn read_body(&mut self) -> MacrocellResult<'src, I::Output> {
+        self.read_nodes()?;
+        Ok(I::into_output(self.nodes.pop()))
+    }
+    fn read_nodes(&mut self) -> MacrocellResult<'src, ()> {
         loop {
             match self.peak_token()? {
                 Token::Block => {
                     let block = self.consume_block_line()?;
-                    self.nodes.push(Either::Left(block));
+                    self.nodes.push(I::from_block(block));
                 }
                 Token::Number => {
                     let node = self.consume_node_line()?;
-                    self.nodes.push(Either::Right(node));
+                    self.nodes.push(node);
                 }
                 Token::Eol => {
+                    if self.peak() == Some(b'#') {
+                        self.metadata.push(self.at);
+                    }
                     self.consume_line();
                 }
-                Token::Eof => {
-                    return Ok(match self.nodes.pop() {
-                        Some(Either::Right(node)) => node,
-                        // normalize smaller than node patterns into the smallest node
-                        Some(Either::Left(block)) => block.expand().into(),
-                        None => Node::empty(0),
-                    });
-                }
+                Token::Eof => return Ok(()),
             }
         }
     }
 
-    fn consume_node_line(&mut self) -> MacrocellResult<'src, Node> {
+    fn consume_node_line(&mut self) -> MacrocellResult<'src, I> {
         let pos = self.at;
         #[allow(clippy::cast_possible_truncation)] // max size checked
         let size = self.consume_number(
             Node::MAX_WIDTH_LOG2 as usize,
             MacrocellErrorHint::SizeTooLarge,
         )? as u8;
-        if size < Node::MIN_WIDTH_LOG2 {
-            return self.fail_at(pos, MacrocellErrorHint::InvalidTwoStateDepth);
-        }
-        let value = if size == Node::MIN_WIDTH_LOG2 {
-            let nw = self.expect_block_ref()?;
-            let ne = self.expect_block_ref()?;
-            let sw = self.expect_block_ref()?;
-            let se = self.expect_block_ref()?;
-            Node::new(nw, ne, sw, se)
-        } else {
-            let child_depth = size - Node::MIN_WIDTH_LOG2 - 1;
-            let nw = self.expect_node_ref(child_depth)?;
-            let ne = self.expect_node_ref(child_depth)?;
-            let sw = self.expect_node_ref(child_depth)?;
-            let se = self.expect_node_ref(child_depth)?;
-            Node::new(nw, ne, sw, se)
+        let (min_size, min_size_hint) = I::MIN_SIZE;
+        if size < min_size {
+            return self.fail_at(pos, min_size_hint);
+        }
+        let (max, too_large_hint) = I::max_child(size, &self.nodes);
+        let mut children = [0; 4];
+        let mut positions = [0; 4];
+        for (child, position) in children.iter_mut().zip(&mut positions) {
+            match self.peak_token()? {
+                Token::Block => return self.fail(MacrocellErrorHint::InvalidBlockAfterNumber),
+                Token::Number => {}
+                Token::Eol | Token::Eof => {
+                    return self.fail(MacrocellErrorHint::InvalidEolAfterNumber)
+                }
+            }
+            *position = self.at;
+            *child = self.consume_number(max, too_large_hint)?;
+        }
+        let value = match I::from_children(size, children, &self.nodes) {
+            Ok(value) => value,
+            Err(i) => return self.fail_at(positions[i], MacrocellErrorHint::InvalidRefDepth),
         };
 
         self.expect_line(
@@ -408,41 +845,6 @@ impl<'src> McReader<'src> {
 
         Ok(value)
     }
-    fn expect_node_ref(&mut self, expected_depth: u8) -> MacrocellResult<'src, Node> {
-        self.expect_ref(
-            || Node::empty(expected_depth),
-            |r| match r {
-                Either::Right(node) if node.depth() == expected_depth => Some(node.clone()),
-                _ => None,
-            },
-        )
-    }
-    fn expect_block_ref(&mut self) -> MacrocellResult<'src, Block> {
-        self.expect_ref(Block::empty, |r| match r {
-            Either::Left(block) => Some(*block),
-            Either::Right(_) => None,
-        })
-    }
-    fn expect_ref<T>(
-        &mut self,
-        empty: impl Fn() -> T,
-        filter: impl Fn(&Either<Block, Node>) -> Option<T>,
-    ) -> MacrocellResult<'src, T> {
-        match self.peak_token()? {
-            Token::Block => return self.fail(MacrocellErrorHint::InvalidBlockAfterNumber),
-            Token::Number => {}
-            Token::Eol | Token::Eof => return self.fail(MacrocellErrorHint::InvalidEolAfterNumber),
-        }
-        let pos = self.at;
-        let index = self.consume_number(self.nodes.len(), MacrocellErrorHint::InvalidForwardRef)?;
-        if index == 0 {
-            Ok(empty())
-        } else if let Some(value) = filter(&self.nodes[index - 1]) {
-            Ok(value)
-        } else {
-            self.fail_at(pos, MacrocellErrorHint::InvalidRefDepth)
-        }
-    }
     fn consume_number(
         &mut self,
         max: usize,
@@ -525,6 +927,13 @@ impl<'src> McReader<'src> {
             Token::Eof => Ok(()),
         }
     }
+    /// the end of the line starting at `at`, before any line terminator
+    fn line_end(&self, at: usize) -> usize {
+        self.src[at..]
+            .iter()
+            .position(|b| matches!(b, b'\n' | b'\r'))
+            .map_or(self.src.len(), |len| at + len)
+    }
     fn consume_line(&mut self) {
         loop {
             let b = self.peak();
@@ -569,13 +978,102 @@ impl<'src> McReader<'src> {
     }
 }
 
+/// an io error, or else the same result as reading from bytes but with the line of an error copied out
+pub type McStreamResult<T> = Result<Result<T, MacrocellError<Vec<u8>>>, IoError>;
+
+/// reads one line at a time with a `McReader`, which is given the nodes read from the previous lines
+struct McStreamReader<R, I> {
+    read: R,
+    /// the current line, reused for each line
+    buf: Vec<u8>,
+    /// index of the current line in the file
+    line: usize,
+    nodes: Vec<I>,
+    /// each `#` line and its index, only parsed if the metadata is asked for
+    metadata: Vec<(usize, Vec<u8>)>,
+}
+impl<R: BufRead, I: McItem> McStreamReader<R, I> {
+    fn new(read: R) -> Self {
+        Self {
+            read,
+            buf: Vec::new(),
+            line: 0,
+            nodes: Vec::new(),
+            metadata: Vec::new(),
+        }
+    }
+
+    fn read(mut self) -> McStreamResult<I::Output> {
+        Ok(self
+            .read_nodes()?
+            .map(|()| I::into_output(self.nodes.pop())))
+    }
+    fn read_pattern(mut self) -> McStreamResult<Pattern<I::Output>> {
+        if let Err(err) = self.read_nodes()? {
+            return Ok(Err(err));
+        }
+        let mut pattern = Pattern::new(I::into_output(self.nodes.pop()));
+        for (line, line_src) in self.metadata {
+            if let Err((column, hint)) = read_metadata(&mut pattern, &line_src) {
+                let line = LineInfo {
+                    line,
+                    column,
+                    line_src,
+                };
+                return Ok(Err(MacrocellError::new(line, hint)));
+            }
+        }
+        Ok(Ok(pattern))
+    }
+    fn read_nodes(&mut self) -> McStreamResult<()> {
+        loop {
+            self.buf.clear();
+            // a `\r` without a `\n` also ends a line, which `McReader` handles inside of `buf`
+            let is_eof = self.read.read_until(b'\n', &mut self.buf)? == 0;
+            if is_eof && self.line > 0 {
+                return Ok(Ok(()));
+            }
+            let mut reader = McReader {
+                src: &self.buf,
+                at: 0,
+                nodes: std::mem::take(&mut self.nodes),
+                metadata: Vec::new(),
+            };
+            let result = if self.line == 0 {
+                reader.read_header().and_then(|()| reader.read_nodes())
+            } else {
+                reader.read_nodes()
+            };
+            if let Err(err) = result {
+                let mut err = err.into_owned();
+                err.0.line.line += self.line;
+                return Ok(Err(err));
+            }
+            for &line_at in &reader.metadata {
+                let line = self.line + LineInfo::from_src_offset(&self.buf, line_at).line;
+                let line_src = self.buf[line_at..reader.line_end(line_at)].to_vec();
+                self.metadata.push((line, line_src));
+            }
+            self.nodes = reader.nodes;
+            // the same lines that `LineInfo` counts
+            self.line += 1 + self
+                .buf
+                .windows(2)
+                .filter(|pair| pair[0] == b'\r' && pair[1] != b'\n')
+                .count();
+        }
+    }
+}
+
 // tests
 
 #[cfg(test)]
 mod test {
+    use std::io::BufReader;
+
     use unindent::unindent;
 
-    use crate::{Block, Node};
+    use crate::{BigUint, Block, BoundedGrid, MultiNode, Node, Pattern, Pos, Rule, Topology};
 
     #[allow(clippy::needless_pass_by_value)]
     fn assert_node_fmt(node: Node, fmt: &str) {
@@ -617,4 +1115,223 @@ mod test {
             ",
         );
     }
+
+    #[test]
+    fn rule() {
+        let node = Node::new(
+            Block::from_rows(0x80),
+            Block::empty(),
+            Block::empty(),
+            Block::empty(),
+        );
+        let hex = "B2/S34H".parse::<Rule>().unwrap();
+        let mut out = Vec::new();
+        let pattern = Pattern::new(node.clone()).with_rule(hex.clone());
+        pattern.write_to(&mut out).unwrap();
+        assert!(out.starts_with(b"[M2] (metalife 1.0)\n#R B2/S34H\n"));
+        let read = Pattern::<Node>::read_from_bytes(&out).expect("valid input");
+        assert_eq!(read, pattern);
+
+        let read = Pattern::<Node>::read_from_bytes(b"[M2]\n4 0 0 0 0\n").unwrap();
+        assert_eq!(read.rule, None);
+        // rules that can't be parsed are kept as they were written
+        let fmt = b"[M2] (metalife 1.0)\n#R LifeHistory\n4 0 0 0 0\n";
+        let read = Pattern::<Node>::read_from_bytes(fmt).expect("valid input");
+        assert_eq!(read.rule, Some(Err("LifeHistory".to_owned())));
+        let mut out = Vec::new();
+        read.write_to(&mut out).unwrap();
+        assert_eq!(out, fmt);
+        // including rules from a rule file, which are written as their name
+        let wireworld = Rule::read_from_string(
+            "@RULE WireWorld
+            @TABLE
+            n_states:4
+            neighborhood:Moore
+            symmetries:permute
+            var a={0,1,2,3}
+            var b={a}
+            var c={a}
+            var d={a}
+            var e={a}
+            var f={a}
+            var g={a}
+            var h={a}
+            1,a,b,c,d,e,f,g,h,2
+            2,a,b,c,d,e,f,g,h,3
+            ",
+        )
+        .unwrap();
+        let multi = Pattern::new(MultiNode::from(node.clone())).with_rule(wireworld);
+        let mut out = Vec::new();
+        multi.write_to(&mut out).unwrap();
+        let read = Pattern::<MultiNode>::read_from_bytes(&out).expect("valid input");
+        assert_eq!(read.rule, Some(Err("WireWorld".to_owned())));
+        assert_eq!(read.node, multi.node);
+
+        let multi = Pattern::new(MultiNode::from(node)).with_rule("/2/3".parse().unwrap());
+        let mut out = Vec::new();
+        multi.write_to(&mut out).unwrap();
+        assert_eq!(
+            Pattern::<MultiNode>::read_from_bytes(&out).expect("valid input"),
+            multi
+        );
+
+        // bounded grids are part of the rule
+        let read =
+            Pattern::<Node>::read_from_bytes(b"[M2]\n#R B3/S23:T20,10\n4 0 0 0 0\n").unwrap();
+        let grid = read.rule.unwrap().unwrap().grid().copied();
+        assert_eq!(grid, Some(BoundedGrid::new(Topology::Torus, 20, 10)));
+        let read = Pattern::<Node>::read_from_bytes(b"[M2]\n#R B3/S23:T20\n4 0 0 0 0\n").unwrap();
+        assert_eq!(read.rule, Some(Err("B3/S23:T20".to_owned())));
+    }
+
+    #[test]
+    fn metadata() {
+        let node = Node::new(
+            Block::from_rows(0x80_00_00_00_00_00_00_00),
+            Block::empty(),
+            Block::empty(),
+            Block::from_rows(0x01),
+        );
+        let generation = BigUint::from(u64::MAX) * 1000_u32 + 7_u32;
+        let pattern = Pattern::new(node)
+            .with_rule(Rule::LIFE)
+            .with_generation(generation)
+            .with_name("two cells")
+            .with_author("someone")
+            .with_comment("far apart")
+            .with_comment("")
+            .with_comment(" indented");
+        let mut out = Vec::new();
+        pattern.write_to(&mut out).unwrap();
+        let fmt = unindent(
+            "
+            [M2] (metalife 1.0)
+            #R B3/S23
+            #G 18446744073709551615007
+            #N two cells
+            #O someone
+            #C far apart
+            #C
+            #C  indented
+            *$$$$$$$$
+            $$$$$$$.......*$
+            4 1 0 0 2
+            ",
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), fmt);
+        assert_eq!(Pattern::<Node>::read_from_string(&fmt).unwrap(), pattern);
+        let multi = pattern.clone().map(MultiNode::from);
+        assert_eq!(Pattern::<MultiNode>::read_from_string(&fmt).unwrap(), multi);
+
+        // golly's headers and unknown lines are ignored
+        let fmt = "[M2] (golly 4.2)\r\n#G 12\r\n#FRAMES 3\r\n#C a\r\n4 0 0 0 0\r\n";
+        let read = Pattern::<Node>::read_from_string(fmt).unwrap();
+        assert_eq!(
+            read,
+            Pattern::new(Node::empty(0))
+                .with_generation(12_u32)
+                .with_comment("a")
+        );
+
+        // a generation of 0 isn't written
+        assert!(!Node::empty(0).write_to_string().contains("#G"));
+
+        let err = Pattern::<Node>::read_from_string("[M2]\n#G 12x4\n4 0 0 0 0\n").unwrap_err();
+        assert_eq!((err.line(), err.column()), (1, 5));
+        let err = Pattern::<Node>::read_from_string("[M2]\n#G\n4 0 0 0 0\n").unwrap_err();
+        assert_eq!((err.line(), err.column()), (1, 2));
+    }
+
+    #[test]
+    fn multi_state() {
+        let node = MultiNode::empty(0)
+            .set(Pos { x: -8, y: -8 }, 1)
+            .set(Pos { x: -7, y: -8 }, 2)
+            .set(Pos { x: -8, y: -7 }, 3)
+            .set(Pos { x: 7, y: 7 }, 1);
+        let fmt = unindent(
+            "
+            [M2] (metalife 1.0)
+            1 1 2 3 0
+            2 1 0 0 0
+            3 2 0 0 0
+            1 0 0 0 1
+            2 0 0 0 4
+            3 0 0 0 5
+            4 3 0 0 6
+            ",
+        );
+        assert_eq!(node.write_to_string(), fmt);
+        assert_eq!(
+            MultiNode::read_from_string(&fmt).expect("valid input"),
+            node
+        );
+        // the size 1 line isn't valid for two-state
+        let err = Node::read_from_string(&fmt).unwrap_err();
+        assert_eq!((err.line(), err.column()), (1, 0));
+
+        let rule = "345/2/4".parse::<Rule>().unwrap();
+        let node = node.step(&rule, 20);
+        assert_eq!(
+            MultiNode::read_from_bytes(&node.write_to_bytes()).expect("valid input"),
+            node
+        );
+    }
+
+    #[test]
+    fn multi_state_reads_two_state() {
+        let b1 = Block::from_rows(0x80_00_00_00_00_00_00_00);
+        let b2 = Block::from_rows(0x80_40_20_10_08_04_02_01);
+        let node = Node::new(b1, Block::empty(), b2, b1);
+        let multi = MultiNode::read_from_bytes(&node.write_to_bytes()).expect("valid input");
+        assert_eq!(multi, MultiNode::from(node));
+
+        let err = MultiNode::read_from_string("[M2]\n1 0 256 0 0").unwrap_err();
+        assert_eq!((err.line(), err.column()), (1, 4));
+    }
+
+    #[test]
+    fn stream() {
+        let node = Node::new(
+            Block::from_rows(0x80_40_20_10_08_04_02_01),
+            Block::empty(),
+            Block::from_rows(0x01),
+            Block::from_rows(0x01),
+        )
+        .expand()
+        .expand();
+        let bytes = node.write_to_bytes();
+        assert_eq!(Node::read_from(&bytes[..]).unwrap().unwrap(), node);
+        // lines that are split across several reads
+        let small_reads = BufReader::with_capacity(3, &bytes[..]);
+        assert_eq!(Node::read_from_buf(small_reads).unwrap().unwrap(), node);
+        assert_eq!(
+            MultiNode::read_from(&bytes[..]).unwrap().unwrap(),
+            MultiNode::from(node)
+        );
+
+        let fmt = "[M2]\r\n#N name\r#C a\r\n\r\n#C b\n4 0 0 0 0\n#R B36/S23\n";
+        assert_eq!(
+            Pattern::<Node>::read_from(fmt.as_bytes()).unwrap().unwrap(),
+            Pattern::<Node>::read_from_string(fmt).unwrap()
+        );
+
+        // errors are on the same line as when reading from bytes
+        for fmt in [
+            "",
+            "[M",
+            "#C no header\n[M2]\n",
+            "[M2]\n$\n*$\r4 1 2 1 x\n",
+            "[M2]\r\n.*\r\n4 1 1 1\r\n",
+            "[M2]\n\r\r4 0 0 0 0\n#R B3/S23\n#G 1x\n",
+        ] {
+            let err = Pattern::<Node>::read_from(fmt.as_bytes())
+                .unwrap()
+                .unwrap_err();
+            let expected = Pattern::<Node>::read_from_string(fmt).unwrap_err();
+            assert_eq!(err.to_string(), expected.to_string(), "{fmt:?}");
+            assert_eq!(err.line(), expected.line());
+        }
+    }
 }
@@ -1,6 +1,6 @@
-use crate::{Block, DepthQuad, Node, Pos, Quad};
+use crate::{Block, DepthQuad, Node, Population, Pos, Quad};
 
-// TODO memoize? or if not then special case empty
+// TODO memoize?
 
 // TODO if this was normalizing then offset_norm wouldn't be as necessary
 
@@ -65,6 +65,10 @@ impl Quad<&Node> {
         if x == 0 && y == 0 {
             return self.se.clone();
         }
+        // so sparse nodes only look at the parts with cells
+        if self.is_empty() {
+            return Node::empty(self.se.depth());
+        }
         match self.children() {
             DepthQuad::Leaf(leaf) => leaf.offset_shrink_se(x, y).into(),
             DepthQuad::Inner(_, inner) => inner.offset_shrink_se(x, y).into(),
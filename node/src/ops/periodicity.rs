@@ -0,0 +1,275 @@
+//! finds still lifes, oscillators, and spaceships by stepping until the cells repeat
+//!
+//! each generation is moved so the north west corner of its cells is at the origin and centered at a depth that only depends on its size,
+//! so the same cells in any position are the same node and comparing generations doesn't look at any cells
+
+use crate::{Node, Population, Pos, Rule};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Periodicity {
+    /// also used for an empty node
+    StillLife,
+    Oscillator {
+        period: u64,
+    },
+    /// `displacement` is how far the cells move each period
+    Spaceship {
+        period: u64,
+        displacement: Pos,
+    },
+    /// the cells didn't repeat within the max period
+    Unknown,
+}
+impl Periodicity {
+    pub fn period(&self) -> Option<u64> {
+        match self {
+            Periodicity::StillLife => Some(1),
+            Periodicity::Oscillator { period } | Periodicity::Spaceship { period, .. } => {
+                Some(*period)
+            }
+            Periodicity::Unknown => None,
+        }
+    }
+}
+
+impl Node {
+    /// steps 1 generation at a time for up to `max_period` generations until the cells are the same as `self` in some position
+    ///
+    /// only finds patterns that repeat from the first generation, so a pattern that settles into an oscillator is `Unknown`
+    ///
+    /// the cells are only compared in generations where the background is dead, see `Node::step_with_background`,
+    /// so with B0 rules that turn the background alive every other generation the period is even
+    pub fn periodicity(&self, rule: &Rule, max_period: u64) -> Periodicity {
+        let Some((start, cells)) = normalize(self) else {
+            return Periodicity::StillLife;
+        };
+        let mut node = self.clone();
+        let mut background = false;
+        for period in 1..=max_period {
+            (node, background) = node.step_with_background(rule, 1, background);
+            match normalize(&node) {
+                Some((pos, stepped)) if !background && stepped == cells => {
+                    let displacement = pos - start;
+                    return if displacement != Pos::new(0, 0) {
+                        Periodicity::Spaceship {
+                            period,
+                            displacement,
+                        }
+                    } else if period == 1 {
+                        Periodicity::StillLife
+                    } else {
+                        Periodicity::Oscillator { period }
+                    };
+                }
+                // dying out never repeats, since `self` wasn't empty,
+                // and neither does a background that is entirely alive
+                None => return Periodicity::Unknown,
+                Some(_) => {}
+            }
+        }
+        Periodicity::Unknown
+    }
+}
+
+/// the north west corner of the cells, and the cells moved so that corner is at the origin
+///
+/// the node is the smallest one centered on the origin that fits the cells, so it only depends on the cells and not where they were
+fn normalize(node: &Node) -> Option<(Pos, Node)> {
+    if node.is_empty() {
+        return None;
+    }
+    let (pos, node) = node.offset_norm();
+    let rect = node.bounding_rect();
+    let moved = node.offset(-rect.nw());
+    let last = (rect.east() - rect.west()).max(rect.south() - rect.north());
+    // the cells are in `0..=last`, so they fit if `last < half_width`
+    let width_log2 = (64 - last.leading_zeros()).max(u32::from(Node::MIN_WIDTH_LOG2) - 1) + 1;
+    let depth = u8::try_from(width_log2).expect("less than 64") - Node::MIN_WIDTH_LOG2;
+    Some((pos + rect.nw(), moved.center_at_depth(depth)))
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use super::Periodicity;
+    use crate::{test_node, Node, Pos, Rule};
+
+    fn glider() -> Node {
+        test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "}
+    }
+    fn block() -> Node {
+        test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ........oo......
+            ........oo......
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            "}
+    }
+
+    #[test]
+    fn objects() {
+        assert_eq!(block().periodicity(&Rule::LIFE, 10), Periodicity::StillLife);
+        let blinker = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .......ooo......
+            ................
+            ................
+            "};
+        assert_eq!(
+            blinker.periodicity(&Rule::LIFE, 10),
+            Periodicity::Oscillator { period: 2 }
+        );
+        assert_eq!(
+            glider().periodicity(&Rule::LIFE, 10),
+            Periodicity::Spaceship {
+                period: 4,
+                displacement: Pos::new(1, 1)
+            }
+        );
+        let lwss = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o..o...
+            ........o.......
+            ........o...o...
+            ........oooo....
+            ................
+            ................
+            ................
+            ................
+            "};
+        assert_eq!(
+            lwss.periodicity(&Rule::LIFE, 10),
+            Periodicity::Spaceship {
+                period: 4,
+                displacement: Pos::new(-2, 0)
+            }
+        );
+        assert_eq!(
+            Node::empty(3).periodicity(&Rule::LIFE, 10),
+            Periodicity::StillLife
+        );
+    }
+
+    #[test]
+    fn unknown() {
+        // grows for a long time
+        let r_pentomino = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........oo.....
+            ........oo......
+            .........o......
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        assert_eq!(
+            r_pentomino.periodicity(&Rule::LIFE, 50),
+            Periodicity::Unknown
+        );
+        assert_eq!(Periodicity::Unknown.period(), None);
+        // dies
+        assert_eq!(
+            Node::empty(0)
+                .set(Pos::new(0, 0), true)
+                .set(Pos::new(1, 0), true)
+                .periodicity(&Rule::LIFE, 50),
+            Periodicity::Unknown
+        );
+        // too short of a max period
+        assert_eq!(glider().periodicity(&Rule::LIFE, 3), Periodicity::Unknown);
+    }
+
+    #[test]
+    fn b0() {
+        // every cell changes every generation, so the cells that differ from the background are the same,
+        // but the cells only repeat once the background is dead again
+        let flip = "B012345678/S".parse().unwrap();
+        assert_eq!(
+            glider().periodicity(&flip, 10),
+            Periodicity::Oscillator { period: 2 }
+        );
+        // with S8 the background stays alive
+        let inverse_life = "B0123478/S01234678".parse().unwrap();
+        assert_eq!(block().periodicity(&inverse_life, 10), Periodicity::Unknown);
+    }
+
+    #[test]
+    fn far_apart() {
+        // only the parts with cells are stepped and moved
+        let blinkers = [
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (1 << 40, -(1 << 41)),
+            ((1 << 40) + 1, -(1 << 41)),
+            ((1 << 40) + 2, -(1 << 41)),
+        ]
+        .into_iter()
+        .fold(Node::empty(0), |node, (x, y)| {
+            node.set(Pos::new(x, y), true)
+        });
+        assert_eq!(
+            blinkers.periodicity(&Rule::LIFE, 10),
+            Periodicity::Oscillator { period: 2 }
+        );
+        assert_eq!(blinkers.periodicity(&Rule::LIFE, 10).period(), Some(2));
+    }
+}
@@ -0,0 +1,841 @@
+//! the run length encoded format that most pattern collections and Golly use
+//!
+//! cells are read straight into the blocks that contain them, so long runs of dead cells are free
+
+use std::error::Error;
+use std::fmt::{Debug, Display};
+use std::io::{Error as IoError, Write};
+
+use num_bigint::BigUint;
+
+use super::blocks::{BlockSet, Run};
+use super::life_format::{offset_pos, MAX_COORD};
+use super::mc_format::LineInfo;
+use crate::{MultiNode, Node, Pattern, Pos, Rule};
+
+impl Node {
+    /// writes the pattern with `rule = B3/S23` in the header, see `Pattern::write_rle` to write other metadata
+    pub fn write_rle(&self, write: impl Write) -> Result<(), IoError> {
+        Pattern::new(self.clone())
+            .with_rule(Rule::LIFE)
+            .write_rle(write)
+    }
+    pub fn write_rle_to_string(&self) -> String {
+        let mut out = Vec::new();
+        self.write_rle(&mut out).expect("valid bytes");
+        String::from_utf8(out).expect("valid string")
+    }
+    /// ignores any metadata, see `Pattern::read_rle` to also read it
+    pub fn read_rle(bytes: &[u8]) -> Result<Self, RleError<&[u8]>> {
+        let planes = RleReader::new(bytes, 1).read()?;
+        Ok(node_from_planes(planes))
+    }
+}
+impl MultiNode {
+    /// writes states as `.` and `A` to `X`, with `p` to `y` before them for states above 24
+    pub fn write_rle(&self, write: impl Write) -> Result<(), IoError> {
+        Pattern::new(self.clone()).write_rle(write)
+    }
+    pub fn write_rle_to_string(&self) -> String {
+        let mut out = Vec::new();
+        self.write_rle(&mut out).expect("valid bytes");
+        String::from_utf8(out).expect("valid string")
+    }
+    /// reads either multi-state or two-state cells, ignoring any metadata
+    pub fn read_rle(bytes: &[u8]) -> Result<Self, RleError<&[u8]>> {
+        let planes = RleReader::new(bytes, u8::MAX).read()?;
+        Ok(multi_node_from_planes(planes))
+    }
+}
+impl Pattern<Node> {
+    /// writes the name, author and comments in `#N`, `#O` and `#C` lines,
+    /// and the position and generation in a `#CXRLE` line so they are kept when read back
+    pub fn write_rle(&self, write: impl Write) -> Result<(), IoError> {
+        RleWriter::new(write, false).write(self, &self.node.runs())
+    }
+    /// the north west cell of the pattern is at the `Pos` of the `#CXRLE` line if there is one,
+    /// otherwise the pattern is centered on the origin using the width and height in the header
+    pub fn read_rle(bytes: &[u8]) -> Result<Self, RleError<&[u8]>> {
+        Ok(RleReader::new(bytes, 1)
+            .read_pattern()?
+            .map(node_from_planes))
+    }
+}
+impl Pattern<MultiNode> {
+    pub fn write_rle(&self, write: impl Write) -> Result<(), IoError> {
+        RleWriter::new(write, true).write(self, &self.node.runs())
+    }
+    pub fn read_rle(bytes: &[u8]) -> Result<Self, RleError<&[u8]>> {
+        Ok(RleReader::new(bytes, u8::MAX)
+            .read_pattern()?
+            .map(multi_node_from_planes))
+    }
+}
+
+fn node_from_planes(planes: Vec<BlockSet>) -> Node {
+    planes
+        .into_iter()
+        .next()
+        .map_or_else(|| Node::empty(0), BlockSet::into_node)
+}
+fn multi_node_from_planes(planes: Vec<BlockSet>) -> MultiNode {
+    if planes.is_empty() {
+        return MultiNode::empty(0);
+    }
+    MultiNode::new(planes.into_iter().map(BlockSet::into_node).collect())
+}
+
+// formatting
+
+/// Golly keeps lines to at most 70 characters
+const MAX_LINE_LEN: usize = 70;
+
+struct RleWriter<W> {
+    write: W,
+    multi_state: bool,
+    line_len: usize,
+}
+impl<W: Write> RleWriter<W> {
+    fn new(write: W, multi_state: bool) -> Self {
+        Self {
+            write,
+            multi_state,
+            line_len: 0,
+        }
+    }
+    fn write<N>(mut self, pattern: &Pattern<N>, runs: &[Run]) -> Result<(), IoError> {
+        if let Some(name) = &pattern.name {
+            writeln!(self.write, "#N {name}")?;
+        }
+        if let Some(author) = &pattern.author {
+            writeln!(self.write, "#O {author}")?;
+        }
+        // comments with several lines are written as several comments
+        for line in pattern
+            .comments
+            .iter()
+            .flat_map(|comment| comment.split('\n'))
+        {
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                writeln!(self.write, "#C")?;
+            } else {
+                writeln!(self.write, "#C {line}")?;
+            }
+        }
+
+        let west = runs.iter().map(|run| run.pos.x).min().unwrap_or(0);
+        let east = runs
+            .iter()
+            .map(|run| run.pos.x + run.len)
+            .max()
+            .unwrap_or(0);
+        let north = runs.first().map_or(0, |run| run.pos.y);
+        let south = runs.last().map_or(0, |run| run.pos.y + 1);
+        let has_generation = pattern.generation != BigUint::default();
+        if !runs.is_empty() || has_generation {
+            write!(self.write, "#CXRLE Pos={west},{north}")?;
+            if has_generation {
+                write!(self.write, " Gen={}", pattern.generation)?;
+            }
+            writeln!(self.write)?;
+        }
+        write!(self.write, "x = {}, y = {}", east - west, south - north)?;
+        if let Some(rule) = pattern.rule_name() {
+            write!(self.write, ", rule = {rule}")?;
+        }
+        writeln!(self.write)?;
+
+        let mut at = Pos::new(west, north);
+        for run in runs {
+            if run.pos.y > at.y {
+                self.write_token(run.pos.y - at.y, "$")?;
+                at = Pos::new(west, run.pos.y);
+            }
+            if run.pos.x > at.x {
+                self.write_token(run.pos.x - at.x, self.dead())?;
+            }
+            let state = self.state(run.state);
+            self.write_token(run.len, &state)?;
+            at.x = run.pos.x + run.len;
+        }
+        self.write_token(1, "!")?;
+        writeln!(self.write)
+    }
+    fn dead(&self) -> &'static str {
+        if self.multi_state {
+            "."
+        } else {
+            "b"
+        }
+    }
+    fn state(&self, state: u8) -> String {
+        if !self.multi_state {
+            return "o".to_owned();
+        }
+        let letter = |i: u8| char::from(b'A' + i);
+        match state {
+            0 => ".".to_owned(),
+            1..=24 => letter(state - 1).to_string(),
+            _ => {
+                let prefix = char::from(b'p' + (state - 25) / 24);
+                format!("{prefix}{}", letter((state - 25) % 24))
+            }
+        }
+    }
+    /// a count of 1 isn't written, and tokens are never split across lines
+    fn write_token(&mut self, count: i64, token: &str) -> Result<(), IoError> {
+        let token = if count == 1 {
+            token.to_owned()
+        } else {
+            format!("{count}{token}")
+        };
+        if self.line_len + token.len() > MAX_LINE_LEN {
+            writeln!(self.write)?;
+            self.line_len = 0;
+        }
+        self.line_len += token.len();
+        self.write.write_all(token.as_bytes())
+    }
+}
+
+// parsing
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RleErrorKind {
+    InvalidHeader,
+    InvalidContent,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RleErrorHint {
+    InvalidHeader,
+    InvalidPos,
+    InvalidGeneration,
+    CountTooLarge,
+    InvalidState,
+    StateTooLarge,
+    NotTwoState,
+    InvalidChar,
+}
+
+#[derive(Clone, Copy)]
+struct RleErrorData<S> {
+    line: LineInfo<S>,
+    hint: RleErrorHint,
+}
+#[derive(Clone)]
+pub struct RleError<S>(Box<RleErrorData<S>>);
+impl<S> RleError<S> {
+    fn new(line: LineInfo<S>, hint: RleErrorHint) -> Self {
+        Self(Box::new(RleErrorData { line, hint }))
+    }
+    pub fn kind(&self) -> RleErrorKind {
+        match self.0.hint {
+            RleErrorHint::InvalidHeader
+            | RleErrorHint::InvalidPos
+            | RleErrorHint::InvalidGeneration => RleErrorKind::InvalidHeader,
+            _ => RleErrorKind::InvalidContent,
+        }
+    }
+    pub fn line_src(&self) -> &S {
+        &self.0.line.line_src
+    }
+    /// 0 based line index
+    pub fn line(&self) -> usize {
+        self.0.line.line
+    }
+    /// 0 based column index
+    pub fn column(&self) -> usize {
+        self.0.line.column
+    }
+    fn hint_code(&self) -> &RleErrorHint {
+        &self.0.hint
+    }
+    pub fn hint(&self) -> &'static str {
+        match self.hint_code() {
+            RleErrorHint::InvalidHeader => "The header must look like x = 3, y = 3, rule = B3/S23",
+            RleErrorHint::InvalidPos => "The position in #CXRLE must look like Pos=-3,5",
+            RleErrorHint::InvalidGeneration => {
+                "The generation in #CXRLE must look like Gen=1024"
+            }
+            RleErrorHint::CountTooLarge => "Run count is too large",
+            RleErrorHint::InvalidState => {
+                "Multi-state cells are '.' or 'A' to 'X', with 'p' to 'y' before them for states above 24"
+            }
+            RleErrorHint::StateTooLarge => "States must be less than 256",
+            RleErrorHint::NotTwoState => "Two-state patterns only have 'b' and 'o' cells",
+            RleErrorHint::InvalidChar => "Invalid character",
+        }
+    }
+}
+impl RleError<&[u8]> {
+    pub fn into_owned(self) -> RleError<Vec<u8>> {
+        RleError(Box::new(RleErrorData {
+            line: self.0.line.into_owned(),
+            hint: self.0.hint,
+        }))
+    }
+}
+impl<S: AsRef<[u8]>> Debug for RleError<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RleError")
+            .field("line", &self.line())
+            .field("column", &self.column())
+            .field(
+                "line_src",
+                &String::from_utf8_lossy(self.line_src().as_ref()),
+            )
+            .field("hint", &self.hint_code())
+            .finish()
+    }
+}
+impl<S: AsRef<[u8]>> Display for RleError<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let line = self.line() + 1;
+        let line_src = String::from_utf8_lossy(self.line_src().as_ref());
+        let column = self.column() + 1;
+        let mark = "^";
+        let hint = self.hint();
+        writeln!(
+            f,
+            "Failed to parse RLE on line {line}:\n{line_src}\n{mark:>column$}\n{hint}\n"
+        )
+    }
+}
+impl<S: AsRef<[u8]>> Error for RleError<S> {}
+
+type RleResult<'src, T> = Result<T, RleError<&'src [u8]>>;
+
+struct RleReader<'src> {
+    src: &'src [u8],
+    at: usize,
+    /// the largest state that can be read, so two-state patterns reject multi-state cells
+    max_state: u8,
+    /// start of each `#` line before the header, only parsed if the metadata is asked for
+    metadata: Vec<usize>,
+    /// where the rule is in the header, only parsed if the metadata is asked for
+    rule: Option<(usize, usize)>,
+    /// the north west cell from a `#CXRLE` line
+    pos: Option<Pos>,
+    /// the width and height from the header
+    size: Option<(i64, i64)>,
+}
+impl<'src> RleReader<'src> {
+    fn new(src: &'src [u8], max_state: u8) -> Self {
+        Self {
+            src,
+            at: 0,
+            max_state,
+            metadata: Vec::new(),
+            rule: None,
+            pos: None,
+            size: None,
+        }
+    }
+
+    /// each bit-plane of the states
+    fn read(&mut self) -> RleResult<'src, Vec<BlockSet>> {
+        self.read_header()?;
+        self.read_body()
+    }
+    fn read_pattern(mut self) -> RleResult<'src, Pattern<Vec<BlockSet>>> {
+        let mut pattern = Pattern::new(self.read()?);
+        if let Some((start, end)) = self.rule {
+            pattern.rule = Some(self.parse_rule(start, end)?);
+        }
+        for &line_at in &self.metadata {
+            let end = self.line_end(line_at);
+            let line = &self.src[line_at..end];
+            if line.starts_with(b"#CXRLE") {
+                if let Some((at, generation)) = self.cxrle_value(line_at, "Gen=") {
+                    let generation = Some(generation)
+                        .filter(|generation| generation.iter().all(u8::is_ascii_digit))
+                        .and_then(|generation| BigUint::parse_bytes(generation, 10));
+                    let Some(generation) = generation else {
+                        return self.fail_at(at, RleErrorHint::InvalidGeneration);
+                    };
+                    pattern.generation = generation;
+                }
+                continue;
+            }
+            let Some(&tag @ (b'N' | b'O' | b'C' | b'c' | b'r')) = line.get(1) else {
+                continue;
+            };
+            let at = line_at + 2;
+            if tag == b'r' {
+                // older files have the rule in a `#r` line instead of the header
+                if pattern.rule.is_none() {
+                    pattern.rule = Some(self.parse_rule(at, end)?);
+                }
+                continue;
+            }
+            let text = match std::str::from_utf8(&self.src[at..end]) {
+                Ok(text) => text.strip_prefix(' ').unwrap_or(text).to_owned(),
+                Err(err) => return self.fail_at(at + err.valid_up_to(), RleErrorHint::InvalidChar),
+            };
+            match tag {
+                b'N' => pattern.name = Some(text),
+                b'O' => pattern.author = Some(text),
+                _ => pattern.comments.push(text),
+            }
+        }
+        Ok(pattern)
+    }
+    /// rules that can't be parsed are kept as text, see `Pattern::rule`
+    fn parse_rule(&self, start: usize, end: usize) -> RleResult<'src, Result<Rule, String>> {
+        let rule = match std::str::from_utf8(&self.src[start..end]) {
+            Ok(rule) => rule.trim(),
+            Err(err) => return self.fail_at(start + err.valid_up_to(), RleErrorHint::InvalidChar),
+        };
+        Ok(rule.parse().map_err(|_| rule.to_owned()))
+    }
+
+    /// reads the `#` lines and the `x = .., y = ..` line, which may be missing
+    fn read_header(&mut self) -> RleResult<'src, ()> {
+        loop {
+            self.skip_space();
+            match self.peak() {
+                Some(b'\n' | b'\r') => self.consume(),
+                Some(b'#') => {
+                    self.metadata.push(self.at);
+                    if self.src[self.at..].starts_with(b"#CXRLE") {
+                        self.pos = self.read_pos()?;
+                    }
+                    self.at = self.line_end(self.at);
+                }
+                Some(b'x') => return self.read_size_line(),
+                _ => return Ok(()),
+            }
+        }
+    }
+    fn read_pos(&self) -> RleResult<'src, Option<Pos>> {
+        let Some((at, pos)) = self.cxrle_value(self.at, "Pos=") else {
+            return Ok(None);
+        };
+        let parse = |s: &[u8]| std::str::from_utf8(s).ok()?.parse::<i64>().ok();
+        let mut coordinates = pos.splitn(2, |&b| b == b',');
+        match (
+            coordinates.next().and_then(parse),
+            coordinates.next().and_then(parse),
+        ) {
+            // the cells have to fit in the largest node
+            (Some(x), Some(y))
+                if (-MAX_COORD..MAX_COORD).contains(&x) && (-MAX_COORD..MAX_COORD).contains(&y) =>
+            {
+                Ok(Some(Pos::new(x, y)))
+            }
+            _ => self.fail_at(at, RleErrorHint::InvalidPos),
+        }
+    }
+    /// the value after `key` in the `#CXRLE` line starting at `line_at`, and where the value starts
+    fn cxrle_value(&self, line_at: usize, key: &str) -> Option<(usize, &'src [u8])> {
+        let end = self.line_end(line_at);
+        let mut at = line_at;
+        for word in self.src[line_at..end].split(u8::is_ascii_whitespace) {
+            if let Some(value) = word.strip_prefix(key.as_bytes()) {
+                return Some((at + key.len(), value));
+            }
+            at += word.len() + 1;
+        }
+        None
+    }
+    fn read_size_line(&mut self) -> RleResult<'src, ()> {
+        let end = self.line_end(self.at);
+        let (mut width, mut height) = (None, None);
+        loop {
+            self.skip_space();
+            let key_at = self.at;
+            while self.peak().is_some_and(|b| b.is_ascii_alphabetic()) {
+                self.consume();
+            }
+            let key = &self.src[key_at..self.at];
+            self.skip_space();
+            if key.is_empty() || self.peak() != Some(b'=') {
+                return self.fail(RleErrorHint::InvalidHeader);
+            }
+            self.consume();
+            self.skip_space();
+            let value_at = self.at;
+            let value_end = self.src[value_at..end]
+                .iter()
+                .position(|&b| b == b',')
+                .map_or(end, |i| value_at + i);
+            let value = std::str::from_utf8(&self.src[value_at..value_end]).unwrap_or("");
+            match key {
+                b"x" | b"y" => {
+                    let size = value.trim().parse::<i64>().ok().filter(|&size| size >= 0);
+                    let Some(size) = size else {
+                        return self.fail_at(value_at, RleErrorHint::InvalidHeader);
+                    };
+                    if key == b"x" {
+                        width = Some(size);
+                    } else {
+                        height = Some(size);
+                    }
+                }
+                b"rule" => self.rule = Some((value_at, value_end)),
+                // such as Golly's `x = 0, y = 0, rule = B3/S23, x0 = 0`
+                _ => {}
+            }
+            self.at = value_end;
+            if self.at == end {
+                break;
+            }
+            self.consume();
+        }
+        self.size = width.zip(height);
+        Ok(())
+    }
+
+    fn read_body(&mut self) -> RleResult<'src, Vec<BlockSet>> {
+        let origin = match (self.pos, self.size) {
+            (Some(pos), _) => pos,
+            (None, Some((width, height))) => Pos::new(-(width / 2), -(height / 2)),
+            (None, None) => Pos::new(0, 0),
+        };
+        let mut planes: Vec<BlockSet> = Vec::new();
+        let mut at = Pos::new(0, 0);
+        loop {
+            let count_at = self.at;
+            let count = self.read_count()?;
+            let Some(b) = self.peak() else {
+                break;
+            };
+            let state_at = self.at;
+            self.consume();
+            let state = match b {
+                b' ' | b'\t' | b'\n' | b'\r' if count.is_none() => continue,
+                b'#' if count.is_none() => {
+                    self.at = self.line_end(self.at);
+                    continue;
+                }
+                b'!' if count.is_none() => break,
+                b'$' => {
+                    let Some(y) = at.y.checked_add(count.unwrap_or(1)) else {
+                        return self.fail_at(count_at, RleErrorHint::CountTooLarge);
+                    };
+                    at = Pos::new(0, y);
+                    continue;
+                }
+                b'b' | b'.' => 0,
+                b'o' => 1,
+                b'A'..=b'X' => b - b'A' + 1,
+                b'p'..=b'y' => {
+                    let Some(letter @ b'A'..=b'X') = self.peak() else {
+                        return self.fail(RleErrorHint::InvalidState);
+                    };
+                    self.consume();
+                    let state = usize::from(b - b'o') * 24 + usize::from(letter - b'A' + 1);
+                    match u8::try_from(state) {
+                        Ok(state) => state,
+                        Err(_) => return self.fail_at(state_at, RleErrorHint::StateTooLarge),
+                    }
+                }
+                _ => return self.fail_at(state_at, RleErrorHint::InvalidChar),
+            };
+            if state > self.max_state {
+                return self.fail_at(state_at, RleErrorHint::NotTwoState);
+            }
+            let len = count.unwrap_or(1);
+            let Some(next_x) = at.x.checked_add(len) else {
+                return self.fail_at(count_at, RleErrorHint::CountTooLarge);
+            };
+            if state != 0 {
+                let west = offset_pos(origin, at.x, at.y);
+                let Some(west) = west.filter(|_| offset_pos(origin, next_x - 1, at.y).is_some())
+                else {
+                    return self.fail_at(count_at, RleErrorHint::CountTooLarge);
+                };
+                let plane_count = (u8::BITS - state.leading_zeros()) as usize;
+                if planes.len() < plane_count {
+                    planes.resize_with(plane_count, BlockSet::default);
+                }
+                for (i, plane) in planes.iter_mut().enumerate() {
+                    if state & (1 << i) != 0 {
+                        plane.set_row(west, len);
+                    }
+                }
+            }
+            at.x = next_x;
+        }
+        Ok(planes)
+    }
+    fn read_count(&mut self) -> RleResult<'src, Option<i64>> {
+        let count_at = self.at;
+        let mut count = None;
+        while let Some(b @ b'0'..=b'9') = self.peak() {
+            self.consume();
+            count = count
+                .unwrap_or(0_i64)
+                .checked_mul(10)
+                .and_then(|count| count.checked_add(i64::from(b - b'0')));
+            if count.is_none() {
+                return self.fail_at(count_at, RleErrorHint::CountTooLarge);
+            }
+        }
+        Ok(count)
+    }
+
+    /// the end of the line starting at `at`, before any line terminator
+    fn line_end(&self, at: usize) -> usize {
+        self.src[at..]
+            .iter()
+            .position(|b| matches!(b, b'\n' | b'\r'))
+            .map_or(self.src.len(), |len| at + len)
+    }
+    fn skip_space(&mut self) {
+        while let Some(b' ' | b'\t') = self.peak() {
+            self.consume();
+        }
+    }
+    fn peak(&self) -> Option<u8> {
+        self.src.get(self.at).copied()
+    }
+    fn consume(&mut self) {
+        self.at += 1;
+    }
+
+    fn fail<T>(&self, hint: RleErrorHint) -> RleResult<'src, T> {
+        self.fail_at(self.at, hint)
+    }
+    fn fail_at<T>(&self, at: usize, hint: RleErrorHint) -> RleResult<'src, T> {
+        Err(RleError::new(LineInfo::from_src_offset(self.src, at), hint))
+    }
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use unindent::unindent;
+
+    use crate::{test_node, MultiNode, Node, Pattern, Population, Pos, Rect, Rule};
+
+    #[test]
+    fn glider() {
+        let glider = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        let fmt = unindent(
+            "
+            #CXRLE Pos=0,0
+            x = 3, y = 3, rule = B3/S23
+            bo$2bo$3o!
+            ",
+        );
+        assert_eq!(glider.write_rle_to_string(), fmt);
+        assert_eq!(Node::read_rle(fmt.as_bytes()).unwrap(), glider);
+
+        // without a position the pattern is centered
+        let lifewiki = "#N Glider\n#O Richard K. Guy\nx = 3, y = 3, rule = B3/S23\nbob$2bo$3o!";
+        let read = Pattern::<Node>::read_rle(lifewiki.as_bytes()).unwrap();
+        assert_eq!(
+            read.node,
+            test_node! {"
+                ................
+                ................
+                ................
+                ................
+                ................
+                ................
+                ................
+                ........o.......
+                .........o......
+                .......ooo......
+                ................
+                ................
+                ................
+                ................
+                ................
+                ................
+                "}
+        );
+        assert_eq!(read.name.as_deref(), Some("Glider"));
+        assert_eq!(read.author.as_deref(), Some("Richard K. Guy"));
+        assert_eq!(read.rule, Some(Ok(Rule::LIFE)));
+
+        // and without a header the north west cell is at the origin
+        assert_eq!(Node::read_rle(b"bo$2bo$3o!").unwrap(), glider);
+    }
+
+    #[test]
+    fn runs_and_lines() {
+        let node = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .....ooo....o...
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ........o.......
+            ................
+            ................
+            "};
+        let fmt = unindent(
+            "
+            #CXRLE Pos=-3,-2
+            x = 8, y = 8, rule = B3/S23
+            3o4bo7$3bo!
+            ",
+        );
+        assert_eq!(node.write_rle_to_string(), fmt);
+        assert_eq!(Node::read_rle(fmt.as_bytes()).unwrap(), node);
+
+        // lines are kept under 70 characters
+        let row = (0..60).fold(Node::empty(0), |node, x| node.set(Pos::new(x * 2, 0), true));
+        let out = row.write_rle_to_string();
+        assert!(out.lines().all(|line| line.len() <= 70));
+        assert_eq!(Node::read_rle(out.as_bytes()).unwrap(), row);
+        assert_eq!(
+            Node::empty(0).write_rle_to_string(),
+            "x = 0, y = 0, rule = B3/S23\n!\n"
+        );
+    }
+
+    #[test]
+    fn metadata() {
+        let pattern = Pattern::new(Node::empty(0).set(Pos::new(10, 10), true))
+            .with_rule("B36/S23".parse().unwrap())
+            .with_generation(12_345_u32)
+            .with_name("cell")
+            .with_author("someone")
+            .with_comment("a single cell\nover two lines");
+        let mut out = Vec::new();
+        pattern.write_rle(&mut out).unwrap();
+        let fmt = unindent(
+            "
+            #N cell
+            #O someone
+            #C a single cell
+            #C over two lines
+            #CXRLE Pos=10,10 Gen=12345
+            x = 1, y = 1, rule = B36/S23
+            o!
+            ",
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), fmt);
+        let read = Pattern::<Node>::read_rle(fmt.as_bytes()).unwrap();
+        assert_eq!(
+            read,
+            Pattern {
+                comments: vec!["a single cell".to_owned(), "over two lines".to_owned()],
+                ..pattern
+            }
+        );
+
+        // an older `#r` line, and unknown rules are kept as they were written
+        let read = Pattern::<Node>::read_rle(b"#r 23/3\nx = 1, y = 1\no!").unwrap();
+        assert_eq!(read.rule, Some(Ok(Rule::LIFE)));
+        let unknown = "x = 1, y = 1, rule = LifeHistory\no!\n";
+        let read = Pattern::<Node>::read_rle(unknown.as_bytes()).unwrap();
+        assert_eq!(read.rule, Some(Err("LifeHistory".to_owned())));
+        let mut out = Vec::new();
+        read.write_rle(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("#CXRLE Pos=0,0\n{unknown}")
+        );
+    }
+
+    #[test]
+    fn multi_state() {
+        let node = MultiNode::empty(0)
+            .set(Pos::new(0, 0), 1)
+            .set(Pos::new(1, 0), 2)
+            .set(Pos::new(2, 0), 2)
+            .set(Pos::new(0, 1), 24)
+            .set(Pos::new(2, 1), 25)
+            .set(Pos::new(3, 1), 255);
+        let fmt = unindent(
+            "
+            #CXRLE Pos=0,0
+            x = 4, y = 2
+            A2B$X.pAyO!
+            ",
+        );
+        assert_eq!(node.write_rle_to_string(), fmt);
+        assert_eq!(MultiNode::read_rle(fmt.as_bytes()).unwrap(), node);
+
+        // two-state cells can be read as multi-state
+        let read = MultiNode::read_rle(b"bo$2bo$3o!").unwrap();
+        assert_eq!(
+            read,
+            MultiNode::from(Node::read_rle(b"bo$2bo$3o!").unwrap())
+        );
+        // but multi-state cells can't be read as two-state
+        let err = Node::read_rle(b"A2B!").unwrap_err();
+        assert_eq!((err.line(), err.column()), (0, 2));
+    }
+
+    #[test]
+    fn sparse() {
+        // far apart cells only build the blocks they are in
+        let fmt = b"#CXRLE Pos=-1000000000000,0\nx = 2000000000001, y = 3000000000001\no1999999999999bo3000000000000$o!";
+        let node = Node::read_rle(fmt).unwrap();
+        let expected = Node::empty(0)
+            .set(Pos::new(-1_000_000_000_000, 0), true)
+            .set(Pos::new(1_000_000_000_000, 0), true)
+            .set(Pos::new(-1_000_000_000_000, 3_000_000_000_000), true);
+        assert_eq!(node, expected.center_at_depth(node.depth()));
+        assert_eq!(
+            Node::read_rle(node.write_rle_to_string().as_bytes()).unwrap(),
+            node
+        );
+    }
+
+    #[test]
+    fn long_run() {
+        // a long run builds shared nodes instead of each block in it
+        let node = Node::read_rle(b"1000000000000o$3o!").unwrap();
+        assert_eq!(node.population(), 1_000_000_000_003);
+        assert_eq!(
+            node.bounding_rect(),
+            Rect::min_max(Pos::new(0, 0), Pos::new(999_999_999_999, 1))
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |s: &str| {
+            let err = Pattern::<MultiNode>::read_rle(s.as_bytes()).unwrap_err();
+            (err.line(), err.column())
+        };
+        assert_eq!(error("x = 3 y = 3\no!"), (0, 4));
+        assert_eq!(error("x = a, y = 3\no!"), (0, 4));
+        assert_eq!(error("#CXRLE Pos=1\nx = 1, y = 1\no!"), (0, 11));
+        assert_eq!(error("#CXRLE Gen=1a\nx = 1, y = 1\no!"), (0, 11));
+        assert_eq!(error("x = 1, y = 1\no\n2z!"), (2, 1));
+        assert_eq!(error("x = 1, y = 1\npZ!"), (1, 1));
+        assert_eq!(error("x = 1, y = 1\nyP!"), (1, 0));
+        assert_eq!(error("x = 1, y = 1\n99999999999999999999o!"), (1, 0));
+        // cells that don't fit in the largest node
+        assert_eq!(error("9223372036854775807$9223372036854775807$o!"), (0, 20));
+        assert_eq!(error("4611686018427387904$o!"), (0, 20));
+        assert_eq!(error("4611686018427387903b2o!"), (0, 20));
+        assert_eq!(error("#CXRLE Pos=9223372036854775806,0\n3o!"), (0, 11));
+        assert_eq!(error("#CXRLE Pos=4611686018427387904,0\no!"), (0, 11));
+        assert!(Node::read_rle(b"#CXRLE Pos=4611686018427387903,0\no!").is_ok());
+    }
+}
@@ -4,7 +4,52 @@ use crate::{Block, DepthQuad, Node, Quad};
 
 // TODO many (all after setup?) Node ops just delegate, could use a macro to generalize
 
+/// the 8 ways to rotate and reflect a node, each one is the same as calling the method with the same name
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Transform {
+    Identity,
+    RotateCw,
+    Rotate180,
+    RotateCcw,
+    FlipH,
+    FlipV,
+    /// swaps x and y, so the north east cell moves to the south west
+    FlipDiagonal,
+    /// the other diagonal, so the north west cell moves to the south east
+    FlipAntiDiagonal,
+}
+impl Transform {
+    pub const ALL: [Transform; 8] = [
+        Transform::Identity,
+        Transform::RotateCw,
+        Transform::Rotate180,
+        Transform::RotateCcw,
+        Transform::FlipH,
+        Transform::FlipV,
+        Transform::FlipDiagonal,
+        Transform::FlipAntiDiagonal,
+    ];
+    pub const ROTATIONS: [Transform; 4] = [
+        Transform::Identity,
+        Transform::RotateCw,
+        Transform::Rotate180,
+        Transform::RotateCcw,
+    ];
+}
+
 impl Node {
+    pub fn transform(&self, transform: Transform) -> Node {
+        match transform {
+            Transform::Identity => self.clone(),
+            Transform::RotateCw => self.rotate_cw(),
+            Transform::Rotate180 => self.rotate_180(),
+            Transform::RotateCcw => self.rotate_ccw(),
+            Transform::FlipH => self.flip_h(),
+            Transform::FlipV => self.flip_v(),
+            Transform::FlipDiagonal => self.rotate_cw().flip_h(),
+            Transform::FlipAntiDiagonal => self.rotate_ccw().flip_h(),
+        }
+    }
     pub fn flip_h(&self) -> Node {
         match self.depth_quad() {
             DepthQuad::Leaf(leaf) => Node::new_leaf(leaf.flip_h()),
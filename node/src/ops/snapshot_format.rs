@@ -0,0 +1,572 @@
+//! a binary format for checkpointing huge nodes, which is much faster to write and read than macrocell
+//!
+//! all numbers are little endian, and the file is laid out as
+//! - a header with the magic bytes, version, population, number of blocks, number of nodes, and length of the nodes
+//! - each distinct block as its 64 bit rows
+//! - each distinct node as its depth followed by the varint index of each child, children before their parents and the root last
+//! - the offset of every `INDEX_STRIDE`th node, so a node can be found without reading the nodes before it
+//! - a crc32 of everything before it
+//!
+//! children are 1 based indices into the blocks for leaf nodes or into the nodes for inner nodes, where 0 is empty
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::io::{Error as IoError, Write};
+use std::num::NonZeroU8;
+
+use crc32fast::Hasher;
+
+use crate::{Block, DepthQuad, Node, Population, Pos, Quad, Quadrant};
+
+const MAGIC: [u8; 8] = *b"\x89NODE\r\n\x1a";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 44;
+/// how many nodes there are between each offset in the index
+const INDEX_STRIDE: usize = 64;
+/// a depth and 4 varints of at most 10 bytes
+const MAX_RECORD_LEN: usize = 41;
+
+impl Node {
+    /// writes the distinct blocks and nodes once each, so the snapshot is about as large as the node table
+    pub fn write_snapshot(&self, write: impl Write) -> Result<(), IoError> {
+        SnapshotWriter::default().write(self, write)
+    }
+    pub fn write_snapshot_to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_snapshot(&mut out).expect("valid bytes");
+        out
+    }
+    /// checks the checksum and population before returning the node, see `SnapshotView` to only read part of a snapshot
+    pub fn read_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let view = SnapshotView::new(bytes)?;
+        view.verify()?;
+        let node = view.node()?;
+        if node.population() != view.population() {
+            return Err(SnapshotError::new(
+                12,
+                SnapshotErrorKind::PopulationMismatch,
+            ));
+        }
+        Ok(node)
+    }
+}
+
+// writing
+
+#[derive(Default)]
+struct SnapshotWriter<'n> {
+    blocks: Vec<Block>,
+    block_indices: HashMap<Block, u64>,
+    /// children before their parents
+    nodes: Vec<&'n Node>,
+    node_indices: HashMap<&'n Node, u64>,
+}
+impl<'n> SnapshotWriter<'n> {
+    fn write(mut self, root: &'n Node, write: impl Write) -> Result<(), IoError> {
+        // an empty root is still written so the depth is kept
+        if self.add_node(root) == 0 {
+            self.nodes.push(root);
+        }
+        let mut index = Vec::new();
+        let mut nodes_len = 0;
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i % INDEX_STRIDE == 0 {
+                index.push(nodes_len as u64);
+            }
+            nodes_len += self.record(node).1;
+        }
+
+        let mut write = ChecksumWriter::new(write);
+        write.write_all(&MAGIC)?;
+        write.write_all(&VERSION.to_le_bytes())?;
+        write.write_all(&root.population().to_le_bytes())?;
+        write.write_all(&(self.blocks.len() as u64).to_le_bytes())?;
+        write.write_all(&(self.nodes.len() as u64).to_le_bytes())?;
+        write.write_all(&(nodes_len as u64).to_le_bytes())?;
+        for block in &self.blocks {
+            write.write_all(&block.to_rows().to_le_bytes())?;
+        }
+        for node in &self.nodes {
+            let (record, len) = self.record(node);
+            write.write_all(&record[..len])?;
+        }
+        for offset in index {
+            write.write_all(&offset.to_le_bytes())?;
+        }
+        let checksum = write.hasher.finalize();
+        write.write.write_all(&checksum.to_le_bytes())
+    }
+    /// 1 based index of `node` after adding it and its children, or 0 if it is empty
+    fn add_node(&mut self, node: &'n Node) -> u64 {
+        if node.is_empty() {
+            return 0;
+        }
+        if let Some(&index) = self.node_indices.get(node) {
+            return index;
+        }
+        match node.depth_quad() {
+            DepthQuad::Leaf(leaf) => {
+                for &block in leaf.iter() {
+                    self.add_block(block);
+                }
+            }
+            DepthQuad::Inner(_, inner) => {
+                for child in inner.iter() {
+                    self.add_node(child);
+                }
+            }
+        }
+        self.nodes.push(node);
+        let index = self.nodes.len() as u64;
+        self.node_indices.insert(node, index);
+        index
+    }
+    fn add_block(&mut self, block: Block) -> u64 {
+        if block.is_empty() {
+            return 0;
+        }
+        let next = self.blocks.len() as u64 + 1;
+        *self.block_indices.entry(block).or_insert_with(|| {
+            self.blocks.push(block);
+            next
+        })
+    }
+    /// the encoded node and its length, after its children were added
+    fn record(&self, node: &Node) -> ([u8; MAX_RECORD_LEN], usize) {
+        let children = match node.depth_quad() {
+            DepthQuad::Leaf(leaf) => {
+                leaf.map(|block| self.block_indices.get(&block).copied().unwrap_or(0))
+            }
+            DepthQuad::Inner(_, inner) => inner
+                .as_ref()
+                .map(|child| self.node_indices.get(child).copied().unwrap_or(0)),
+        };
+        let mut record = [0; MAX_RECORD_LEN];
+        record[0] = node.depth();
+        let mut len = 1;
+        for mut child in children {
+            loop {
+                #[allow(clippy::cast_possible_truncation)] // masked to 7 bits
+                let byte = (child & 0x7f) as u8;
+                child >>= 7;
+                if child == 0 {
+                    record[len] = byte;
+                    len += 1;
+                    break;
+                }
+                record[len] = byte | 0x80;
+                len += 1;
+            }
+        }
+        (record, len)
+    }
+}
+
+struct ChecksumWriter<W> {
+    write: W,
+    hasher: Hasher,
+}
+impl<W: Write> ChecksumWriter<W> {
+    fn new(write: W) -> Self {
+        Self {
+            write,
+            hasher: Hasher::new(),
+        }
+    }
+}
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        let len = self.write.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+    fn flush(&mut self) -> Result<(), IoError> {
+        self.write.flush()
+    }
+}
+
+// reading
+
+/// a snapshot that is only read as it is used, which avoids reading all of a memory-mapped file to look at part of it
+///
+/// only the header is checked up front, each node is checked as it is read,
+/// and the checksum is only checked by `verify` since that reads the whole snapshot
+#[derive(Clone, Copy, Debug)]
+pub struct SnapshotView<'a> {
+    bytes: &'a [u8],
+    population: u64,
+    block_count: usize,
+    node_count: usize,
+    blocks: &'a [u8],
+    nodes: &'a [u8],
+    index: &'a [u8],
+}
+impl<'a> SnapshotView<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, SnapshotError> {
+        let Some(header) = bytes.get(..HEADER_LEN) else {
+            return Err(SnapshotError::new(
+                bytes.len(),
+                SnapshotErrorKind::InvalidLength,
+            ));
+        };
+        if header[..8] != MAGIC {
+            return Err(SnapshotError::new(0, SnapshotErrorKind::InvalidMagic));
+        }
+        let u64_at =
+            |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().expect("8 bytes"));
+        let version = u32::from_le_bytes(header[8..12].try_into().expect("4 bytes"));
+        if version != VERSION {
+            return Err(SnapshotError::new(8, SnapshotErrorKind::UnsupportedVersion));
+        }
+        let invalid_length = SnapshotError::new(bytes.len(), SnapshotErrorKind::InvalidLength);
+        let to_usize = |n: u64| usize::try_from(n).map_err(|_| invalid_length);
+        let block_count = to_usize(u64_at(20))?;
+        let node_count = to_usize(u64_at(28))?;
+        let nodes_len = to_usize(u64_at(36))?;
+        let index_len = node_count.div_ceil(INDEX_STRIDE) * 8;
+        let len = block_count
+            .checked_mul(8)
+            .and_then(|blocks_len| blocks_len.checked_add(nodes_len))
+            .and_then(|len| len.checked_add(HEADER_LEN + index_len + 4));
+        if node_count == 0 || len != Some(bytes.len()) {
+            return Err(invalid_length);
+        }
+        let (blocks, rest) = bytes[HEADER_LEN..].split_at(block_count * 8);
+        let (nodes, rest) = rest.split_at(nodes_len);
+        Ok(Self {
+            bytes,
+            population: u64_at(12),
+            block_count,
+            node_count,
+            blocks,
+            nodes,
+            index: &rest[..index_len],
+        })
+    }
+    /// the population of the whole snapshot from the header
+    pub fn population(&self) -> u64 {
+        self.population
+    }
+    pub fn depth(&self) -> Result<u8, SnapshotError> {
+        Ok(self.record(self.node_count - 1)?.depth)
+    }
+    /// checks the checksum, which reads the whole snapshot
+    pub fn verify(&self) -> Result<(), SnapshotError> {
+        let (body, checksum) = self.bytes.split_at(self.bytes.len() - 4);
+        if crc32fast::hash(body).to_le_bytes() == checksum {
+            Ok(())
+        } else {
+            Err(SnapshotError::new(
+                body.len(),
+                SnapshotErrorKind::InvalidChecksum,
+            ))
+        }
+    }
+    /// only reads the nodes that contain `pos`, like `Node::get`
+    pub fn get(&self, pos: Pos) -> Result<bool, SnapshotError> {
+        let mut record = self.record(self.node_count - 1)?;
+        let half_width = 1_i64 << (record.depth + Node::MIN_WIDTH_LOG2 - 1);
+        if pos.x >= half_width || pos.y >= half_width || pos.x < -half_width || pos.y < -half_width
+        {
+            return Ok(false);
+        }
+        let mut pos = pos;
+        let mut half_width = half_width;
+        while record.depth > 0 {
+            let child = record.children[Quadrant::from_pos(pos)];
+            if child == 0 {
+                return Ok(false);
+            }
+            pos = pos.re_center(half_width / 2);
+            half_width /= 2;
+            let parent = record;
+            record = self.record(to_index(child))?;
+            if record.depth + 1 != parent.depth {
+                return Err(SnapshotError::new(
+                    parent.at,
+                    SnapshotErrorKind::InvalidChild,
+                ));
+            }
+        }
+        let leaf = self.leaf(&record);
+        Ok(Node::new_leaf(leaf).get(pos))
+    }
+    /// reads every node
+    pub fn node(&self) -> Result<Node, SnapshotError> {
+        let mut nodes: Vec<Node> = Vec::with_capacity(self.node_count.min(self.nodes.len()));
+        let mut at = 0;
+        for i in 0..self.node_count {
+            let record = self.record_at(i, at)?;
+            at = record.end;
+            let node = if record.depth == 0 {
+                Node::new_leaf(self.leaf(&record))
+            } else {
+                let child_depth = record.depth - 1;
+                let child = |child: u64| match child {
+                    0 => Ok(Node::empty(child_depth)),
+                    child => match nodes.get(to_index(child)) {
+                        Some(node) if node.depth() == child_depth => Ok(node.clone()),
+                        _ => Err(SnapshotError::new(
+                            record.at,
+                            SnapshotErrorKind::InvalidChild,
+                        )),
+                    },
+                };
+                let children = Quad {
+                    nw: child(record.children.nw)?,
+                    ne: child(record.children.ne)?,
+                    sw: child(record.children.sw)?,
+                    se: child(record.children.se)?,
+                };
+                let depth = NonZeroU8::new(record.depth).expect("inner node");
+                Node::new_depth_inner(depth, children)
+            };
+            nodes.push(node);
+        }
+        Ok(nodes.pop().expect("at least one node"))
+    }
+
+    /// finds the `i`th node from the nearest offset in the index
+    fn record(&self, i: usize) -> Result<Record, SnapshotError> {
+        let index_at = i / INDEX_STRIDE * 8;
+        let offset = u64::from_le_bytes(
+            self.index[index_at..index_at + 8]
+                .try_into()
+                .expect("8 bytes"),
+        );
+        let index_at = self.bytes.len() - 4 - self.index.len() + index_at;
+        let mut at = usize::try_from(offset)
+            .map_err(|_| SnapshotError::new(index_at, SnapshotErrorKind::InvalidLength))?;
+        for skipped in i / INDEX_STRIDE * INDEX_STRIDE..i {
+            at = self.record_at(skipped, at)?.end;
+        }
+        self.record_at(i, at)
+    }
+    /// reads the `i`th node, which starts at `at` in the nodes
+    fn record_at(&self, i: usize, at: usize) -> Result<Record, SnapshotError> {
+        let offset = HEADER_LEN + self.blocks.len();
+        let Some(&depth) = self.nodes.get(at) else {
+            return Err(SnapshotError::new(
+                offset + at,
+                SnapshotErrorKind::InvalidLength,
+            ));
+        };
+        if depth > Node::MAX_DEPTH {
+            return Err(SnapshotError::new(
+                offset + at,
+                SnapshotErrorKind::InvalidDepth,
+            ));
+        }
+        let mut end = at + 1;
+        let mut children = [0; 4];
+        for child in &mut children {
+            let child_at = end;
+            let mut shift = 0;
+            loop {
+                let Some(&byte) = self.nodes.get(end) else {
+                    return Err(SnapshotError::new(
+                        offset + end,
+                        SnapshotErrorKind::InvalidLength,
+                    ));
+                };
+                end += 1;
+                let bits = u64::from(byte & 0x7f);
+                if shift > 63 || bits << shift >> shift != bits {
+                    return Err(SnapshotError::new(
+                        offset + child_at,
+                        SnapshotErrorKind::InvalidChild,
+                    ));
+                }
+                *child |= bits << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            // children come before their parents, so there can't be any cycles
+            let max = if depth == 0 { self.block_count } else { i };
+            if usize::try_from(*child).map_or(true, |child| child > max) {
+                return Err(SnapshotError::new(
+                    offset + child_at,
+                    SnapshotErrorKind::InvalidChild,
+                ));
+            }
+        }
+        let [nw, ne, sw, se] = children;
+        Ok(Record {
+            at: offset + at,
+            end,
+            depth,
+            children: Quad { nw, ne, sw, se },
+        })
+    }
+    fn leaf(&self, record: &Record) -> Quad<Block> {
+        debug_assert_eq!(record.depth, 0);
+        record.children.map(|child| match child {
+            0 => Block::empty(),
+            child => {
+                let at = to_index(child) * 8;
+                Block::from_rows(u64::from_le_bytes(
+                    self.blocks[at..at + 8].try_into().expect("8 bytes"),
+                ))
+            }
+        })
+    }
+}
+/// a node as it is stored
+#[derive(Clone, Copy)]
+struct Record {
+    /// where the node starts in the snapshot
+    at: usize,
+    /// where the next node starts in the nodes
+    end: usize,
+    depth: u8,
+    children: Quad<u64>,
+}
+/// 0 based index of a 1 based child that was already checked
+#[allow(clippy::cast_possible_truncation)]
+fn to_index(child: u64) -> usize {
+    child as usize - 1
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotErrorKind {
+    InvalidMagic,
+    UnsupportedVersion,
+    InvalidLength,
+    InvalidChecksum,
+    InvalidDepth,
+    InvalidChild,
+    PopulationMismatch,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SnapshotError {
+    offset: usize,
+    kind: SnapshotErrorKind,
+}
+impl SnapshotError {
+    fn new(offset: usize, kind: SnapshotErrorKind) -> Self {
+        Self { offset, kind }
+    }
+    pub fn kind(&self) -> SnapshotErrorKind {
+        self.kind
+    }
+    /// where the error is in the snapshot, in bytes
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    pub fn hint(&self) -> &'static str {
+        match self.kind {
+            SnapshotErrorKind::InvalidMagic => "Not a node snapshot",
+            SnapshotErrorKind::UnsupportedVersion => "Snapshot is from an unsupported version",
+            SnapshotErrorKind::InvalidLength => {
+                "Snapshot is truncated or its sections don't match the header"
+            }
+            SnapshotErrorKind::InvalidChecksum => "Checksum doesn't match, the snapshot is corrupt",
+            SnapshotErrorKind::InvalidDepth => "Node is too large to be handled",
+            SnapshotErrorKind::InvalidChild => {
+                "Child must be an earlier node with a depth exactly 1 less than its parent"
+            }
+            SnapshotErrorKind::PopulationMismatch => "Population doesn't match the header",
+        }
+    }
+}
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let offset = self.offset;
+        let hint = self.hint();
+        writeln!(f, "Failed to read snapshot at byte {offset}:\n{hint}\n")
+    }
+}
+impl Error for SnapshotError {}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use super::{SnapshotErrorKind, SnapshotView, HEADER_LEN};
+    use crate::{Block, Node, Population, Pos};
+
+    fn sparse() -> Node {
+        let cells = [
+            (0, 0),
+            (-1, -1),
+            (7, 3),
+            (-100, 37),
+            (1 << 40, -5),
+            (-(1 << 50), 1 << 45),
+        ];
+        cells.into_iter().fold(Node::empty(0), |node, (x, y)| {
+            node.set(Pos::new(x, y), true)
+        })
+    }
+
+    #[test]
+    fn round_trip() {
+        let b = Block::from_rows(0x80_40_20_10_08_04_02_01);
+        let repeated = Node::new(b, b, Block::empty(), b).expand().expand();
+        for node in [Node::empty(0), Node::empty(30), sparse(), repeated] {
+            let bytes = node.write_snapshot_to_bytes();
+            let read = Node::read_snapshot(&bytes).unwrap();
+            // identical, not just equal, since nodes are hash-consed
+            assert!(std::ptr::eq(read.depth_quad(), node.depth_quad()));
+            assert_eq!(read.population(), node.population());
+        }
+    }
+
+    #[test]
+    fn view() {
+        let node = sparse();
+        let bytes = node.write_snapshot_to_bytes();
+        let view = SnapshotView::new(&bytes).unwrap();
+        assert_eq!(view.depth(), Ok(node.depth()));
+        assert_eq!(view.population(), 6);
+        for pos in [
+            Pos::new(0, 0),
+            Pos::new(1, 0),
+            Pos::new(-1, -1),
+            Pos::new(1 << 40, -5),
+            Pos::new(-(1 << 50), 1 << 45),
+            Pos::new(i64::MAX, i64::MIN),
+        ] {
+            assert_eq!(view.get(pos), Ok(node.get(pos)), "{pos:?}");
+        }
+
+        // more nodes than the index stride
+        let mut big = Node::empty(0);
+        for i in 0..300 {
+            big = big.set(Pos::new(i * 37, i * i), true);
+        }
+        let bytes = big.write_snapshot_to_bytes();
+        let view = SnapshotView::new(&bytes).unwrap();
+        for i in 0..300 {
+            assert_eq!(view.get(Pos::new(i * 37, i * i)), Ok(true));
+            assert_eq!(view.get(Pos::new(i * 37 + 1, i * i)), Ok(false));
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let bytes = sparse().write_snapshot_to_bytes();
+        let kind = |bytes: &[u8]| Node::read_snapshot(bytes).unwrap_err().kind();
+        assert_eq!(kind(&bytes[..10]), SnapshotErrorKind::InvalidLength);
+        assert_eq!(
+            kind(&bytes[..bytes.len() - 1]),
+            SnapshotErrorKind::InvalidLength
+        );
+        let mut changed = bytes.clone();
+        changed[0] = b'[';
+        assert_eq!(kind(&changed), SnapshotErrorKind::InvalidMagic);
+        let mut changed = bytes.clone();
+        changed[8] = 2;
+        assert_eq!(kind(&changed), SnapshotErrorKind::UnsupportedVersion);
+        let mut changed = bytes.clone();
+        changed[HEADER_LEN] ^= 1;
+        let err = Node::read_snapshot(&changed).unwrap_err();
+        assert_eq!(err.kind(), SnapshotErrorKind::InvalidChecksum);
+        assert_eq!(err.offset(), bytes.len() - 4);
+        // the view doesn't check the checksum
+        assert!(SnapshotView::new(&changed).is_ok());
+    }
+}
@@ -0,0 +1,358 @@
+//! runs random soups until they settle and counts the objects left over, like apgsearch
+//!
+//! soups are generated the same way as apgsearch's `C1` soups, so a seed and soup number gives the same soup as it does there,
+//! and the census is written in the same text format that Catagolue shows for a census
+
+use std::collections::BTreeMap;
+use std::io::{Error as IoError, Write};
+use std::ops::Range;
+
+use sha2::{Digest, Sha256};
+
+use super::blocks::BlockSet;
+use crate::{Connectivity, Node, Population, Pos, Rule};
+
+/// what Catagolue calls anything that didn't settle into known objects
+pub const PATHOLOGICAL: &str = "PATHOLOGICAL";
+pub const SOUP_WIDTH: i64 = 16;
+/// how many times the population has to repeat before it counts as periodic
+const REPEATS: u64 = 4;
+/// and how many generations it has to repeat for, so a short period doesn't settle by chance
+const MIN_STABLE_GENERATIONS: u64 = 60;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SoupSearch {
+    seed: String,
+    rule: Rule,
+    max_generations: u64,
+    max_period: u64,
+}
+impl SoupSearch {
+    pub fn new(seed: impl Into<String>) -> Self {
+        Self {
+            seed: seed.into(),
+            rule: Rule::LIFE,
+            max_generations: 10_000,
+            max_period: 60,
+        }
+    }
+    pub fn with_rule(self, rule: Rule) -> Self {
+        Self { rule, ..self }
+    }
+    /// soups that are still changing after this many generations are `PATHOLOGICAL`
+    pub fn with_max_generations(self, max_generations: u64) -> Self {
+        Self {
+            max_generations,
+            ..self
+        }
+    }
+    /// the longest period of the population and of each object
+    pub fn with_max_period(self, max_period: u64) -> Self {
+        Self { max_period, ..self }
+    }
+    pub fn seed(&self) -> &str {
+        &self.seed
+    }
+    pub fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    /// the cells are in `0..16` in both directions
+    ///
+    /// each row is 2 bytes of the SHA-256 of the seed followed by `index`, with the most significant bit to the west
+    pub fn soup(&self, index: u64) -> Node {
+        let digest = Sha256::digest(format!("{}{index}", self.seed));
+        let mut cells = BlockSet::default();
+        for (pos, byte) in (0..).zip(digest) {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    cells.set(Pos::new(bit + 8 * (pos % 2), pos / 2));
+                }
+            }
+        }
+        cells.into_node()
+    }
+    /// the apgcodes of the objects that soup `index` settles into, in no particular order
+    pub fn objects(&self, index: u64) -> Vec<String> {
+        let Some(node) = self.stabilize(self.soup(index)) else {
+            return vec![PATHOLOGICAL.to_owned()];
+        };
+        node.components(Connectivity::WithinTwo)
+            .into_iter()
+            .flat_map(|(pos, group)| self.classify(&group.offset(pos)))
+            .collect()
+    }
+    /// adds the objects of every soup in `indices` to `census`
+    pub fn search(&self, indices: Range<u64>, census: &mut Census) {
+        for index in indices {
+            census.add_soup(self.objects(index));
+        }
+    }
+
+    /// steps until the population is periodic, or None if that takes longer than `max_generations`
+    ///
+    /// only stops when the background is dead, so with B0 rules the cells aren't inverted, see `Node::step_with_background`
+    fn stabilize(&self, mut node: Node) -> Option<Node> {
+        let mut background = false;
+        let mut populations = vec![node.population()];
+        let settled = |node: &Node, background: bool, populations: &[u64]| {
+            !background && (node.is_empty() || self.population_is_periodic(populations))
+        };
+        for _ in 0..self.max_generations {
+            if settled(&node, background, &populations) {
+                return Some(node);
+            }
+            (node, background) = node.step_with_background(&self.rule, 1, background);
+            populations.push(node.population());
+        }
+        settled(&node, background, &populations).then_some(node)
+    }
+    fn population_is_periodic(&self, populations: &[u64]) -> bool {
+        let len = populations.len() as u64;
+        (1..=self.max_period).any(|period| {
+            let window = (period * REPEATS).max(MIN_STABLE_GENERATIONS);
+            // the window is compared with the one a period before it
+            if len < window + period {
+                return false;
+            }
+            let start = usize::try_from(len - window).expect("fits in memory");
+            let period = usize::try_from(period).expect("fits in memory");
+            populations[start..]
+                .iter()
+                .zip(&populations[start - period..])
+                .all(|(a, b)| a == b)
+        })
+    }
+
+    /// a group of cells with at least 2 dead cells between it and anything else can't interact with anything else
+    ///
+    /// but it can still be made of several objects that don't interact with each other, like a pseudo still life,
+    /// which are counted separately like Catagolue does
+    fn classify(&self, group: &Node) -> Vec<String> {
+        let Some(period) = group.periodicity(&self.rule, self.max_period).period() else {
+            return vec![PATHOLOGICAL.to_owned()];
+        };
+        let pieces: Vec<Node> = group
+            .components(Connectivity::Adjacent)
+            .into_iter()
+            .map(|(pos, piece)| piece.offset(pos))
+            .collect();
+        if pieces.len() > 1 && self.independent(group, pieces.clone(), period) {
+            let codes: Option<Vec<String>> = pieces
+                .iter()
+                .map(|piece| piece.to_apgcode(&self.rule, self.max_period))
+                .collect();
+            if let Some(codes) = codes {
+                return codes;
+            }
+        }
+        vec![group
+            .to_apgcode(&self.rule, self.max_period)
+            .unwrap_or_else(|| PATHOLOGICAL.to_owned())]
+    }
+    /// the pieces evolve the same on their own as they do together for a whole period of the group
+    ///
+    /// the background is the same for every piece, and the cells that differ from it are the union of the pieces either way
+    fn independent(&self, group: &Node, mut pieces: Vec<Node>, period: u64) -> bool {
+        let mut group = group.clone();
+        let mut background = false;
+        for _ in 0..period {
+            let next_background;
+            (group, next_background) = group.step_with_background(&self.rule, 1, background);
+            let mut together = Node::empty(0);
+            for piece in &mut pieces {
+                *piece = piece.step_with_background(&self.rule, 1, background).0;
+                together = together.or(piece);
+            }
+            background = next_background;
+            if !group.xor(&together).is_empty() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// how many of each object have been found, and in how many soups
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Census {
+    counts: BTreeMap<String, u64>,
+    soups: u64,
+}
+impl Census {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add_soup(&mut self, objects: impl IntoIterator<Item = String>) {
+        self.soups += 1;
+        for object in objects {
+            *self.counts.entry(object).or_default() += 1;
+        }
+    }
+    /// for combining the censuses of searches run in parallel
+    pub fn merge(&mut self, other: &Census) {
+        self.soups += other.soups;
+        for (object, count) in &other.counts {
+            *self.counts.entry(object.clone()).or_default() += count;
+        }
+    }
+    pub fn soups(&self) -> u64 {
+        self.soups
+    }
+    pub fn get(&self, object: &str) -> u64 {
+        self.counts.get(object).copied().unwrap_or(0)
+    }
+    /// most common first, then alphabetically
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        let mut counts: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(object, &count)| (object.as_str(), count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts.into_iter()
+    }
+    /// a quoted csv with an `"apgcode","occurrences"` header, like Catagolue's textcensus
+    pub fn write_text(&self, mut write: impl Write) -> Result<(), IoError> {
+        writeln!(write, r#""apgcode","occurrences""#)?;
+        for (object, count) in self.iter() {
+            writeln!(write, r#""{object}","{count}""#)?;
+        }
+        Ok(())
+    }
+    pub fn to_text(&self) -> String {
+        let mut out = Vec::new();
+        self.write_text(&mut out).expect("valid bytes");
+        String::from_utf8(out).expect("valid string")
+    }
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use super::{Census, SoupSearch, PATHOLOGICAL, SOUP_WIDTH};
+    use crate::{test_node, Population, Pos, Rect};
+
+    #[test]
+    fn soups() {
+        let search = SoupSearch::new("seed");
+        let soup = search.soup(0);
+        assert_eq!(soup, search.soup(0));
+        assert_ne!(soup, search.soup(1));
+        assert_ne!(soup, SoupSearch::new("other").soup(0));
+        let bounds = soup.bounding_rect();
+        let area = Rect::min_max(Pos::new(0, 0), Pos::new(SOUP_WIDTH - 1, SOUP_WIDTH - 1));
+        let mut clipped = bounds;
+        clipped.intersection(area);
+        assert_eq!(clipped, bounds);
+        // about half of the cells are alive
+        assert!((64..192).contains(&soup.population()));
+    }
+
+    #[test]
+    fn classify() {
+        let search = SoupSearch::new("");
+        // a bi-block is a pseudo still life, so it is 2 blocks
+        let bi_block = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ........oo.oo...
+            ........oo.oo...
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        assert_eq!(search.classify(&bi_block), ["xs4_33", "xs4_33"]);
+        // but the cells of a beehive all touch
+        let beehive = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........oo.....
+            ........o..o....
+            .........oo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        assert_eq!(search.classify(&beehive), ["xs6_696"]);
+        // still growing
+        let r_pentomino = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........oo.....
+            ........oo......
+            .........o......
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        assert_eq!(search.classify(&r_pentomino), [PATHOLOGICAL]);
+    }
+
+    #[test]
+    fn search() {
+        let search = SoupSearch::new("k_test").with_max_generations(3000);
+        let mut census = Census::new();
+        search.search(0..4, &mut census);
+        assert_eq!(census.soups(), 4);
+        // the same seed gives the same census
+        let mut again = Census::new();
+        search.search(0..2, &mut again);
+        let mut rest = Census::new();
+        search.search(2..4, &mut rest);
+        again.merge(&rest);
+        assert_eq!(census, again);
+    }
+
+    #[test]
+    fn b0() {
+        // every cell changes every generation, so everything in the soup is a period 2 oscillator
+        let search = SoupSearch::new("k_test").with_rule("B012345678/S".parse().unwrap());
+        let objects = search.objects(0);
+        assert!(!objects.is_empty());
+        assert!(
+            objects.iter().all(|code| code.starts_with("xp2_")),
+            "{objects:?}"
+        );
+    }
+
+    #[test]
+    fn text() {
+        let mut census = Census::new();
+        census.add_soup(["xs4_33", "xp2_7", "xs4_33"].map(String::from));
+        census.add_soup(["xq4_153", "xp2_7"].map(String::from));
+        assert_eq!(census.soups(), 2);
+        assert_eq!(census.get("xs4_33"), 2);
+        assert_eq!(census.get("xs6_696"), 0);
+        assert_eq!(
+            census.to_text(),
+            "\"apgcode\",\"occurrences\"\n\"xp2_7\",\"2\"\n\"xs4_33\",\"2\"\n\"xq4_153\",\"1\"\n"
+        );
+    }
+}
@@ -1,87 +1,230 @@
+use either::Either;
 use lru::LruCache;
 use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::hash::Hash;
 use std::num::{NonZeroU64, NonZeroUsize};
+use std::thread::LocalKey;
 use tracing::{trace, trace_span};
 
-use crate::{Block, DepthQuad, Node, Population, Quad};
+use crate::multi_node::{planes_from_states, BlockPlanes};
+use crate::rules::{Ltl, LtlNeighbourhood, Neighbourhood, Transition};
+use crate::{Block, DepthQuad, MultiNode, Node, Population, Quad, Rule};
 
 const LRU_CACHE_SIZE: NonZeroUsize = NonZeroUsize::new(1 << 24).unwrap();
+/// how many rules keep their id, a rule that is used again after being dropped gets a new id
+const MAX_RULE_IDS: usize = 64;
 
 // TODO may want to store Either<Node,Block> results so the smallest cache is 16x16 instead of 32x32
 //      xor directly implement Quad<Quad<Block>>::step_center()
-struct StepCache {
-    lru: LruCache<(Node, NonZeroU64), Node>,
+struct StepCache<N> {
+    /// keyed by the rule id, node, steps, and if the background is alive
+    lru: LruCache<(u64, N, NonZeroU64, bool), N>,
+    /// the id of the rule being stepped
+    rule_id: u64,
     // it's a bit weird to store this 'globally' and periodicaly reset them
     // but it means it won't be hard to modify reporting to dump this once a second or every 100 calls ect
     // and with a bit of cleanup could track more interesting stats (like breaking this down per depth )
     hit: u32,
     miss: u32,
 }
-impl StepCache {
+impl<N: StepNode> StepCache<N> {
     fn new() -> Self {
         Self {
             lru: LruCache::new(LRU_CACHE_SIZE),
+            rule_id: 0,
             hit: 0,
             miss: 0,
         }
     }
 }
 thread_local! {
-    static STEP_CACHE: RefCell<StepCache> = RefCell::new(StepCache::new());
+    // every rule shares 1 cache, with the rule id in the key so that results are never shared between rules
+    static STEP_CACHE: RefCell<StepCache<Node>> = RefCell::new(StepCache::new());
+    static MULTI_STEP_CACHE: RefCell<StepCache<MultiNode>> = RefCell::new(StepCache::new());
+    static RULE_IDS: RefCell<RuleIds> = RefCell::new(RuleIds::default());
+}
+
+/// a small id for each recently used rule, so the cache keys don't hold a copy of the rule
+#[derive(Default)]
+struct RuleIds {
+    /// most recently used first
+    rules: VecDeque<(Rule, u64)>,
+    next: u64,
+}
+impl RuleIds {
+    /// rules are compared instead of hashed, so a rule from a `.rule` file is usually just a pointer comparison
+    fn get(&mut self, rule: &Rule) -> u64 {
+        if let Some(index) = self.rules.iter().position(|(other, _)| other == rule) {
+            let entry = self.rules.remove(index).expect("in bounds");
+            let id = entry.1;
+            self.rules.push_front(entry);
+            return id;
+        }
+        let id = self.next;
+        self.next += 1;
+        self.rules.push_front((rule.clone(), id));
+        // results for the dropped rule are never used again, so they age out of the cache
+        self.rules.truncate(MAX_RULE_IDS);
+        id
+    }
+}
+
+/// the rules for a dead and live background, see `Rule::with_background`
+///
+/// or for block rules, the rules for blocks at even and odd coordinates
+struct Phases {
+    rules: [Rule; 2],
+    rows: [RowStep; 2],
+    next: [bool; 2],
+}
+impl Phases {
+    fn new(rule: &Rule) -> Self {
+        if let Transition::Margolus {
+            rule, backwards, ..
+        } = rule.transition()
+        {
+            return Self {
+                rules: [
+                    Rule::margolus(*rule, *backwards, false),
+                    Rule::margolus(*rule, *backwards, true),
+                ],
+                rows: [RowStep::Dense, RowStep::Dense],
+                next: [true, false],
+            };
+        }
+        let live = if rule.can_invert() {
+            rule.with_background(true)
+        } else {
+            rule.clone()
+        };
+        let rules = [rule.with_background(false), live];
+        Self {
+            rows: [RowStep::new(&rules[0]), RowStep::new(&rules[1])],
+            rules,
+            next: [rule.next_background(false), rule.next_background(true)],
+        }
+    }
+    fn start(&self, background: bool) -> StepRule<'_> {
+        StepRule {
+            phases: self,
+            background,
+        }
+    }
+}
+/// the rule for a single generation, which depends on the background for B0 rules
+#[derive(Clone, Copy)]
+struct StepRule<'p> {
+    phases: &'p Phases,
+    background: bool,
+}
+impl<'p> StepRule<'p> {
+    fn rule(&self) -> &'p Rule {
+        &self.phases.rules[usize::from(self.background)]
+    }
+    fn rows(&self) -> &'p RowStep {
+        &self.phases.rows[usize::from(self.background)]
+    }
+    fn next(self) -> Self {
+        Self {
+            background: self.phases.next[usize::from(self.background)],
+            ..self
+        }
+    }
+    fn after(mut self, steps: u64) -> Self {
+        for step in 0..steps {
+            let next = self.next();
+            if next.background == self.background {
+                break;
+            }
+            if next.next().background == self.background {
+                // alternates every step
+                return if (steps - step) & 1 == 0 { self } else { next };
+            }
+            self = next;
+        }
+        self
+    }
 }
 
 /// depth of 0 is a 16x16 area and can conceptually step 4 times
 /// but a node can't represent a 8x8 area
 /// so max steps immediately jumps to 8 for a depth of 1
-fn depth_to_max_steps(depth: u8) -> u64 {
+///
+/// each step shrinks the known area by the range of the rule
+/// so doubling the range halves the max steps
+fn depth_to_max_steps(depth: u8, range_log2: u8) -> u64 {
     if depth == 0 {
         0
     } else {
-        1 << (Block::WIDTH_LOG2 - 1 + depth)
+        (1 << (Block::WIDTH_LOG2 - 1 + depth)) >> range_log2
     }
 }
-fn steps_to_min_depth(steps: NonZeroU64) -> u8 {
-    if steps.get() <= Block::WIDTH {
-        1
-    } else {
-        #[allow(clippy::cast_possible_truncation)] // max from ilog2 is 32
-        let floor = steps.ilog2() as u8 - (Block::WIDTH_LOG2 - 1);
-        if steps.is_power_of_two() {
-            floor
-        } else {
-            floor + 1
-        }
-    }
+fn steps_to_min_depth(steps: NonZeroU64, range_log2: u8) -> u8 {
+    let steps_log2 = steps.ilog2() + u32::from(!steps.is_power_of_two());
+    #[allow(clippy::cast_possible_truncation)] // max from ilog2 is 64
+    let depth = (steps_log2 as u8 + range_log2).saturating_sub(Block::WIDTH_LOG2 - 1);
+    depth.max(1)
 }
 
 // buffer logic
 
 impl Node {
-    pub fn step(&self, steps: u64) -> Node {
+    /// rules with B0 turn empty space alive, which is emulated by inverting the node while the background is alive
+    /// so unless the rule also has the max count for survival (such as S8),
+    /// odd steps return the cells that are dead instead, see `step_with_background`
+    pub fn step(&self, rule: &Rule, steps: u64) -> Node {
+        self.step_with_background(rule, steps, false).0
+    }
+    pub fn step_non_zero(&self, rule: &Rule, steps: NonZeroU64) -> Node {
+        self.step_non_zero_with_background(rule, steps, false).0
+    }
+    /// steps a node where every cell outside of it is `background`,
+    /// and the cells that are set are the cells that differ from the background
+    ///
+    /// returns the stepped node along with its background
+    pub fn step_with_background(&self, rule: &Rule, steps: u64, background: bool) -> (Node, bool) {
         match NonZeroU64::new(steps) {
-            None => self.clone(),
-            Some(steps) => self.step_non_zero(steps),
+            None => (self.clone(), background),
+            Some(steps) => self.step_non_zero_with_background(rule, steps, background),
         }
     }
-    pub fn step_non_zero(&self, steps: NonZeroU64) -> Node {
+    fn step_non_zero_with_background(
+        &self,
+        rule: &Rule,
+        steps: NonZeroU64,
+        background: bool,
+    ) -> (Node, bool) {
+        assert_eq!(rule.states(), 2, "multi-state rules need a MultiNode");
+        if let Some(grid) = rule.grid() {
+            assert!(!background, "bounded grids don't have a background");
+            let stepped = self.step_bounded(rule, steps.get(), grid.topology(), grid.rect());
+            return (stepped, false);
+        }
+        assert!(
+            !background || rule.can_invert(),
+            "only two-state rules with a range of 1 can have a live background"
+        );
+        self.step_buffered(rule, steps, background)
+    }
+    /// steps without checking that `background` is valid for `rule`,
+    /// which is also the phase of block rules
+    pub(crate) fn step_buffered(
+        &self,
+        rule: &Rule,
+        steps: NonZeroU64,
+        background: bool,
+    ) -> (Node, bool) {
         // step will reduce the area of the node, but also the unbuffered node will grow up to the same amount
         // so we need 2 buffer nodes that are both >= min_depth
         // so we unbuffer given node so it is >= min_depth - 1
-        let min_depth = steps_to_min_depth(steps);
+        let min_depth = steps_to_min_depth(steps, rule.range_log2());
         let depth = self.unbufferd_depth(min_depth - 1) + 2;
 
         let _span = trace_span!("step", depth, steps).entered();
 
-        let root = self.center_at_depth(depth);
-        STEP_CACHE.with_borrow_mut(|step_cache| {
-            let result = root.step_center(steps, step_cache);
-
-            trace!(step_cache.hit, step_cache.miss, "cache_perf");
-            step_cache.hit = 0;
-            step_cache.miss = 0;
-
-            result
-        })
+        self.center_at_depth(depth)
+            .step_root(rule, steps, background)
     }
     // find the smallest depth where the node is unbuffered, maxed with target_depth
     fn unbufferd_depth(&self, target_depth: u8) -> u8 {
@@ -108,6 +251,51 @@ impl Node {
         }
     }
 }
+impl MultiNode {
+    /// B0 rules are handled the same as `Node::step`
+    pub fn step(&self, rule: &Rule, steps: u64) -> MultiNode {
+        self.step_with_background(rule, steps, false).0
+    }
+    pub fn step_non_zero(&self, rule: &Rule, steps: NonZeroU64) -> MultiNode {
+        self.step_non_zero_with_background(rule, steps, false).0
+    }
+    /// the same as `Node::step_with_background`, a live background is only possible for two-state rules
+    pub fn step_with_background(
+        &self,
+        rule: &Rule,
+        steps: u64,
+        background: bool,
+    ) -> (MultiNode, bool) {
+        match NonZeroU64::new(steps) {
+            None => (self.clone(), background),
+            Some(steps) => self.step_non_zero_with_background(rule, steps, background),
+        }
+    }
+    fn step_non_zero_with_background(
+        &self,
+        rule: &Rule,
+        steps: NonZeroU64,
+        background: bool,
+    ) -> (MultiNode, bool) {
+        assert!(
+            !background || rule.can_invert(),
+            "only two-state rules with a range of 1 can have a live background"
+        );
+        if let Some(grid) = rule.grid() {
+            assert!(!background, "bounded grids don't have a background");
+            let stepped = self.step_bounded(rule, steps.get(), grid.topology(), grid.rect());
+            return (stepped, false);
+        }
+        // buffered the same as a node, dying cells count as occupied since they still change
+        let min_depth = steps_to_min_depth(steps, rule.range_log2());
+        let depth = self.occupied().unbufferd_depth(min_depth - 1) + 2;
+
+        let _span = trace_span!("step_multi", depth, steps).entered();
+
+        self.center_at_depth(depth)
+            .step_root(rule, steps, background)
+    }
+}
 impl<T> Quad<&Quad<T>>
 where
     T: Population,
@@ -134,13 +322,52 @@ where
 
 // recurse logic
 
-impl Node {
-    fn step_center(self, steps: NonZeroU64, step_cache: &mut StepCache) -> Node {
-        let key = (self, steps);
+/// the parts of a quadtree needed to step it, so two-state and multi-state nodes share the same logic
+trait StepNode: Clone + Eq + Hash + 'static {
+    /// the 8x8 area that `Self` is made from at depth 0
+    type Block: Clone;
+
+    fn step_cache() -> &'static LocalKey<RefCell<StepCache<Self>>>;
+    fn depth(&self) -> u8;
+    /// the children of each child, panics at depth 0
+    fn grandchildren(&self) -> Either<Quad<Quad<Self::Block>>, Quad<Quad<Self>>>;
+    fn from_blocks(quad: Quad<Self::Block>) -> Self;
+    fn from_nodes(quad: Quad<Self>) -> Self;
+    fn center(quad: &Quad<Self>) -> Self;
+    /// steps a 16x16 area at most 4 times, returning the center 8x8 area
+    fn step_blocks(quad: &Quad<Self::Block>, rule: StepRule, steps: u64) -> Self::Block;
+    /// every cell as a state in row-major order
+    fn to_dense(&self) -> Vec<u8>;
+    fn from_dense(states: &[u8], width: usize) -> Self;
+
+    /// returns the stepped node and its background
+    fn step_root(self, rule: &Rule, steps: NonZeroU64, background: bool) -> (Self, bool) {
+        let phases = Phases::new(rule);
+        let step_rule = phases.start(background);
+        // keyed by the rule as given, since the rule for each background can match another rule
+        let rule_id = RULE_IDS.with_borrow_mut(|rule_ids| rule_ids.get(rule));
+        Self::step_cache().with_borrow_mut(|step_cache| {
+            step_cache.rule_id = rule_id;
+            let result = self.step_center(step_rule, steps, step_cache);
+
+            trace!(step_cache.hit, step_cache.miss, "cache_perf");
+            step_cache.hit = 0;
+            step_cache.miss = 0;
+
+            (result, step_rule.after(steps.get()).background)
+        })
+    }
+    fn step_center(
+        self,
+        rule: StepRule,
+        steps: NonZeroU64,
+        step_cache: &mut StepCache<Self>,
+    ) -> Self {
+        let key = (step_cache.rule_id, self, steps, rule.background);
         match step_cache.lru.get(&key) {
             None => {
                 step_cache.miss += 1;
-                let result = key.0.step_center_impl(steps, step_cache);
+                let result = key.1.step_center_impl(rule, steps, step_cache);
                 step_cache.lru.put(key, result.clone());
                 result
             }
@@ -150,36 +377,180 @@ impl Node {
             }
         }
     }
-    fn step_center_impl(&self, steps: NonZeroU64, step_cache: &mut StepCache) -> Node {
-        let max_steps = depth_to_max_steps(self.depth());
+    fn step_center_impl(
+        &self,
+        rule: StepRule,
+        steps: NonZeroU64,
+        step_cache: &mut StepCache<Self>,
+    ) -> Self {
+        let range_log2 = rule.rule().range_log2();
+        let max_steps = depth_to_max_steps(self.depth(), range_log2);
         debug_assert!(steps.get() <= max_steps);
+        if self.depth() + 1 < range_log2 {
+            // children can't step even once, so this is the base case for large ranges
+            return self.step_dense_center(rule, steps.get());
+        }
         let first_half_steps = steps.get().saturating_sub(max_steps / 2);
         let second_half_steps = steps.get() - first_half_steps;
-        match self.inner().unwrap().as_ref().children() {
-            DepthQuad::Leaf(leaf) => leaf
-                .copied()
-                .overlaps_hood()
-                .map(|quad| quad.step_center(first_half_steps))
-                .overlaps_quad()
-                .map(|quad| quad.step_center(second_half_steps))
-                .into(),
-            DepthQuad::Inner(_, inner) => inner
-                .cloned()
-                .overlaps_hood()
-                .map(|quad| quad.step_center(first_half_steps, step_cache))
-                .overlaps_quad()
-                .map(|quad| quad.step_center(second_half_steps, step_cache))
-                .into(),
-        }
-    }
-}
-impl Quad<Node> {
-    fn step_center(self, steps: u64, step_cache: &mut StepCache) -> Node {
+        let second_half_rule = rule.after(first_half_steps);
+        match self.grandchildren() {
+            Either::Left(leaf) => Self::from_blocks(
+                leaf.overlaps_hood()
+                    .map(|quad| Self::step_blocks(&quad, rule, first_half_steps))
+                    .overlaps_quad()
+                    .map(|quad| Self::step_blocks(&quad, second_half_rule, second_half_steps)),
+            ),
+            Either::Right(inner) => Self::from_nodes(
+                inner
+                    .overlaps_hood()
+                    .map(|quad| Self::step_quad_center(quad, rule, first_half_steps, step_cache))
+                    .overlaps_quad()
+                    .map(|quad| {
+                        Self::step_quad_center(
+                            quad,
+                            second_half_rule,
+                            second_half_steps,
+                            step_cache,
+                        )
+                    }),
+            ),
+        }
+    }
+    fn step_dense_center(&self, rule: StepRule, steps: u64) -> Self {
+        let width = 1 << (self.depth() + Node::MIN_WIDTH_LOG2);
+        let mut states = self.to_dense();
+        step_dense(&mut states, width, rule, steps);
+        let (quarter, half) = (width / 4, width / 2);
+        let center: Vec<u8> = states
+            .chunks(width)
+            .skip(quarter)
+            .take(half)
+            .flat_map(|row| &row[quarter..quarter + half])
+            .copied()
+            .collect();
+        Self::from_dense(&center, half)
+    }
+    fn step_quad_center(
+        quad: Quad<Self>,
+        rule: StepRule,
+        steps: u64,
+        step_cache: &mut StepCache<Self>,
+    ) -> Self {
         match NonZeroU64::new(steps) {
-            None => self.as_ref().center().into(),
-            Some(steps) => Node::from(self).step_center(steps, step_cache),
+            None => Self::center(&quad),
+            Some(steps) => Self::from_nodes(quad).step_center(rule, steps, step_cache),
+        }
+    }
+}
+
+impl StepNode for Node {
+    type Block = Block;
+
+    fn step_cache() -> &'static LocalKey<RefCell<StepCache<Self>>> {
+        &STEP_CACHE
+    }
+    fn depth(&self) -> u8 {
+        Node::depth(self)
+    }
+    fn grandchildren(&self) -> Either<Quad<Quad<Block>>, Quad<Quad<Node>>> {
+        match self.inner().unwrap().as_ref().children() {
+            DepthQuad::Leaf(leaf) => Either::Left(leaf.copied()),
+            DepthQuad::Inner(_, inner) => Either::Right(inner.cloned()),
+        }
+    }
+    fn from_blocks(quad: Quad<Block>) -> Self {
+        quad.into()
+    }
+    fn from_nodes(quad: Quad<Node>) -> Self {
+        quad.into()
+    }
+    fn center(quad: &Quad<Node>) -> Self {
+        quad.as_ref().center().into()
+    }
+    fn step_blocks(quad: &Quad<Block>, rule: StepRule, steps: u64) -> Block {
+        quad.step_center(rule, steps)
+    }
+    fn to_dense(&self) -> Vec<u8> {
+        let width = 1 << self.width_log2();
+        let mut states = vec![0; width * width];
+        node_to_dense(self, &mut states, width, (0, 0), 0);
+        states
+    }
+    fn from_dense(states: &[u8], width: usize) -> Self {
+        node_from_dense(states, width, (0, 0), width, 0)
+    }
+}
+impl StepNode for MultiNode {
+    type Block = BlockPlanes;
+
+    fn step_cache() -> &'static LocalKey<RefCell<StepCache<Self>>> {
+        &MULTI_STEP_CACHE
+    }
+    fn depth(&self) -> u8 {
+        MultiNode::depth(self)
+    }
+    fn grandchildren(&self) -> Either<Quad<Quad<BlockPlanes>>, Quad<Quad<MultiNode>>> {
+        let children = match self.children() {
+            DepthQuad::Inner(_, inner) => inner,
+            DepthQuad::Leaf(_) => panic!("can't get grandchildren of a leaf"),
+        };
+        if children.nw.depth() == 0 {
+            Either::Left(children.map(|child| match child.children() {
+                DepthQuad::Leaf(leaf) => leaf,
+                DepthQuad::Inner(..) => panic!("inconsistent node depth"),
+            }))
+        } else {
+            Either::Right(children.map(|child| match child.children() {
+                DepthQuad::Inner(_, inner) => inner,
+                DepthQuad::Leaf(_) => panic!("inconsistent node depth"),
+            }))
         }
     }
+    fn from_blocks(quad: Quad<BlockPlanes>) -> Self {
+        MultiNode::new_leaf(&quad)
+    }
+    fn from_nodes(quad: Quad<MultiNode>) -> Self {
+        MultiNode::new_inner(quad)
+    }
+    fn center(quad: &Quad<MultiNode>) -> Self {
+        let len = quad
+            .iter()
+            .map(|node| node.planes().len())
+            .max()
+            .unwrap_or(0);
+        MultiNode::new(
+            (0..len)
+                .map(|i| {
+                    quad.as_ref()
+                        .map(|node| node.plane(i))
+                        .as_ref()
+                        .center()
+                        .into()
+                })
+                .collect(),
+        )
+    }
+    fn step_blocks(quad: &Quad<BlockPlanes>, rule: StepRule, steps: u64) -> BlockPlanes {
+        step_states_center(quad, rule, steps)
+    }
+    fn to_dense(&self) -> Vec<u8> {
+        let width = 1 << self.width_log2();
+        let mut states = vec![0; width * width];
+        for (bit, plane) in (0..).zip(self.planes()) {
+            node_to_dense(plane, &mut states, width, (0, 0), bit);
+        }
+        states
+    }
+    fn from_dense(states: &[u8], width: usize) -> Self {
+        let max = states.iter().fold(0, |max, &state| max | state);
+        // at least 1 plane to keep the depth
+        let planes = (u8::BITS - max.leading_zeros()).max(1);
+        MultiNode::new(
+            (0..planes)
+                .map(|bit| node_from_dense(states, width, (0, 0), width, bit))
+                .collect(),
+        )
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
@@ -292,8 +663,21 @@ where
 // base logic
 
 impl Quad<Block> {
-    fn step_center(&self, steps: u64) -> Block {
+    fn step_center(&self, rule: StepRule, steps: u64) -> Block {
         debug_assert!(steps <= 4);
+        if let RowStep::Dense = rule.rows() {
+            let mut states = vec![0; 16 * 16];
+            for (block, at) in [
+                (self.nw, (0, 0)),
+                (self.ne, (8, 0)),
+                (self.sw, (0, 8)),
+                (self.se, (8, 8)),
+            ] {
+                block_to_dense(block, &mut states, 16, at, 0);
+            }
+            step_dense(&mut states, 16, rule, steps);
+            return block_from_dense(&states, 16, (4, 4), 0);
+        }
         // convert 4 8x8 blocks into 4 4x16 blocks
         let mut rows = [
             shape_north(self.nw.to_rows(), self.ne.to_rows()),
@@ -302,13 +686,83 @@ impl Quad<Block> {
             shape_south(self.sw.to_rows(), self.se.to_rows()),
         ];
         // TODO could optimize by dropping indicies that are no longer needed
+        let mut rule = rule;
         for _ in 0..steps {
-            step_rows_once(&mut rows);
+            // matched once per generation, so each row step is inlined into its own loop
+            match rule.rows() {
+                RowStep::Life => step_rows_once(&mut rows, step_row_life),
+                RowStep::Totalistic {
+                    neighbourhood: Neighbourhood::Moore,
+                    born,
+                    survive,
+                } => step_rows_once(&mut rows, |above, row, below| {
+                    step_row_totalistic(born, survive, count_moore(above, row, below), row)
+                }),
+                RowStep::Totalistic {
+                    neighbourhood: Neighbourhood::VonNeumann,
+                    born,
+                    survive,
+                } => step_rows_once(&mut rows, |above, row, below| {
+                    let digits = count_bits(&[above, row << 1, row >> 1, below]);
+                    step_row_totalistic(born, survive, digits, row)
+                }),
+                RowStep::Totalistic {
+                    neighbourhood: Neighbourhood::Hexagonal,
+                    born,
+                    survive,
+                } => step_rows_once(&mut rows, |above, row, below| {
+                    let digits =
+                        count_bits(&[above, above >> 1, row << 1, row >> 1, below, below << 1]);
+                    step_row_totalistic(born, survive, digits, row)
+                }),
+                RowStep::Isotropic(table) => step_rows_once(&mut rows, |above, row, below| {
+                    step_row_isotropic(table, above, row, below)
+                }),
+                RowStep::Dense => unreachable!("stepped densely"),
+            }
+            rule = rule.next();
         }
         // convert back into an 8x8 block
         Block::from_rows(unshape_center(rows[1], rows[2]))
     }
 }
+
+/// how a 2 state rule steps a row of cells, picked once per rule instead of for every row
+enum RowStep {
+    /// `B3/S23`, which has its own faster step
+    Life,
+    /// a mask of all 1s for each count that is born or survives, and all 0s for the rest
+    Totalistic {
+        neighbourhood: Neighbourhood,
+        born: [u64; 9],
+        survive: [u64; 9],
+    },
+    Isotropic([u64; 8]),
+    /// rules that don't step by rows
+    Dense,
+}
+impl RowStep {
+    fn new(rule: &Rule) -> Self {
+        if *rule.transition() == *Rule::LIFE.transition() {
+            return Self::Life;
+        }
+        match rule.transition() {
+            &Transition::Totalistic {
+                birth,
+                survival,
+                neighbourhood,
+            } => Self::Totalistic {
+                neighbourhood,
+                born: count_masks(birth),
+                survive: count_masks(survival),
+            },
+            Transition::Isotropic(table) => Self::Isotropic(**table),
+            Transition::LargerThanLife(_) | Transition::Table(_) | Transition::Margolus { .. } => {
+                Self::Dense
+            }
+        }
+    }
+}
 #[allow(clippy::similar_names)]
 fn shape_north(w: u64, e: u64) -> u64 {
     let r0w = w & 0xff_00_00_00_00_00_00_00;
@@ -345,7 +799,7 @@ fn unshape_center(n: u64, s: u64) -> u64 {
     r0 | r1 | r2 | r3 | r4 | r5 | r6 | r7
 }
 
-fn step_rows_once(rows: &mut [u64; 4]) {
+fn step_rows_once(rows: &mut [u64; 4], step_row: impl Fn(u64, u64, u64) -> u64) {
     // isn't necessarilly very optimized, but it will take a lot of staring at generated asm to find something better
     // in particular it may be that shifting data up 1 row (left by 16 bits) each call can both use less registers (only need 2 values for each call to step_row)
     // and omiting calls that produce unused values: only need rows 1,2 for the last step; or even fewer, but more complicated, when shifting data
@@ -358,7 +812,7 @@ fn step_rows_once(rows: &mut [u64; 4]) {
         step_row_shift(rows[2], rows[3], 0),
     ];
 }
-fn step_row(above: u64, row: u64, below: u64) -> u64 {
+fn step_row_life(above: u64, row: u64, below: u64) -> u64 {
     // compute a bitwise addition of 3 values (as 2 separate results)
     let bit_sum = |a, b, c| (a ^ b ^ c, a & b | a & c | b & c);
 
@@ -393,45 +847,446 @@ fn step_row(above: u64, row: u64, below: u64) -> u64 {
     // (a1 ^ b0) & !b1 is 2 or 3 neighbors
     (row | a0) & (a1 ^ b0) & !b1
 }
+/// count the 8 neighbours of every cell into 4 bitwise digits
+fn count_moore(above: u64, row: u64, below: u64) -> [u64; 4] {
+    // compute a bitwise addition of 3 values (as 2 separate results)
+    let bit_sum = |a, b, c| (a ^ b ^ c, a & b | a & c | b & c);
+
+    let (a0, a1) = bit_sum(above << 1, above, above >> 1); // a0=1,3 a1=2,3
+    let (b0, b1) = bit_sum(row << 1, row >> 1, below); // b0=1,3 b1=2,3
+    let (c0, c1) = (below << 1 ^ below >> 1, below << 1 & below >> 1); // c0=1 c1=2
+    let (d0, d1) = bit_sum(a0, b0, c0); // ones digit and a carry into the twos
+    let (e0, e1) = bit_sum(a1, b1, c1); // twos that don't include the carry
+    let (f0, f1) = (e0 ^ d1, e0 & d1);
+    [d0, f0, e1 ^ f1, e1 & f1]
+}
+/// count any neighbours of every cell into 4 bitwise digits, slower than `count_moore`
+fn count_bits(neighbours: &[u64]) -> [u64; 4] {
+    let mut digits = [0; 4];
+    for &neighbour in neighbours {
+        // bitwise increment where the neighbour is alive
+        let mut carry = neighbour;
+        for digit in &mut digits {
+            let next_carry = *digit & carry;
+            *digit ^= carry;
+            carry = next_carry;
+        }
+    }
+    digits
+}
+fn count_masks(counts: u16) -> [u64; 9] {
+    std::array::from_fn(|count| if counts & 1 << count == 0 { 0 } else { !0 })
+}
+fn step_row_totalistic(born: &[u64; 9], survive: &[u64; 9], digits: [u64; 4], row: u64) -> u64 {
+    (!row & select_count(born, digits)) | (row & select_count(survive, digits))
+}
+/// picks the mask of the count of each cell, by muxing on each digit from the lowest
+fn select_count(masks: &[u64; 9], digits: [u64; 4]) -> u64 {
+    let mux = |low: u64, high: u64, digit: u64| (low & !digit) | (high & digit);
+    let [ones, twos, fours, eights] = digits;
+    let pairs: [u64; 4] = std::array::from_fn(|i| mux(masks[2 * i], masks[2 * i + 1], ones));
+    let below_eight = mux(
+        mux(pairs[0], pairs[1], twos),
+        mux(pairs[2], pairs[3], twos),
+        fours,
+    );
+    // the only count with the eights digit is 8, so the other digits are 0
+    mux(below_eight, masks[8], eights)
+}
+#[allow(clippy::cast_possible_truncation)] // masked to 3 bits
+fn step_row_isotropic(table: &[u64; 8], above: u64, row: u64, below: u64) -> u64 {
+    // cells without any live cells in their neighbourhood all have neighbourhood 0,
+    // so only the cells near live cells are looked up, which skips most of a sparse row
+    let near = |cells: u64| cells | cells << 1 | cells >> 1;
+    let active = near(above | row | below);
+    let mut next = if table[0] & 1 == 0 { 0 } else { !active };
+    // the west, center and east cell of each row are the low 3 bits after shifting by the column,
+    // with a dead cell shifted in past either edge
+    let windows = [above, row, below].map(|cells| u128::from(cells) << 1);
+    let mut remaining = active;
+    while remaining != 0 {
+        let i = remaining.trailing_zeros();
+        remaining &= remaining - 1;
+        // in the bit order of the table, with the south east cell as the lowest bit
+        let [above, row, below] = windows.map(|cells| (cells >> i) as usize & 0b111);
+        let index = above << 6 | row << 3 | below;
+        next |= ((table[index / 64] >> (index % 64)) & 1) << i;
+    }
+    next
+}
+
+// dense base logic
+
+fn step_states_center(quad: &Quad<BlockPlanes>, rule: StepRule, steps: u64) -> BlockPlanes {
+    debug_assert!(steps <= 4);
+    let mut states = vec![0; 16 * 16];
+    for (planes, at) in [
+        (&quad.nw, (0, 0)),
+        (&quad.ne, (8, 0)),
+        (&quad.sw, (0, 8)),
+        (&quad.se, (8, 8)),
+    ] {
+        for (bit, &block) in (0..).zip(planes.iter()) {
+            block_to_dense(block, &mut states, 16, at, bit);
+        }
+    }
+    step_dense(&mut states, 16, rule, steps);
+    let mut center = [[0; 8]; 8];
+    for (y, row) in center.iter_mut().enumerate() {
+        row.copy_from_slice(&states[(4 + y) * 16 + 4..][..8]);
+    }
+    planes_from_states(&center)
+}
+
+/// steps a square area of states in row-major order
+/// cells past the edge are treated as dead, so only cells at least `steps * range` from the edge are correct
+fn step_dense(states: &mut [u8], width: usize, rule: StepRule, steps: u64) {
+    let mut step_rule = rule;
+    for _ in 0..steps {
+        let rule = step_rule.rule();
+        step_rule = step_rule.next();
+        let alive: Vec<bool> = states.iter().map(|&state| state == 1).collect();
+        if let Transition::LargerThanLife(ltl) = rule.transition() {
+            let counts = count_in_range(&alive, width, ltl);
+            for (state, count) in states.iter_mut().zip(counts) {
+                *state = rule.next_state_from_count(ltl, *state, count);
+            }
+        } else if let Transition::Margolus {
+            rule,
+            backwards,
+            odd,
+        } = rule.transition()
+        {
+            let table = rule.phases(*backwards)[usize::from(*odd)];
+            step_dense_blocks(states, width, &table, *odd);
+        } else if let Transition::Table(table) = rule.transition() {
+            let current = states.to_vec();
+            let state_at = |x: usize, y: usize| {
+                // wrapping_sub is out of bounds, and so dead
+                if x < width && y < width {
+                    current[y * width + x]
+                } else {
+                    0
+                }
+            };
+            for (i, state) in states.iter_mut().enumerate() {
+                let (x, y) = (i % width, i / width);
+                let cells = std::array::from_fn(|j| {
+                    state_at((x + j % 3).wrapping_sub(1), (y + j / 3).wrapping_sub(1))
+                });
+                *state = table.next_state(&cells);
+            }
+        } else {
+            let is_alive = |x: usize, y: usize| {
+                // wrapping_sub is out of bounds, and so not alive
+                usize::from(x < width && y < width && alive[y * width + x])
+            };
+            for (i, state) in states.iter_mut().enumerate() {
+                let (x, y) = (i % width, i / width);
+                let neighbourhood = [y.wrapping_sub(1), y, y + 1]
+                    .into_iter()
+                    .flat_map(|y| [x.wrapping_sub(1), x, x + 1].map(|x| (x, y)))
+                    .fold(0, |neighbourhood, (x, y)| {
+                        (neighbourhood << 1) | is_alive(x, y)
+                    });
+                *state = rule.next_state(*state, neighbourhood);
+            }
+        }
+    }
+}
+/// replaces each 2x2 block starting at `odd` coordinates using `table`
+/// blocks past the edge are skipped, so cells on the edge are left as they are
+fn step_dense_blocks(states: &mut [u8], width: usize, table: &[u8; 16], odd: bool) {
+    let start = usize::from(odd);
+    for y in (start..width - 1).step_by(2) {
+        for x in (start..width - 1).step_by(2) {
+            let cells = [
+                y * width + x,
+                y * width + x + 1,
+                (y + 1) * width + x,
+                (y + 1) * width + x + 1,
+            ];
+            let block = cells
+                .iter()
+                .enumerate()
+                .fold(0, |block, (bit, &i)| block | (states[i] & 1) << bit);
+            let next = table[usize::from(block)];
+            for (bit, &i) in cells.iter().enumerate() {
+                states[i] = (next >> bit) & 1;
+            }
+        }
+    }
+}
+/// number of live cells in range of each cell
+fn count_in_range(alive: &[bool], width: usize, ltl: &Ltl) -> Vec<u32> {
+    let range = usize::from(ltl.range);
+    let counts: Vec<u32> = match ltl.neighbourhood {
+        LtlNeighbourhood::Moore => {
+            let sums = SummedArea::new(width, |x, y| alive[y * width + x]);
+            (0..width * width)
+                .map(|i| sums.square(i % width, i / width, range))
+                .collect()
+        }
+        LtlNeighbourhood::VonNeumann => {
+            // rotating by 45 degrees turns the diamond into a square
+            // (x, y) is at (x + y, x - y + width - 1), leaving gaps that are never alive
+            let rotated_width = 2 * width - 1;
+            let sums = SummedArea::new(rotated_width, |u, v| {
+                // twice (x, y), which wraps to out of bounds
+                let x2 = (u + v).wrapping_sub(width - 1);
+                let y2 = (u + width - 1).wrapping_sub(v);
+                x2 % 2 == 0 && x2 / 2 < width && y2 / 2 < width && alive[y2 / 2 * width + x2 / 2]
+            });
+            (0..width * width)
+                .map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    sums.square(x + y, x + width - 1 - y, range)
+                })
+                .collect()
+        }
+    };
+    if ltl.middle {
+        counts
+    } else {
+        counts
+            .into_iter()
+            .zip(alive)
+            .map(|(count, &alive)| count - u32::from(alive))
+            .collect()
+    }
+}
+
+/// counts of cells in any rectangle of a square area
+struct SummedArea {
+    width: usize,
+    /// `sums[y * (width + 1) + x]` is the count in `[0, x) x [0, y)`
+    sums: Vec<u32>,
+}
+impl SummedArea {
+    fn new(width: usize, alive: impl Fn(usize, usize) -> bool) -> Self {
+        let stride = width + 1;
+        let mut sums = vec![0; stride * stride];
+        for y in 0..width {
+            for x in 0..width {
+                sums[(y + 1) * stride + x + 1] =
+                    u32::from(alive(x, y)) + sums[y * stride + x + 1] + sums[(y + 1) * stride + x]
+                        - sums[y * stride + x];
+            }
+        }
+        Self { width, sums }
+    }
+    /// count in the square of cells within `range` of `(x, y)`, clipped to the area
+    fn square(&self, x: usize, y: usize, range: usize) -> u32 {
+        let stride = self.width + 1;
+        let (x0, y0) = (x.saturating_sub(range), y.saturating_sub(range));
+        let (x1, y1) = (
+            (x + range + 1).min(self.width),
+            (y + range + 1).min(self.width),
+        );
+        self.sums[y1 * stride + x1] + self.sums[y0 * stride + x0]
+            - self.sums[y0 * stride + x1]
+            - self.sums[y1 * stride + x0]
+    }
+}
+
+fn node_to_dense(node: &Node, states: &mut [u8], width: usize, at: (usize, usize), bit: u32) {
+    if node.is_empty() {
+        return;
+    }
+    let (x, y) = at;
+    match node.depth_quad() {
+        DepthQuad::Leaf(leaf) => {
+            block_to_dense(leaf.nw, states, width, (x, y), bit);
+            block_to_dense(leaf.ne, states, width, (x + 8, y), bit);
+            block_to_dense(leaf.sw, states, width, (x, y + 8), bit);
+            block_to_dense(leaf.se, states, width, (x + 8, y + 8), bit);
+        }
+        DepthQuad::Inner(_, inner) => {
+            let half = 1 << (node.width_log2() - 1);
+            node_to_dense(&inner.nw, states, width, (x, y), bit);
+            node_to_dense(&inner.ne, states, width, (x + half, y), bit);
+            node_to_dense(&inner.sw, states, width, (x, y + half), bit);
+            node_to_dense(&inner.se, states, width, (x + half, y + half), bit);
+        }
+    }
+}
+fn node_from_dense(
+    states: &[u8],
+    width: usize,
+    at: (usize, usize),
+    node_width: usize,
+    bit: u32,
+) -> Node {
+    let (x, y) = at;
+    let half = node_width / 2;
+    if half == 8 {
+        Node::new(
+            block_from_dense(states, width, (x, y), bit),
+            block_from_dense(states, width, (x + 8, y), bit),
+            block_from_dense(states, width, (x, y + 8), bit),
+            block_from_dense(states, width, (x + 8, y + 8), bit),
+        )
+    } else {
+        Node::new(
+            node_from_dense(states, width, (x, y), half, bit),
+            node_from_dense(states, width, (x + half, y), half, bit),
+            node_from_dense(states, width, (x, y + half), half, bit),
+            node_from_dense(states, width, (x + half, y + half), half, bit),
+        )
+    }
+}
+fn block_to_dense(block: Block, states: &mut [u8], width: usize, at: (usize, usize), bit: u32) {
+    let (x, y) = at;
+    for (row, bits) in states[y * width..]
+        .chunks_mut(width)
+        .zip(block.to_rows_array())
+    {
+        for (i, state) in row[x..x + 8].iter_mut().enumerate() {
+            *state |= ((bits >> (7 - i)) & 1) << bit;
+        }
+    }
+}
+fn block_from_dense(states: &[u8], width: usize, at: (usize, usize), bit: u32) -> Block {
+    let (x, y) = at;
+    Block::from_rows_array(std::array::from_fn(|row| {
+        states[(y + row) * width + x..][..8]
+            .iter()
+            .fold(0, |bits, &state| (bits << 1) | ((state >> bit) & 1))
+    }))
+}
 
 // tests
 
 #[cfg(test)]
 #[allow(clippy::many_single_char_names)]
 mod tests {
-    use crate::{test_block, Block, Node};
+    use crate::{test_block, Block, MultiNode, Node, Pos, Rule};
 
-    use super::{depth_to_max_steps, steps_to_min_depth};
+    use super::{
+        count_masks, count_moore, depth_to_max_steps, step_row_isotropic, step_row_life,
+        step_row_totalistic, steps_to_min_depth, Phases, LRU_CACHE_SIZE, MAX_RULE_IDS, RULE_IDS,
+        STEP_CACHE,
+    };
     use std::num::NonZeroU64;
 
     #[test]
     fn test_depth_to_max_steps() {
-        assert_eq!(depth_to_max_steps(0), 0);
-        assert_eq!(depth_to_max_steps(1), 8);
-        assert_eq!(depth_to_max_steps(2), 16);
-        assert_eq!(depth_to_max_steps(3), 32);
-        assert_eq!(depth_to_max_steps(Node::MAX_DEPTH), 1 << 61);
+        assert_eq!(depth_to_max_steps(0, 0), 0);
+        assert_eq!(depth_to_max_steps(1, 0), 8);
+        assert_eq!(depth_to_max_steps(2, 0), 16);
+        assert_eq!(depth_to_max_steps(3, 0), 32);
+        assert_eq!(depth_to_max_steps(Node::MAX_DEPTH, 0), 1 << 61);
+        // range 2
+        assert_eq!(depth_to_max_steps(1, 1), 4);
+        assert_eq!(depth_to_max_steps(2, 1), 8);
+        // range 5 is treated as 8
+        assert_eq!(depth_to_max_steps(1, 3), 1);
+        assert_eq!(depth_to_max_steps(2, 3), 2);
+        assert_eq!(depth_to_max_steps(1, 4), 0);
     }
     #[test]
     fn test_steps_to_min_depth() {
-        assert_eq!(steps_to_min_depth(NonZeroU64::new(1).unwrap()), 1);
-        assert_eq!(steps_to_min_depth(NonZeroU64::new(8).unwrap()), 1);
-        assert_eq!(steps_to_min_depth(NonZeroU64::new(9).unwrap()), 2);
-        assert_eq!(steps_to_min_depth(NonZeroU64::new(16).unwrap()), 2);
-        assert_eq!(steps_to_min_depth(NonZeroU64::new(17).unwrap()), 3);
-        assert_eq!(steps_to_min_depth(NonZeroU64::new(32).unwrap()), 3);
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(1).unwrap(), 0), 1);
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(8).unwrap(), 0), 1);
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(9).unwrap(), 0), 2);
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(16).unwrap(), 0), 2);
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(17).unwrap(), 0), 3);
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(32).unwrap(), 0), 3);
         assert_eq!(
-            steps_to_min_depth(NonZeroU64::new((1 << 60) + 1).unwrap()),
+            steps_to_min_depth(NonZeroU64::new((1 << 60) + 1).unwrap(), 0),
             Node::MAX_DEPTH
         );
         assert_eq!(
-            steps_to_min_depth(NonZeroU64::new(1 << 61).unwrap()),
+            steps_to_min_depth(NonZeroU64::new(1 << 61).unwrap(), 0),
             Node::MAX_DEPTH
         );
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(4).unwrap(), 1), 1);
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(5).unwrap(), 1), 2);
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(1).unwrap(), 3), 1);
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(2).unwrap(), 3), 2);
+        assert_eq!(steps_to_min_depth(NonZeroU64::new(1).unwrap(), 9), 7);
+    }
+
+    #[test]
+    fn isotropic_rows() {
+        // looks up each cell's neighbourhood one at a time, with dead cells past the edges
+        fn slow(table: &[u64; 8], rows: [u64; 3]) -> u64 {
+            let get = |cells: u64, i: i32| (0..64).contains(&i) && cells >> i & 1 != 0;
+            (0..64).fold(0, |next, i| {
+                let index = rows
+                    .iter()
+                    .flat_map(|&cells| [i + 1, i, i - 1].map(|i| get(cells, i)))
+                    .fold(0, |index, alive| index << 1 | usize::from(alive));
+                next | ((table[index / 64] >> (index % 64)) & 1) << i
+            })
+        }
+        let mut state = 1_u64;
+        let mut random = || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            state
+        };
+        for _ in 0..100 {
+            let mut table = [0; 8].map(|_: u64| random());
+            let rows = [
+                random(),
+                random() & random(),
+                random() & random() & random(),
+            ];
+            for b0 in [false, true] {
+                table[0] = table[0] & !1 | u64::from(b0);
+                let [above, row, below] = rows;
+                assert_eq!(
+                    step_row_isotropic(&table, above, row, below),
+                    slow(&table, rows)
+                );
+            }
+        }
+        assert_eq!(step_row_isotropic(&[!0; 8], 0, 0, 0), !0);
+    }
+
+    #[test]
+    fn totalistic_rows() {
+        // life through the masks matches its own step
+        let (born, survive) = (count_masks(1 << 3), count_masks(1 << 2 | 1 << 3));
+        let mut state = 1_u64;
+        let mut random = || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            state
+        };
+        for _ in 0..100 {
+            let [above, row, below] = [random(), random() & random(), random() | random()];
+            assert_eq!(
+                step_row_totalistic(&born, &survive, count_moore(above, row, below), row),
+                step_row_life(above, row, below)
+            );
+        }
+        // all 9 counts, with 8 only away from the ends of a full row
+        let (born, survive) = (count_masks(1), count_masks(1 << 8));
+        assert_eq!(
+            step_row_totalistic(&born, &survive, count_moore(!0, !0, !0), !0),
+            !(1 << 63 | 1)
+        );
+        assert_eq!(
+            step_row_totalistic(&born, &survive, count_moore(0, 0, 0), 0),
+            !0
+        );
     }
 
     fn assert_block_step(input: Block, steps: u64, output: Block) {
-        assert_eq!(input.expand().step_center(steps), output);
+        assert_rule_block_step(&Rule::LIFE, input, steps, output);
+        // same rule but going through the lookup table
+        assert_rule_block_step(&Rule::LIFE.to_isotropic(), input, steps, output);
+    }
+    fn assert_rule_block_step(rule: &Rule, input: Block, steps: u64, output: Block) {
+        let phases = Phases::new(rule);
+        assert_eq!(
+            input.expand().step_center(phases.start(false), steps),
+            output
+        );
     }
 
     #[test]
@@ -719,4 +1574,689 @@ mod tests {
         assert_block_step(a, 3, d);
         assert_block_step(a, 4, e);
     }
+
+    #[test]
+    fn block_highlife() {
+        let rule = "B36/S23".parse().unwrap();
+        let a = test_block! {"
+            ........
+            ........
+            ..ooo...
+            ..o.o...
+            ...o....
+            ........
+            ........
+            ........
+            "};
+        let b = test_block! {"
+            ........
+            ...o....
+            ..o.o...
+            ..ooo...
+            ...o....
+            ........
+            ........
+            ........
+            "};
+        assert_rule_block_step(&rule, a, 1, b);
+        // without B6 the center cell isn't born
+        let c = test_block! {"
+            ........
+            ...o....
+            ..o.o...
+            ..o.o...
+            ...o....
+            ........
+            ........
+            ........
+            "};
+        assert_block_step(a, 1, c);
+    }
+
+    #[test]
+    fn block_tlife() {
+        let rule = "B3/S2-i34q".parse().unwrap();
+        let a = test_block! {"
+            ........
+            ........
+            ...o....
+            ...o....
+            ...o....
+            ........
+            ........
+            ........
+            "};
+        // the center of the blinker has 2 neighbours in an i shape
+        let b = test_block! {"
+            ........
+            ........
+            ........
+            ..o.o...
+            ........
+            ........
+            ........
+            ........
+            "};
+        assert_rule_block_step(&rule, a, 1, b);
+    }
+
+    #[test]
+    fn block_seeds() {
+        let rule = "B2/S".parse().unwrap();
+        let a = test_block! {"
+            ........
+            ........
+            ........
+            ...oo...
+            ........
+            ........
+            ........
+            ........
+            "};
+        let b = test_block! {"
+            ........
+            ........
+            ...oo...
+            ........
+            ...oo...
+            ........
+            ........
+            ........
+            "};
+        assert_rule_block_step(&rule, a, 1, b);
+    }
+
+    #[test]
+    fn node_rules_dont_share_cache() {
+        let glider = Node::new(
+            test_block! {"
+                ........
+                ........
+                ........
+                ...ooo..
+                ...o....
+                ....o...
+                ........
+                ........
+                "},
+            Block::empty(),
+            Block::empty(),
+            Block::empty(),
+        );
+        let seeds = "B2/S".parse().unwrap();
+        let life = glider.step(&Rule::LIFE, 8);
+        assert_eq!(glider.step(&Rule::LIFE, 8), life);
+        assert_ne!(glider.step(&seeds, 8), life);
+        assert_eq!(glider.step(&Rule::LIFE, 8), life);
+    }
+
+    #[test]
+    fn caches_are_bounded() {
+        let glider = Node::new(
+            test_block! {"
+                ........
+                ........
+                ........
+                ...ooo..
+                ...o....
+                ....o...
+                ........
+                ........
+                "},
+            Block::empty(),
+            Block::empty(),
+            Block::empty(),
+        );
+        let life = glider.step(&Rule::LIFE, 8);
+        // more rules than keep their id, each with different survival counts
+        for survival in 0..u32::try_from(MAX_RULE_IDS).unwrap() + 10 {
+            let survival: String = (0..8)
+                .filter(|count| survival & 1 << count != 0)
+                .map(|count| char::from_digit(count, 10).unwrap())
+                .collect();
+            let rule = format!("B3/S{survival}").parse().unwrap();
+            glider.step(&rule, 8);
+        }
+        RULE_IDS.with_borrow(|rule_ids| assert_eq!(rule_ids.rules.len(), MAX_RULE_IDS));
+        STEP_CACHE.with_borrow(|step_cache| assert_eq!(step_cache.lru.cap(), LRU_CACHE_SIZE));
+        // life was dropped, but still steps the same with its new id
+        assert_eq!(glider.step(&Rule::LIFE, 8), life);
+    }
+
+    /// digits are states, and `.` is 0
+    fn multi_node(origin: Pos, rows: &str) -> MultiNode {
+        let mut node = MultiNode::empty(0);
+        for (y, row) in (origin.y..).zip(rows.split_whitespace()) {
+            for (x, c) in (origin.x..).zip(row.bytes()) {
+                if c != b'.' {
+                    node = node.set(Pos { x, y }, c - b'0');
+                }
+            }
+        }
+        node
+    }
+    fn assert_multi_step(rule: &Rule, input: &MultiNode, steps: u64, output: &MultiNode) {
+        let stepped = input.step(rule, steps);
+        let depth = stepped.depth().max(output.depth());
+        assert_eq!(
+            stepped.center_at_depth(depth),
+            output.center_at_depth(depth)
+        );
+    }
+
+    #[test]
+    fn multi_brians_brain() {
+        let rule = "/2/3".parse().unwrap();
+        let a = multi_node(
+            Pos { x: 0, y: 0 },
+            "
+            11
+            11
+            ",
+        );
+        let b = multi_node(
+            Pos { x: -1, y: -1 },
+            "
+            .11.
+            1221
+            1221
+            .11.
+            ",
+        );
+        let c = multi_node(
+            Pos { x: -4, y: -4 },
+            "
+            ..........
+            ....11....
+            ...1221...
+            ..12..21..
+            .12....21.
+            .12....21.
+            ..12..21..
+            ...1221...
+            ....11....
+            ..........
+            ",
+        );
+        assert_multi_step(&rule, &a, 1, &b);
+        assert_multi_step(&rule, &a, 3, &c);
+        assert_multi_step(&rule, &b, 2, &c);
+    }
+
+    #[test]
+    fn multi_life_matches_node() {
+        let glider = Node::new(
+            test_block! {"
+                ........
+                ........
+                ........
+                ...ooo..
+                ...o....
+                ....o...
+                ........
+                ........
+                "},
+            Block::empty(),
+            Block::empty(),
+            Block::empty(),
+        );
+        for steps in [1, 4, 8, 100, 1000] {
+            assert_multi_step(
+                &Rule::LIFE,
+                &MultiNode::from(glider.clone()),
+                steps,
+                &MultiNode::from(glider.step(&Rule::LIFE, steps)),
+            );
+        }
+    }
+
+    #[test]
+    fn multi_steps_at_once() {
+        let star_wars = "345/2/4".parse().unwrap();
+        let a = multi_node(
+            Pos { x: -4, y: -2 },
+            "
+            .1.31..2
+            2.11.1..
+            .131..1.
+            1...21.1
+            ",
+        );
+        let mut one_at_a_time = a.clone();
+        for steps in 1..=100 {
+            one_at_a_time = one_at_a_time.step(&star_wars, 1);
+            if steps % 20 == 0 {
+                assert_multi_step(&star_wars, &a, steps, &one_at_a_time);
+            }
+        }
+    }
+
+    #[test]
+    fn hexagonal() {
+        let rule = "B2/S34H".parse().unwrap();
+        let a = multi_node(
+            Pos { x: -4, y: -4 },
+            "
+            1.1..11.
+            11.....1
+            .....1..
+            11......
+            1....111
+            ...1.11.
+            .......1
+            .....1..
+            ",
+        );
+        let b = multi_node(
+            Pos { x: 0, y: -4 },
+            "
+            ..1.
+            ...1
+            ....
+            ....
+            ....
+            1.1.
+            .11.
+            ",
+        );
+        let stepped = a.occupied().step(&rule, 6);
+        assert_eq!(stepped, b.occupied().center_at_depth(stepped.depth()));
+        // and the same through the multi-state base case
+        assert_multi_step(&rule, &a, 6, &b);
+    }
+
+    #[test]
+    fn von_neumann() {
+        let rule = "B1/S01V".parse().unwrap();
+        let a = multi_node(
+            Pos { x: -4, y: -4 },
+            "
+            1..1....
+            11...1..
+            1..11..1
+            1.11..11
+            1.11...1
+            ..11....
+            ..1.....
+            11..1...
+            ",
+        );
+        let b = multi_node(
+            Pos { x: -7, y: -7 },
+            "
+            ...1..1.......
+            ..11.1111.....
+            .11.1.1.111...
+            1111..1.1111..
+            1.1.1.11..1.1.
+            1..1...1.1.111
+            1.1111..1111.1
+            111.11.1..1111
+            .1.11.1.1..11.
+            .11..1....11..
+            1111..1.11....
+            .111.1.1.1....
+            ..111.111.....
+            ...11..1......
+            ",
+        );
+        let stepped = a.occupied().step(&rule, 3);
+        assert_eq!(stepped, b.occupied().center_at_depth(stepped.depth()));
+        assert_multi_step(&rule, &a, 3, &b);
+    }
+
+    #[test]
+    fn b0_with_max_survival() {
+        let rule = "B0123478/S34678".parse().unwrap();
+        let a = multi_node(
+            Pos { x: -4, y: -4 },
+            "
+            ......1.
+            ...1.1..
+            111..1.1
+            .11.11..
+            1...1...
+            .111111.
+            1.11..1.
+            .1.1..1.
+            ",
+        )
+        .occupied();
+        // the dead cells on a live background
+        let b = multi_node(
+            Pos { x: -4, y: -4 },
+            "
+            ....11..
+            ...11.1.
+            ...1..11
+            ...1.111
+            ....11..
+            11.....1
+            ...1.111
+            11...11.
+            ",
+        )
+        .occupied();
+        let (stepped, background) = a.step_with_background(&rule, 5, false);
+        assert!(background);
+        assert_eq!(stepped, b.center_at_depth(stepped.depth()));
+        // and stays alive
+        let (two_parts, background) = a
+            .step_with_background(&rule, 2, false)
+            .0
+            .step_with_background(&rule, 3, true);
+        assert!(background);
+        assert_eq!(two_parts, stepped.center_at_depth(two_parts.depth()));
+    }
+
+    #[test]
+    fn b0_alternating() {
+        let rule = "B0124/S34".parse().unwrap();
+        let a = multi_node(
+            Pos { x: -4, y: -4 },
+            "
+            ..11...1
+            ...111..
+            ..1111.1
+            1...1111
+            ...1....
+            ..1..1..
+            ......1.
+            ......11
+            ",
+        );
+        let b = multi_node(
+            Pos { x: -3, y: -4 },
+            "
+            .1..1.
+            ..11.1
+            111111
+            1111.1
+            ....1.
+            ..1...
+            .....1
+            ",
+        );
+        let (stepped, background) = a.occupied().step_with_background(&rule, 3, false);
+        assert!(background);
+        assert_eq!(stepped, b.occupied().center_at_depth(stepped.depth()));
+        assert_multi_step(&rule, &a, 3, &b);
+
+        // even steps end on a dead background, however the steps are split up
+        let at_once = a.occupied().step(&rule, 40);
+        let mut node = a.occupied();
+        let mut background = false;
+        for steps in [1, 6, 3, 17, 13] {
+            (node, background) = node.step_with_background(&rule, steps, background);
+        }
+        assert!(!background);
+        let depth = node.depth().max(at_once.depth());
+        assert_eq!(node.center_at_depth(depth), at_once.center_at_depth(depth));
+    }
+
+    #[test]
+    fn ltl_range_1_matches_life() {
+        let rule = "R1,C0,M0,S2..3,B3..3,NM".parse().unwrap();
+        let a = multi_node(
+            Pos { x: -4, y: -2 },
+            "
+            .1.11..1
+            1.11.1..
+            .111..1.
+            1...11.1
+            ",
+        )
+        .occupied();
+        for steps in [1, 4, 8, 100] {
+            assert_eq!(a.step(&rule, steps), a.step(&Rule::LIFE, steps));
+        }
+    }
+
+    #[test]
+    fn ltl_bosco() {
+        let bosco = "R5,C0,M1,S34..58,B34..45,NM".parse().unwrap();
+        let a = multi_node(
+            Pos { x: -6, y: -6 },
+            "
+            11.1.11.1111
+            1.11...1.1.1
+            111.1..1.111
+            .11.11..1...
+            .1.11.111...
+            .1...1..1.1.
+            ...11.11111.
+            111.11....11
+            1..11111.111
+            1......1....
+            111.11111111
+            111..11111..
+            ",
+        );
+        let b = multi_node(
+            Pos { x: -4, y: -4 },
+            "
+            ...111..
+            .111111.
+            .11..11.
+            11....11
+            11....11
+            11....11
+            .111111.
+            ..1111..
+            ",
+        );
+        assert_multi_step(&bosco, &a, 4, &b);
+        let stepped = a.occupied().step(&bosco, 4);
+        assert_eq!(stepped, b.occupied().center_at_depth(stepped.depth()));
+    }
+
+    #[test]
+    fn ltl_von_neumann_generations() {
+        let rule = "R2,C3,M0,S1..3,B2..3,NN".parse().unwrap();
+        let a = multi_node(
+            Pos { x: -3, y: -3 },
+            "
+            11.1.1
+            1.1111
+            1.11..
+            .1.1.1
+            111.1.
+            .1.111
+            ",
+        );
+        let b = multi_node(
+            Pos { x: -6, y: -6 },
+            "
+            .1111111111.
+            1.11222211.1
+            11........11
+            11..11....21
+            12.12..1.22.
+            12.1..11122.
+            12.....1..21
+            12...111.22.
+            11..1..2.22.
+            11...1..2221
+            1.112222.2.1
+            .1111.11111.
+            ",
+        );
+        assert_multi_step(&rule, &a, 3, &b);
+    }
+
+    #[test]
+    fn ltl_steps_at_once() {
+        // range 20 steps densely above the leaves
+        for rule in [
+            "R5,C0,M1,S34..58,B34..45,NM",
+            "R20,C4,M0,S300..700,B250..400,NN",
+        ] {
+            let rule = rule.parse().unwrap();
+            let a = multi_node(
+                Pos { x: -6, y: -6 },
+                "
+                11.1.11.1111
+                1.11...1.1.1
+                111.1..1.111
+                .11.11..1...
+                .1.11.111...
+                .1...1..1.1.
+                ...11.11111.
+                111.11....11
+                1..11111.111
+                1......1....
+                111.11111111
+                111..11111..
+                ",
+            );
+            let mut one_at_a_time = a.clone();
+            for steps in 1..=12 {
+                one_at_a_time = one_at_a_time.step(&rule, 1);
+                if steps % 4 == 0 {
+                    assert_multi_step(&rule, &a, steps, &one_at_a_time);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn table_wireworld() {
+        let rule = Rule::read_from_string(
+            "@RULE WireWorld
+            @TABLE
+            n_states:4
+            neighborhood:Moore
+            symmetries:permute
+            var a={0,1,2,3}
+            var b={a}
+            var c={a}
+            var d={a}
+            var e={a}
+            var f={a}
+            var g={a}
+            var h={a}
+            var i={0,2,3}
+            var j={i}
+            var k={i}
+            var l={i}
+            var m={i}
+            var n={i}
+            var o={i}
+            1,a,b,c,d,e,f,g,h,2
+            2,a,b,c,d,e,f,g,h,3
+            3,1,i,j,k,l,m,n,o,1
+            3,1,1,i,j,k,l,m,n,1
+            ",
+        )
+        .unwrap();
+        let a = multi_node(
+            Pos { x: -3, y: -1 },
+            "
+            .2133.
+            3....3
+            .3333.
+            ",
+        );
+        let b = multi_node(
+            Pos { x: -3, y: -1 },
+            "
+            .3332.
+            3....1
+            .3333.
+            ",
+        );
+        assert_multi_step(&rule, &a, 3, &b);
+        // the electron goes around the loop every 10 generations
+        for steps in [10, 20, 10 << 10] {
+            assert_multi_step(&rule, &a, steps, &a);
+        }
+    }
+
+    #[test]
+    fn table_von_neumann() {
+        // any cell next to a live cell is born, and live cells die
+        let rule = Rule::read_from_string(
+            "@RULE Spread
+            @TABLE
+            n_states:2
+            neighborhood:vonNeumann
+            symmetries:rotate4
+            var a={0,1}
+            var b={a}
+            var c={a}
+            var d={a}
+            0,1,a,b,c,1
+            1,a,b,c,d,0
+            ",
+        )
+        .unwrap();
+        let totalistic = "B1234/SV".parse().unwrap();
+        let a = multi_node(
+            Pos { x: -2, y: -1 },
+            "
+            1..1.
+            .1..1
+            11...
+            ",
+        );
+        for steps in [1, 2, 5, 16] {
+            assert_eq!(a.step(&rule, steps), a.step(&totalistic, steps));
+        }
+    }
+
+    #[test]
+    fn tree_life() {
+        let rule = Rule::read_from_string(
+            "@RULE LifeTree
+            @TREE
+            num_states=2
+            num_neighbors=8
+            num_nodes=32
+            1 0 0
+            2 0 0
+            1 0 1
+            2 0 2
+            3 1 3
+            1 1 1
+            2 2 5
+            3 3 6
+            4 4 7
+            2 5 0
+            3 6 9
+            4 7 10
+            5 8 11
+            3 9 1
+            4 10 13
+            5 11 14
+            6 12 15
+            3 1 1
+            4 13 17
+            5 14 18
+            6 15 19
+            7 16 20
+            4 17 17
+            5 18 22
+            6 19 23
+            7 20 24
+            8 21 25
+            5 22 22
+            6 23 27
+            7 24 28
+            8 25 29
+            9 26 30
+            ",
+        )
+        .unwrap();
+        let a = multi_node(
+            Pos { x: -1, y: -1 },
+            "
+            .11
+            11.
+            .1.
+            ",
+        );
+        for steps in [1, 4, 30, 100] {
+            assert_eq!(a.step(&rule, steps), a.step(&Rule::LIFE, steps));
+        }
+    }
 }
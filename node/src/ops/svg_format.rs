@@ -0,0 +1,286 @@
+//! draws an area of a node as an SVG, with the live cells merged into as few rectangles as possible
+//!
+//! each cell is 1 unit of the `viewBox`, so the drawing can be scaled without blurring
+
+use std::collections::HashMap;
+use std::io::{Error as IoError, Write};
+
+use crate::{Node, Rect};
+
+// the cells and the selection are the same colors as in the GUI
+const BACKGROUND: &str = "#000000";
+const ALIVE: &str = "#ffffff";
+const GRID: &str = "#404040";
+const BOUNDING_BOX: &str = "#ff8000";
+const SELECTION: &str = "#0080ff";
+
+/// what to draw over the cells, and how big the cells are
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SvgOptions {
+    pub cell_size: u32,
+    pub grid: bool,
+    pub bounding_box: bool,
+    pub selection: Option<Rect>,
+}
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            cell_size: 8,
+            grid: false,
+            bounding_box: false,
+            selection: None,
+        }
+    }
+}
+impl SvgOptions {
+    /// the width of a cell in pixels
+    pub fn with_cell_size(self, cell_size: u32) -> Self {
+        Self { cell_size, ..self }
+    }
+    /// lines between every cell
+    pub fn with_grid(self) -> Self {
+        Self { grid: true, ..self }
+    }
+    /// an outline around the live cells
+    pub fn with_bounding_box(self) -> Self {
+        Self {
+            bounding_box: true,
+            ..self
+        }
+    }
+    /// a translucent rect over the cells like the GUI's selection
+    pub fn with_selection(self, selection: Rect) -> Self {
+        Self {
+            selection: Some(selection),
+            ..self
+        }
+    }
+}
+
+impl Node {
+    /// only the cells in `rect` are drawn, with the north west corner of `rect` at the top left
+    pub fn write_svg(
+        &self,
+        rect: Rect,
+        options: &SvgOptions,
+        mut write: impl Write,
+    ) -> Result<(), IoError> {
+        let side = |min: i64, max: i64| (i128::from(max) - i128::from(min) + 1).max(0);
+        let width = side(rect.west(), rect.east());
+        let height = side(rect.north(), rect.south());
+        let cell_size = i128::from(options.cell_size);
+        writeln!(
+            write,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {width} {height}" shape-rendering="crispEdges">"#,
+            width * cell_size,
+            height * cell_size,
+        )?;
+        writeln!(
+            write,
+            r#"<rect width="{width}" height="{height}" fill="{BACKGROUND}"/>"#
+        )?;
+        // relative to the north west corner of `rect`, which may not fit in an i64
+        let to_svg = |area: Rect| {
+            let x = i128::from(area.west()) - i128::from(rect.west());
+            let y = i128::from(area.north()) - i128::from(rect.north());
+            (
+                x,
+                y,
+                side(area.west(), area.east()),
+                side(area.north(), area.south()),
+            )
+        };
+
+        let cells = if rect.is_empty() {
+            Vec::new()
+        } else {
+            merged_cells(&self.clip(rect))
+        };
+        if !cells.is_empty() {
+            writeln!(write, r#"<g fill="{ALIVE}">"#)?;
+            for &cell in &cells {
+                let (x, y, w, h) = to_svg(cell);
+                writeln!(write, r#"<rect x="{x}" y="{y}" width="{w}" height="{h}"/>"#)?;
+            }
+            writeln!(write, "</g>")?;
+        }
+        if options.grid && width > 0 && height > 0 {
+            write!(write, r#"<path stroke="{GRID}" stroke-width="0.0625" d=""#)?;
+            for x in 1..width {
+                write!(write, "M{x} 0V{height}")?;
+            }
+            for y in 1..height {
+                write!(write, "M0 {y}H{width}")?;
+            }
+            writeln!(write, r#""/>"#)?;
+        }
+        if options.bounding_box {
+            let mut bounds = Rect::NOTHING;
+            for cell in &cells {
+                bounds.union(*cell);
+            }
+            if !bounds.is_empty() {
+                let (x, y, w, h) = to_svg(bounds);
+                writeln!(
+                    write,
+                    r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" fill="none" stroke="{BOUNDING_BOX}" stroke-width="0.125"/>"#
+                )?;
+            }
+        }
+        if let Some(mut selection) = options.selection {
+            selection.intersection(rect);
+            if !selection.is_empty() {
+                let (x, y, w, h) = to_svg(selection);
+                writeln!(
+                    write,
+                    r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" fill="{SELECTION}" fill-opacity="0.5"/>"#
+                )?;
+            }
+        }
+        writeln!(write, "</svg>")
+    }
+    pub fn write_svg_to_string(&self, rect: Rect, options: &SvgOptions) -> String {
+        let mut out = Vec::new();
+        self.write_svg(rect, options, &mut out)
+            .expect("valid bytes");
+        String::from_utf8(out).expect("valid string")
+    }
+}
+
+/// the live cells as rects, where runs of the same columns in the rows below each other are merged
+///
+/// sorted by their north west corners in reading order
+fn merged_cells(node: &Node) -> Vec<Rect> {
+    let mut done = Vec::new();
+    // the rects that can still be extended by the next row, keyed by their columns
+    let mut open: HashMap<(i64, i64), Rect> = HashMap::new();
+    for run in node.runs() {
+        let mut cell = Rect::min_max(run.pos, run.pos);
+        cell.set_east(run.pos.x + run.len - 1);
+        match open.get_mut(&(run.pos.x, run.len)) {
+            Some(rect) if rect.south() + 1 == run.pos.y => rect.set_south(run.pos.y),
+            Some(rect) => done.push(std::mem::replace(rect, cell)),
+            None => {
+                open.insert((run.pos.x, run.len), cell);
+            }
+        }
+    }
+    done.extend(open.into_values());
+    done.sort_by_key(|rect| (rect.north(), rect.west()));
+    done
+}
+
+// tests
+
+#[cfg(test)]
+mod test {
+    use unindent::unindent;
+
+    use super::SvgOptions;
+    use crate::{test_node, Pos, Rect};
+
+    #[test]
+    fn glider() {
+        let glider = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            .........o......
+            ..........o.....
+            ........ooo.....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        let rect = Rect::min_max(Pos::new(0, 0), Pos::new(3, 2));
+        let svg = unindent(
+            r##"
+            <svg xmlns="http://www.w3.org/2000/svg" width="32" height="24" viewBox="0 0 4 3" shape-rendering="crispEdges">
+            <rect width="4" height="3" fill="#000000"/>
+            <g fill="#ffffff">
+            <rect x="1" y="0" width="1" height="1"/>
+            <rect x="2" y="1" width="1" height="1"/>
+            <rect x="0" y="2" width="3" height="1"/>
+            </g>
+            </svg>
+            "##,
+        );
+        assert_eq!(
+            glider.write_svg_to_string(rect, &SvgOptions::default()),
+            svg
+        );
+    }
+
+    #[test]
+    fn merged() {
+        // a block next to a column that is cut off by the rect, with everything turned on
+        let node = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ...........o....
+            ........oo.o....
+            ........oo.o....
+            ...........o....
+            ................
+            ................
+            ................
+            ................
+            ................
+            "};
+        let rect = Rect::min_max(Pos::new(-1, 0), Pos::new(3, 1));
+        let options = SvgOptions::default()
+            .with_cell_size(1)
+            .with_grid()
+            .with_bounding_box()
+            .with_selection(Rect::min_max(Pos::new(3, 1), Pos::new(10, 10)));
+        let svg = unindent(
+            r##"
+            <svg xmlns="http://www.w3.org/2000/svg" width="5" height="2" viewBox="0 0 5 2" shape-rendering="crispEdges">
+            <rect width="5" height="2" fill="#000000"/>
+            <g fill="#ffffff">
+            <rect x="1" y="0" width="2" height="2"/>
+            <rect x="4" y="0" width="1" height="2"/>
+            </g>
+            <path stroke="#404040" stroke-width="0.0625" d="M1 0V2M2 0V2M3 0V2M4 0V2M0 1H5"/>
+            <rect x="1" y="0" width="4" height="2" fill="none" stroke="#ff8000" stroke-width="0.125"/>
+            <rect x="4" y="1" width="1" height="1" fill="#0080ff" fill-opacity="0.5"/>
+            </svg>
+            "##,
+        );
+        assert_eq!(node.write_svg_to_string(rect, &options), svg);
+        // runs with a gap between them aren't merged
+        let gap = test_node! {"
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ................
+            ........o.......
+            ................
+            ........o.......
+            ........o.......
+            ................
+            ................
+            ................
+            ................
+            "};
+        let svg = gap.write_svg_to_string(Rect::EVERYTHING, &SvgOptions::default());
+        assert!(svg.contains(r#"y="9223372036854775808" width="1" height="1"/>"#));
+        assert!(svg.contains(r#"y="9223372036854775810" width="1" height="2"/>"#));
+    }
+}
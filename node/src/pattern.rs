@@ -0,0 +1,76 @@
+//! a pattern along with the metadata that pattern files store next to it
+
+use num_bigint::BigUint;
+
+use crate::{Node, Rule};
+
+/// A pattern and its metadata, as read from or written to a pattern file
+///
+/// `N` is the kind of node, either `Node` or `MultiNode`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Pattern<N = Node> {
+    pub node: N,
+    /// `None` if the file didn't say which rule the pattern uses
+    ///
+    /// rules that can't be parsed, such as `LifeHistory` or the name of a `.rule` file, are kept as text in `Err`
+    /// so they are written back as they were read
+    pub rule: Option<Result<Rule, String>>,
+    /// how many generations the pattern has been stepped, which can be larger than `u64` in huge patterns
+    pub generation: BigUint,
+    pub name: Option<String>,
+    pub author: Option<String>,
+    /// each line of the comments in order, without the leading `#C`
+    pub comments: Vec<String>,
+}
+impl<N> Pattern<N> {
+    /// a pattern at generation 0 without any metadata
+    pub fn new(node: N) -> Self {
+        Self {
+            node,
+            rule: None,
+            generation: BigUint::default(),
+            name: None,
+            author: None,
+            comments: Vec::new(),
+        }
+    }
+    pub fn with_rule(mut self, rule: Rule) -> Self {
+        self.rule = Some(Ok(rule));
+        self
+    }
+    /// the rule as it is written in a file
+    pub fn rule_name(&self) -> Option<String> {
+        match &self.rule {
+            Some(Ok(rule)) => Some(rule.to_string()),
+            Some(Err(name)) => Some(name.clone()),
+            None => None,
+        }
+    }
+    pub fn with_generation(mut self, generation: impl Into<BigUint>) -> Self {
+        self.generation = generation.into();
+        self
+    }
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.comments.push(comment.into());
+        self
+    }
+    /// keeps the metadata while replacing the node
+    pub fn map<M>(self, f: impl FnOnce(N) -> M) -> Pattern<M> {
+        Pattern {
+            node: f(self.node),
+            rule: self.rule,
+            generation: self.generation,
+            name: self.name,
+            author: self.author,
+            comments: self.comments,
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Hensel notation for isotropic non-totalistic rules
+//!
+//! neighbourhoods are 8 bits in reading order, skipping the center cell
+//! ```text
+//! 7 6 5
+//! 4 . 3
+//! 2 1 0
+//! ```
+//! and a full 3x3 neighbourhood (including the center cell) is 9 bits in reading order
+//! ```text
+//! 8 7 6
+//! 5 4 3
+//! 2 1 0
+//! ```
+
+use std::fmt::Write;
+
+/// a 512 bit table where bit `i` is set if a cell with the 3x3 neighbourhood `i` will be alive
+pub(crate) type NeighbourhoodTable = [u64; 8];
+
+/// letters in the order they are written, counts over 4 use the same letters as `8 - count`
+const LETTERS: [&[u8]; 5] = [b"", b"ce", b"cekain", b"cekainyqjr", b"cekainyqjrtwz"];
+/// a representative 3x3 neighbourhood for each letter in `LETTERS`
+/// counts of 0 don't have a letter, but still need a neighbourhood
+const NEIGHBOURHOODS: [&[u16]; 5] = [
+    &[0],
+    &[1, 2],
+    &[5, 10, 33, 3, 40, 68],
+    &[69, 42, 98, 11, 7, 13, 97, 70, 14, 41],
+    &[325, 170, 99, 15, 45, 71, 78, 102, 106, 43, 101, 105, 108],
+];
+
+/// letters for a neighbour count, is empty for 0 and 8
+pub(crate) fn letters(count: u8) -> &'static [u8] {
+    LETTERS[usize::from(count.min(8 - count))]
+}
+/// bit mask with a bit set for every letter (or configuration if there are no letters) of a neighbour count
+pub(crate) fn all_letters(count: u8) -> u16 {
+    let len = NEIGHBOURHOODS[usize::from(count.min(8 - count))].len();
+    (1 << len) - 1
+}
+/// representative neighbours (without the center cell) for a count and index into `letters(count)`
+fn neighbours(count: u8, letter: usize) -> u8 {
+    let index = NEIGHBOURHOODS[usize::from(count.min(8 - count))][letter];
+    let neighbours = without_center(index);
+    if count > 4 {
+        !neighbours
+    } else {
+        neighbours
+    }
+}
+#[allow(clippy::cast_possible_truncation)] // masked to 8 bits
+fn without_center(index: u16) -> u8 {
+    (((index >> 5) << 4) | (index & 0xf)) as u8
+}
+fn with_center(neighbours: u8, alive: bool) -> usize {
+    let neighbours = usize::from(neighbours);
+    ((neighbours >> 4) << 5) | (usize::from(alive) << 4) | (neighbours & 0xf)
+}
+
+/// all 8 rotations and reflections of some neighbours
+fn symmetries(neighbours: u8) -> [u8; 8] {
+    // each bit moves to a new position when rotated clockwise or flipped horizontally
+    const ROTATE_CW: [u8; 8] = [2, 4, 7, 1, 6, 0, 3, 5];
+    const FLIP_H: [u8; 8] = [2, 1, 0, 4, 3, 7, 6, 5];
+    fn apply(neighbours: u8, map: [u8; 8]) -> u8 {
+        (0..8)
+            .filter(|bit| neighbours & (1 << bit) != 0)
+            .fold(0, |out, bit| out | (1 << map[bit]))
+    }
+    let mut out = [neighbours; 8];
+    for i in 1..4 {
+        out[i] = apply(out[i - 1], ROTATE_CW);
+    }
+    for i in 4..8 {
+        out[i] = apply(out[i - 4], FLIP_H);
+    }
+    out
+}
+
+/// neighbour count and index into `letters(count)` of a configuration of neighbours
+fn classify(neighbours: u8) -> (u8, usize) {
+    #[allow(clippy::cast_possible_truncation)] // at most 8
+    let count = neighbours.count_ones() as u8;
+    let letter = (0..all_letters(count).count_ones() as usize)
+        .find(|&letter| symmetries(self::neighbours(count, letter)).contains(&neighbours))
+        .expect("every configuration to have a letter");
+    (count, letter)
+}
+
+/// builds a table from bit masks of letters for each neighbour count
+pub(crate) fn table_from_letters(birth: &[u16; 9], survival: &[u16; 9]) -> NeighbourhoodTable {
+    let mut table = [0; 8];
+    for neighbours in 0..=u8::MAX {
+        let (count, letter) = classify(neighbours);
+        for (alive, letters) in [(false, birth), (true, survival)] {
+            if letters[usize::from(count)] & (1 << letter) != 0 {
+                let index = with_center(neighbours, alive);
+                table[index / 64] |= 1 << (index % 64);
+            }
+        }
+    }
+    table
+}
+/// inverse of `table_from_letters`, assumes the table is isotropic
+pub(crate) fn letters_from_table(table: &NeighbourhoodTable) -> ([u16; 9], [u16; 9]) {
+    let mut birth = [0; 9];
+    let mut survival = [0; 9];
+    for count in 0..=8 {
+        for letter in 0..all_letters(count).count_ones() as usize {
+            let neighbours = neighbours(count, letter);
+            for (alive, letters) in [(false, &mut birth), (true, &mut survival)] {
+                let index = with_center(neighbours, alive);
+                if table[index / 64] & (1 << (index % 64)) != 0 {
+                    letters[usize::from(count)] |= 1 << letter;
+                }
+            }
+        }
+    }
+    (birth, survival)
+}
+
+/// writes counts like `2-a3cnqy4`, using whichever of the positive or negative form is shorter
+pub(crate) fn write_letters(f: &mut impl Write, letters: &[u16; 9]) -> std::fmt::Result {
+    for (count, &present) in (0..).zip(letters) {
+        let all = all_letters(count);
+        if present == 0 {
+            continue;
+        }
+        write!(f, "{count}")?;
+        if present == all {
+            continue;
+        }
+        let (negate, shown) = if present.count_ones() * 2 > all.count_ones() {
+            (true, all & !present)
+        } else {
+            (false, present)
+        };
+        if negate {
+            f.write_char('-')?;
+        }
+        for (i, &letter) in self::letters(count).iter().enumerate() {
+            if shown & (1 << i) != 0 {
+                f.write_char(letter.into())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{all_letters, classify, letters, neighbours, symmetries};
+
+    #[test]
+    fn letters_cover_every_configuration() {
+        // 256 configurations of neighbours, grouped by count
+        let mut seen = [0_u32; 9];
+        for n in 0..=u8::MAX {
+            let (count, letter) = classify(n);
+            assert!(all_letters(count) & (1 << letter) != 0);
+            seen[usize::from(count)] += 1;
+        }
+        assert_eq!(seen, [1, 8, 28, 56, 70, 56, 28, 8, 1]);
+    }
+
+    #[test]
+    fn letters_are_distinct() {
+        for count in 0..=8 {
+            let len = all_letters(count).count_ones() as usize;
+            assert_eq!(letters(count).len(), if len == 1 { 0 } else { len });
+            for letter in 0..len {
+                let n = neighbours(count, letter);
+                assert_eq!(n.count_ones(), u32::from(count));
+                assert_eq!(
+                    classify(n),
+                    (count, letter),
+                    "count={count} letter={letter}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn symmetries_are_a_group() {
+        // nw corner goes to every corner, n edge goes to every edge
+        let corners = symmetries(0b1000_0000);
+        for corner in [0b1000_0000, 0b0010_0000, 0b0000_0100, 0b0000_0001] {
+            assert!(corners.contains(&corner));
+        }
+        let edges = symmetries(0b0100_0000);
+        for edge in [0b0100_0000, 0b0001_0000, 0b0000_1000, 0b0000_0010] {
+            assert!(edges.contains(&edge));
+        }
+    }
+}
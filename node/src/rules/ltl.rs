@@ -0,0 +1,55 @@
+//! Larger than Life rules, written like `R5,C0,M1,S34..58,B34..45,NM`
+//!
+//! cells count every live cell within `range`, including themselves if `middle` is set,
+//! and are alive next generation if that count is within the birth or survival bounds
+
+use std::fmt::Write;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum LtlNeighbourhood {
+    /// a square of width `2 * range + 1`
+    Moore,
+    /// a diamond of cells within a manhattan distance of `range`
+    VonNeumann,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct Ltl {
+    pub(crate) range: u16,
+    pub(crate) middle: bool,
+    /// inclusive bounds
+    pub(crate) survival: (u32, u32),
+    /// inclusive bounds
+    pub(crate) birth: (u32, u32),
+    pub(crate) neighbourhood: LtlNeighbourhood,
+}
+impl Ltl {
+    pub(crate) const MAX_RANGE: u16 = 500;
+
+    /// if a cell will be alive given its count of live cells
+    pub(crate) fn is_alive(&self, alive: bool, count: u32) -> bool {
+        let (min, max) = if alive { self.survival } else { self.birth };
+        min <= count && count <= max
+    }
+
+    pub(crate) fn write(&self, f: &mut impl Write, states: u16) -> std::fmt::Result {
+        let Ltl {
+            range,
+            middle,
+            survival: (s_min, s_max),
+            birth: (b_min, b_max),
+            neighbourhood,
+        } = self;
+        // two-state rules are written with 0 states
+        let states = if states == 2 { 0 } else { states };
+        let middle = u8::from(*middle);
+        let neighbourhood = match neighbourhood {
+            LtlNeighbourhood::Moore => 'M',
+            LtlNeighbourhood::VonNeumann => 'N',
+        };
+        write!(
+            f,
+            "R{range},C{states},M{middle},S{s_min}..{s_max},B{b_min}..{b_max},N{neighbourhood}"
+        )
+    }
+}
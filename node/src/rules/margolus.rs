@@ -0,0 +1,196 @@
+//! block cellular automata using the Margolus neighbourhood
+//!
+//! the grid is split into 2x2 blocks which are each replaced using a table,
+//! and the blocks are offset by 1 cell diagonally every other generation
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use super::rule::{RuleError, RuleErrorHint};
+
+/// A block rule written as a table such as `M0,8,4,3,2,5,9,7,1,6,10,11,12,13,14,15`
+///
+/// entry `i` of the table is the next block for the block `i`,
+/// where the cells of a block are the bits nw = 1, ne = 2, sw = 4, and se = 8
+///
+/// even generations have blocks with their north west cell at even coordinates,
+/// odd generations have blocks with their north west cell at odd coordinates
+///
+/// empty blocks must either stay empty, or swap with full blocks every generation as in `CRITTERS`,
+/// which is stepped like a B0 rule, see `Node::step_margolus`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MargolusRule {
+    table: [u8; 16],
+}
+impl MargolusRule {
+    /// balls move diagonally and bounce off each other and walls
+    pub const BILLIARD_BALL_MACHINE: MargolusRule = MargolusRule {
+        table: [0, 8, 4, 3, 2, 5, 9, 7, 1, 6, 10, 11, 12, 13, 14, 15],
+    };
+    /// blocks are inverted and rotated 180 degrees unless they have 2 live cells
+    pub const CRITTERS: MargolusRule = MargolusRule {
+        table: [15, 14, 13, 3, 11, 5, 6, 1, 7, 9, 10, 2, 12, 4, 8, 0],
+    };
+    /// blocks are inverted if they are empty or full
+    pub const TRON: MargolusRule = MargolusRule {
+        table: [15, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 0],
+    };
+    const FULL: u8 = 0b1111;
+
+    /// `None` if an entry isn't a block, or if empty blocks neither stay empty nor swap with full blocks
+    pub fn new(table: [u8; 16]) -> Option<MargolusRule> {
+        let is_valid = table.iter().all(|&block| block <= Self::FULL)
+            && (table[0] == 0 || (table[0] == Self::FULL && table[15] == 0));
+        is_valid.then_some(MargolusRule { table })
+    }
+    pub fn table(&self) -> &[u8; 16] {
+        &self.table
+    }
+    /// if every block has a different next block, so that the rule can be stepped backwards
+    pub fn is_reversible(&self) -> bool {
+        (0..16).all(|block| self.table.contains(&block))
+    }
+    /// the rule that undoes this rule, if it is reversible
+    pub fn inverse(&self) -> Option<MargolusRule> {
+        if !self.is_reversible() {
+            return None;
+        }
+        let mut table = [0; 16];
+        for (block, &next) in (0..).zip(&self.table) {
+            table[usize::from(next)] = block;
+        }
+        Some(MargolusRule { table })
+    }
+    /// if empty space becomes full every other generation
+    fn flips(&self) -> bool {
+        self.table[0] == Self::FULL
+    }
+
+    /// the tables for blocks at even and odd coordinates, with `backwards` using the inverse,
+    /// which keep empty space empty by inverting the cells of odd generations for rules that flip empty space
+    pub(crate) fn phases(&self, backwards: bool) -> [[u8; 16]; 2] {
+        let table = if backwards {
+            self.inverse()
+                .expect("only reversible rules can be stepped backwards")
+                .table
+        } else {
+            self.table
+        };
+        let invert = |invert_current: bool, invert_next: bool| {
+            std::array::from_fn(|block| {
+                let current = if invert_current {
+                    block ^ 0b1111
+                } else {
+                    block
+                };
+                let next = table[current];
+                if invert_next {
+                    next ^ Self::FULL
+                } else {
+                    next
+                }
+            })
+        };
+        match (self.flips(), backwards) {
+            (false, _) => [table; 2],
+            (true, false) => [invert(false, true), invert(true, false)],
+            (true, true) => [invert(true, false), invert(false, true)],
+        }
+    }
+}
+impl Display for MargolusRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "M")?;
+        for (i, block) in self.table.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{block}")?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for MargolusRule {
+    type Err = RuleError;
+
+    /// accepts an `M` followed by 16 comma separated blocks, as in `M0,8,4,3,2,5,9,7,1,6,10,11,12,13,14,15`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(blocks) = s.strip_prefix(['M', 'm']) else {
+            return Err(RuleError::new(0, RuleErrorHint::InvalidMargolus));
+        };
+        let mut table = [0; 16];
+        let mut at = 1;
+        let mut blocks = blocks.split(',');
+        for entry in &mut table {
+            let block = blocks.next().unwrap_or("");
+            *entry = match block.trim().parse::<u8>() {
+                Ok(block) if block <= Self::FULL => block,
+                _ => {
+                    return Err(RuleError::new(
+                        at.min(s.len()),
+                        RuleErrorHint::InvalidMargolus,
+                    ))
+                }
+            };
+            at += block.len() + 1;
+        }
+        if blocks.next().is_some() {
+            return Err(RuleError::new(at, RuleErrorHint::InvalidMargolus));
+        }
+        MargolusRule::new(table).ok_or(RuleError::new(1, RuleErrorHint::MargolusBackground))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MargolusRule;
+
+    #[test]
+    fn round_trip() {
+        for rule in [
+            MargolusRule::BILLIARD_BALL_MACHINE,
+            MargolusRule::CRITTERS,
+            MargolusRule::TRON,
+        ] {
+            assert_eq!(rule.to_string().parse(), Ok(rule));
+        }
+        assert_eq!(
+            "M0,8,4,3,2,5,9,7,1,6,10,11,12,13,14,15".parse(),
+            Ok(MargolusRule::BILLIARD_BALL_MACHINE)
+        );
+    }
+
+    #[test]
+    fn inverse() {
+        for rule in [
+            MargolusRule::BILLIARD_BALL_MACHINE,
+            MargolusRule::CRITTERS,
+            MargolusRule::TRON,
+        ] {
+            let inverse = rule.inverse().unwrap();
+            for (block, &next) in (0..).zip(rule.table()) {
+                assert_eq!(inverse.table()[usize::from(next)], block);
+            }
+            assert_eq!(inverse.inverse(), Some(rule));
+        }
+        // every block with a live cell becomes full
+        let mut table = [15; 16];
+        table[0] = 0;
+        let fill = MargolusRule::new(table).unwrap();
+        assert!(!fill.is_reversible());
+        assert_eq!(fill.inverse(), None);
+    }
+
+    #[test]
+    fn invalid() {
+        let column = |s: &str| s.parse::<MargolusRule>().unwrap_err().column();
+        assert_eq!(column("0,8,4,3,2,5,9,7,1,6,10,11,12,13,14,15"), 0);
+        assert_eq!(column("M0,8,4,3,2,5,9,7,1,6,10,11,12,13,14"), 35);
+        assert_eq!(column("M0,8,4,3,2,5,9,7,1,6,10,11,12,13,14,15,0"), 39);
+        assert_eq!(column("M0,8,4,3,2,5,9,7,1,6,10,11,12,13,14,16"), 36);
+        assert_eq!(column("M0,8,x,3,2,5,9,7,1,6,10,11,12,13,14,15"), 5);
+        assert_eq!(column("M1,8,4,3,2,5,9,7,1,6,10,11,12,13,14,15"), 1);
+        assert_eq!(column("M15,8,4,3,2,5,9,7,1,6,10,11,12,13,14,15"), 1);
+        assert!(MargolusRule::new([16; 16]).is_none());
+    }
+}
@@ -0,0 +1,1093 @@
+use std::error::Error;
+use std::fmt::{Debug, Display};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use super::hensel::{self, NeighbourhoodTable};
+use super::ltl::{Ltl, LtlNeighbourhood};
+use super::margolus::MargolusRule;
+use super::rule_file::RuleTable;
+use super::topology::{BoundedGrid, Topology, TwistedEdges};
+
+/// A two-state rule such as `B3/S23`, or an isotropic non-totalistic rule such as `B2-a/S12`
+///
+/// Totalistic rules can end with `V` for the von Neumann neighbourhood such as `B1/S1V`,
+/// or `H` for a hexagonal neighbourhood such as `B2/S34H`
+///
+/// Rules with more than 2 states are Generations rules such as `B2/S/C3`,
+/// where a cell that doesn't survive goes through each dying state before it is dead
+///
+/// Larger than Life rules such as `R5,C0,M1,S34..58,B34..45,NM` count live cells over a larger range
+///
+/// rules loaded from a Golly `.rule` file with `Rule::read_from_bytes` are written as their name
+///
+/// two-state rules with B0 such as `B0123478/S34678` are supported, see `Node::step` for how they are stepped
+///
+/// any rule can end with a bounded grid such as `B3/S23:T100,80`, see `Node::step_bounded`
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Rule {
+    transition: Transition,
+    states: u16,
+    grid: Option<BoundedGrid>,
+}
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Transition {
+    /// bit `n` of `birth` (or `survival`) is set if a cell with `n` live neighbours is born (or survives)
+    Totalistic {
+        birth: u16,
+        survival: u16,
+        neighbourhood: Neighbourhood,
+    },
+    /// any isotropic rule, see `hensel` for the layout of the table
+    Isotropic(Box<NeighbourhoodTable>),
+    LargerThanLife(Ltl),
+    /// a `@TABLE` or `@TREE` from a `.rule` file
+    Table(Rc<RuleTable>),
+    /// 2x2 blocks replaced by `rule.phases(backwards)[odd]`, with their north west cell at odd coordinates when `odd`
+    Margolus {
+        rule: MargolusRule,
+        backwards: bool,
+        odd: bool,
+    },
+}
+/// which cells of the 3x3 neighbourhood are counted by a totalistic rule
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum Neighbourhood {
+    Moore,
+    /// only the orthogonal neighbours
+    VonNeumann,
+    /// a hexagonal grid emulated on a square grid, without the north east and south west neighbours
+    Hexagonal,
+}
+impl Neighbourhood {
+    /// the neighbours in a 3x3 neighbourhood, see `hensel` for the layout
+    pub(crate) fn mask(self) -> usize {
+        match self {
+            Neighbourhood::Moore => 0b111_101_111,
+            Neighbourhood::VonNeumann => 0b010_101_010,
+            Neighbourhood::Hexagonal => 0b110_101_011,
+        }
+    }
+    fn max_count(self) -> u8 {
+        match self {
+            Neighbourhood::Moore => 8,
+            Neighbourhood::VonNeumann => 4,
+            Neighbourhood::Hexagonal => 6,
+        }
+    }
+    fn suffix(self) -> &'static str {
+        match self {
+            Neighbourhood::Moore => "",
+            Neighbourhood::VonNeumann => "V",
+            Neighbourhood::Hexagonal => "H",
+        }
+    }
+}
+impl Rule {
+    /// Conway's Game of Life, `B3/S23`
+    pub const LIFE: Rule = Rule {
+        transition: Transition::Totalistic {
+            birth: 1 << 3,
+            survival: 1 << 2 | 1 << 3,
+            neighbourhood: Neighbourhood::Moore,
+        },
+        states: 2,
+        grid: None,
+    };
+    const MAX_NEIGHBOURS: u8 = 8;
+    const MAX_STATES: u16 = 256;
+
+    pub(crate) fn transition(&self) -> &Transition {
+        &self.transition
+    }
+    /// number of states a cell can be in, 2 unless this is a Generations rule
+    pub fn states(&self) -> u16 {
+        self.states
+    }
+    /// number of bit-planes needed to store every state
+    pub fn planes(&self) -> usize {
+        (self.states - 1).ilog2() as usize + 1
+    }
+
+    /// the grid that patterns are bounded by, if any
+    pub fn grid(&self) -> Option<&BoundedGrid> {
+        self.grid.as_ref()
+    }
+    /// the same rule on a different grid, or on an unbounded plane for `None`
+    #[must_use]
+    pub fn with_grid(mut self, grid: Option<BoundedGrid>) -> Rule {
+        self.grid = grid;
+        self
+    }
+
+    /// how far away a cell can be and still effect another cell in 1 generation
+    pub fn range(&self) -> u16 {
+        match &self.transition {
+            Transition::LargerThanLife(ltl) => ltl.range,
+            _ => 1,
+        }
+    }
+    /// `range` rounded up to a power of 2
+    pub(crate) fn range_log2(&self) -> u8 {
+        #[allow(clippy::cast_possible_truncation)] // at most 16
+        let range_log2 = self.range().next_power_of_two().ilog2() as u8;
+        range_log2
+    }
+
+    /// the next state of a cell given its current state and which cells in its 3x3 neighbourhood are alive (state 1)
+    /// see `hensel` for the layout of `neighbourhood`
+    pub(crate) fn next_state(&self, state: u8, neighbourhood: usize) -> u8 {
+        let alive = match &self.transition {
+            Transition::Totalistic {
+                birth,
+                survival,
+                neighbourhood: counted,
+            } => {
+                let counts = if state == 1 { survival } else { birth };
+                counts & (1 << (neighbourhood & counted.mask()).count_ones()) != 0
+            }
+            Transition::Isotropic(table) => {
+                table[neighbourhood / 64] & (1 << (neighbourhood % 64)) != 0
+            }
+            Transition::LargerThanLife(ltl) => {
+                let count = if ltl.middle {
+                    neighbourhood
+                } else {
+                    neighbourhood & !0x10
+                };
+                ltl.is_alive(state == 1, count.count_ones())
+            }
+            Transition::Table(table) => {
+                let mut cells: [u8; 9] =
+                    std::array::from_fn(|i| u8::from(neighbourhood & (1 << (8 - i)) != 0));
+                cells[4] = state;
+                return table.next_state(&cells);
+            }
+            Transition::Margolus { .. } => unreachable!("stepped in blocks"),
+        };
+        self.decay(state, alive)
+    }
+    /// the next state of a cell in a Larger than Life rule given the count of live cells in range
+    pub(crate) fn next_state_from_count(&self, ltl: &Ltl, state: u8, count: u32) -> u8 {
+        self.decay(state, ltl.is_alive(state == 1, count))
+    }
+    fn decay(&self, state: u8, alive: bool) -> u8 {
+        match state {
+            0 | 1 if alive => 1,
+            0 => 0,
+            // dying cells always move to the next state and eventually die
+            _ if u16::from(state) + 1 >= self.states => 0,
+            _ => state + 1,
+        }
+    }
+
+    /// if patterns can be inverted, which is needed to emulate B0 and for a live background
+    pub(crate) fn can_invert(&self) -> bool {
+        self.states == 2
+            && !matches!(
+                self.transition,
+                Transition::LargerThanLife(_) | Transition::Table(_) | Transition::Margolus { .. }
+            )
+    }
+    /// if the background of every cell outside a pattern will be alive next generation
+    pub(crate) fn next_background(&self, alive: bool) -> bool {
+        if alive {
+            self.next_state(1, 0x1ff) == 1
+        } else {
+            self.next_state(0, 0) == 1
+        }
+    }
+    /// the equivalent rule for a pattern that is inverted when its background is `alive`,
+    /// and inverted next generation when that background will be alive, so empty space always stays empty
+    ///
+    /// this is how B0 rules are emulated, alternating between the rules for each background
+    pub(crate) fn with_background(&self, alive: bool) -> Rule {
+        let next_alive = self.next_background(alive);
+        if !alive && !next_alive {
+            return self.clone();
+        }
+        let transition = match &self.transition {
+            Transition::Totalistic {
+                birth,
+                survival,
+                neighbourhood,
+            } => {
+                let max = neighbourhood.max_count();
+                let flip = if next_alive { (1 << (max + 1)) - 1 } else { 0 };
+                // inverting swaps dead and live cells, and reverses the counts of live neighbours
+                let reverse = |counts: u16| counts.reverse_bits() >> (15 - max);
+                let (birth, survival) = if alive {
+                    (reverse(*survival), reverse(*birth))
+                } else {
+                    (*birth, *survival)
+                };
+                Transition::Totalistic {
+                    birth: birth ^ flip,
+                    survival: survival ^ flip,
+                    neighbourhood: *neighbourhood,
+                }
+            }
+            Transition::Isotropic(table) => {
+                let mut inverted = [0; 8];
+                for index in 0..512 {
+                    let from = if alive { !index & 0x1ff } else { index };
+                    let is_alive = (table[from / 64] >> (from % 64)) & 1 == 1;
+                    if is_alive != next_alive {
+                        inverted[index / 64] |= 1 << (index % 64);
+                    }
+                }
+                Transition::Isotropic(Box::new(inverted))
+            }
+            Transition::LargerThanLife(_) | Transition::Table(_) | Transition::Margolus { .. } => {
+                unreachable!("only totalistic and isotropic rules can have a live background")
+            }
+        };
+        Rule {
+            transition,
+            states: self.states,
+            grid: self.grid,
+        }
+    }
+
+    /// the same rule, but forced to use a lookup table, only valid for the Moore neighbourhood
+    #[cfg(test)]
+    pub(crate) fn to_isotropic(&self) -> Rule {
+        let (birth, survival) = self.letters();
+        Rule {
+            transition: Transition::Isotropic(Box::new(hensel::table_from_letters(
+                &birth, &survival,
+            ))),
+            states: self.states,
+            grid: self.grid,
+        }
+    }
+    /// bit masks of letters present for each neighbour count
+    fn letters(&self) -> ([u16; 9], [u16; 9]) {
+        match &self.transition {
+            Transition::Totalistic {
+                birth, survival, ..
+            } => {
+                let expand = |counts: u16| {
+                    std::array::from_fn(|count| {
+                        #[allow(clippy::cast_possible_truncation)] // at most 8
+                        let count = count as u8;
+                        if counts & (1 << count) == 0 {
+                            0
+                        } else {
+                            hensel::all_letters(count)
+                        }
+                    })
+                };
+                (expand(*birth), expand(*survival))
+            }
+            Transition::Isotropic(table) => hensel::letters_from_table(table),
+            Transition::LargerThanLife(_) | Transition::Table(_) | Transition::Margolus { .. } => {
+                unreachable!("only totalistic and isotropic rules are written with letters")
+            }
+        }
+    }
+    /// letters must be totalistic for neighbourhoods other than Moore
+    fn from_letters(
+        birth: &[u16; 9],
+        survival: &[u16; 9],
+        states: u16,
+        neighbourhood: Neighbourhood,
+    ) -> Rule {
+        let totalistic = |letters: &[u16; 9]| {
+            (0..=Rule::MAX_NEIGHBOURS)
+                .zip(letters)
+                .try_fold(0, |counts, (count, &letters)| {
+                    if letters == 0 {
+                        Some(counts)
+                    } else if letters == hensel::all_letters(count) {
+                        Some(counts | 1 << count)
+                    } else {
+                        None
+                    }
+                })
+        };
+        let transition = match (totalistic(birth), totalistic(survival)) {
+            (Some(birth), Some(survival)) => Transition::Totalistic {
+                birth,
+                survival,
+                neighbourhood,
+            },
+            _ if neighbourhood == Neighbourhood::Moore => {
+                Transition::Isotropic(Box::new(hensel::table_from_letters(birth, survival)))
+            }
+            _ => unreachable!("letters are only parsed for the Moore neighbourhood"),
+        };
+        Rule {
+            transition,
+            states,
+            grid: None,
+        }
+    }
+    pub(crate) fn from_table(table: RuleTable, states: u16) -> Rule {
+        Rule {
+            transition: Transition::Table(Rc::new(table)),
+            states,
+            grid: None,
+        }
+    }
+    /// a block rule for one phase, which is only used to step nodes, see `Node::step_margolus`
+    pub(crate) fn margolus(rule: MargolusRule, backwards: bool, odd: bool) -> Rule {
+        Rule {
+            transition: Transition::Margolus {
+                rule,
+                backwards,
+                odd,
+            },
+            states: 2,
+            grid: None,
+        }
+    }
+}
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::LIFE
+    }
+}
+
+// formatting
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.transition {
+            Transition::LargerThanLife(ltl) => ltl.write(f, self.states)?,
+            Transition::Table(table) => f.write_str(table.name())?,
+            // the rule that is stepped, without the phase
+            Transition::Margolus {
+                rule, backwards, ..
+            } => {
+                let rule = if *backwards {
+                    rule.inverse()
+                        .expect("only reversible rules can be stepped backwards")
+                } else {
+                    *rule
+                };
+                write!(f, "{rule}")?;
+            }
+            _ => {
+                let (birth, survival) = self.letters();
+                write!(f, "B")?;
+                hensel::write_letters(f, &birth)?;
+                write!(f, "/S")?;
+                hensel::write_letters(f, &survival)?;
+                if self.states > 2 {
+                    write!(f, "/C{}", self.states)?;
+                }
+                if let Transition::Totalistic { neighbourhood, .. } = &self.transition {
+                    f.write_str(neighbourhood.suffix())?;
+                }
+            }
+        }
+        if let Some(grid) = &self.grid {
+            write!(f, "{grid}")?;
+        }
+        Ok(())
+    }
+}
+impl Debug for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rule({self})")
+    }
+}
+
+// parsing
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RuleErrorHint {
+    Empty,
+    InvalidChar,
+    InvalidCount,
+    InvalidLetter,
+    MissingLetter,
+    DuplicateSection,
+    MissingSlash,
+    MissingSection,
+    TooManySlashes,
+    InvalidStates,
+    InvalidNeighbourhoodCount,
+    NeighbourhoodLetters,
+    InvalidLtl,
+    InvalidRange,
+    InvalidBounds,
+    UnsupportedB0,
+    InvalidTopology,
+    InvalidGridSize,
+    InvalidTwist,
+    UnsupportedShift,
+    InvalidMargolus,
+    MargolusBackground,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct RuleError {
+    column: usize,
+    hint: RuleErrorHint,
+}
+impl RuleError {
+    pub(crate) fn new(column: usize, hint: RuleErrorHint) -> Self {
+        Self { column, hint }
+    }
+    /// 0 based column index
+    pub fn column(&self) -> usize {
+        self.column
+    }
+    pub fn hint(&self) -> &'static str {
+        match self.hint {
+            RuleErrorHint::Empty => "Rule is empty, try B3/S23",
+            RuleErrorHint::InvalidChar => "Invalid character",
+            RuleErrorHint::InvalidCount => "Neighbour counts must be between 0 and 8",
+            RuleErrorHint::InvalidLetter => {
+                "Not a valid Hensel letter for this neighbour count, 0 and 8 can't have letters"
+            }
+            RuleErrorHint::MissingLetter => "Expected letters after a '-'",
+            RuleErrorHint::DuplicateSection => {
+                "Birth, survival, and states can each only be given once"
+            }
+            RuleErrorHint::MissingSlash => "Birth and survival must be separated by a '/'",
+            RuleErrorHint::MissingSection => "Rule needs both birth and survival",
+            RuleErrorHint::TooManySlashes => "Rule has too many '/'s",
+            RuleErrorHint::InvalidStates => "Number of states must be between 2 and 256",
+            RuleErrorHint::InvalidNeighbourhoodCount => {
+                "Neighbour counts must be at most 4 for V rules, and at most 6 for H rules"
+            }
+            RuleErrorHint::NeighbourhoodLetters => "Hensel letters can't be used with V or H rules",
+            RuleErrorHint::InvalidLtl => {
+                "Larger than Life rules are written like R5,C0,M1,S34..58,B34..45,NM"
+            }
+            RuleErrorHint::InvalidRange => "Range must be between 1 and 500",
+            RuleErrorHint::InvalidBounds => "Lower bound can't be larger than the upper bound",
+            RuleErrorHint::UnsupportedB0 => {
+                "B0 is only supported for two-state rules without a range"
+            }
+            RuleErrorHint::InvalidTopology => {
+                "Bounded grids are written like :T100,80, with P for a plane, T for a torus, or K for a Klein bottle"
+            }
+            RuleErrorHint::InvalidGridSize => {
+                "Bounded grid sides must be between 1 and 2^30, unbounded sides aren't supported"
+            }
+            RuleErrorHint::InvalidTwist => {
+                "Klein bottles need a '*' after either the width or height to twist those edges"
+            }
+            RuleErrorHint::UnsupportedShift => "Bounded grids with shifted edges aren't supported",
+            RuleErrorHint::InvalidMargolus => {
+                "Block rules are written like M0,8,4,3,2,5,9,7,1,6,10,11,12,13,14,15, with 16 blocks from 0 to 15"
+            }
+            RuleErrorHint::MargolusBackground => {
+                "Empty blocks must stay empty, or become full with full blocks becoming empty"
+            }
+        }
+    }
+}
+impl Debug for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RuleError")
+            .field("column", &self.column)
+            .field("hint", &self.hint)
+            .finish()
+    }
+}
+impl Display for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let column = self.column + 1;
+        let hint = self.hint();
+        write!(f, "Failed to parse rule at column {column}: {hint}")
+    }
+}
+impl Error for RuleError {}
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    /// accepts `B3/S23` (in either order and any case) or the older `23/3` survival/birth notation
+    /// counts can be followed by Hensel letters, optionally negated by a `-`, as in `B2-a/S12`
+    /// Generations rules add a number of states, as in `B2/S/C3` or `/2/3`
+    /// and totalistic rules can end in `V` or `H` for other neighbourhoods, as in `B2/S34H`
+    /// or Larger than Life rules like `R5,C0,M1,S34..58,B34..45,NM`
+    /// any of which can be followed by a bounded grid, as in `B3/S23:T100,80`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None => RuleReader::new(s).read(),
+            Some((rule_src, _)) => {
+                let rule = RuleReader::new(rule_src).read()?;
+                let grid = RuleReader {
+                    src: s.as_bytes(),
+                    at: rule_src.len() + 1,
+                }
+                .read_grid()?;
+                Ok(rule.with_grid(Some(grid)))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Section {
+    Birth([u16; 9]),
+    Survival([u16; 9]),
+    /// birth or survival depending on the position
+    Counts([u16; 9]),
+    States(u16),
+}
+
+struct RuleReader<'src> {
+    src: &'src [u8],
+    at: usize,
+}
+impl<'src> RuleReader<'src> {
+    fn new(src: &'src str) -> Self {
+        Self {
+            src: src.as_bytes(),
+            at: 0,
+        }
+    }
+
+    fn read(mut self) -> Result<Rule, RuleError> {
+        self.consume_spaces();
+        if self.peak().is_none() {
+            return self.fail(RuleErrorHint::Empty);
+        }
+        if let (Some(b'r' | b'R'), Some(b'0'..=b'9')) = (self.peak(), self.src.get(self.at + 1)) {
+            return self.read_ltl();
+        }
+        let mut birth = None;
+        let mut survival = None;
+        let mut states = None;
+        // unprefixed sections are survival, birth, then states
+        let mut unprefixed = false;
+        // position and neighbourhood of a `V` or `H` at the end of the rule
+        let mut suffix = None;
+        for index in 0.. {
+            let at = self.at;
+            let section = self.read_section(index)?;
+            let duplicate = match section {
+                Section::States(count) => states.replace(count).is_some(),
+                Section::Birth(letters) if !unprefixed => birth.replace(letters).is_some(),
+                Section::Survival(letters) if !unprefixed => survival.replace(letters).is_some(),
+                Section::Counts(letters) if index == 0 => {
+                    unprefixed = true;
+                    survival.replace(letters).is_some()
+                }
+                Section::Counts(letters) if index == 1 && unprefixed => {
+                    birth.replace(letters).is_some()
+                }
+                _ => true,
+            };
+            if duplicate {
+                return Err(RuleError::new(at, RuleErrorHint::DuplicateSection));
+            }
+            match self.peak() {
+                Some(b'/') if index == 2 => return self.fail(RuleErrorHint::TooManySlashes),
+                Some(b'/') => {
+                    let slash = self.at;
+                    self.consume();
+                    self.consume_spaces();
+                    // a trailing '/' is more likely to be a typo than a missing number of states
+                    if index == 1 && self.peak().is_none() {
+                        return Err(RuleError::new(slash, RuleErrorHint::TooManySlashes));
+                    }
+                }
+                None if index == 0 => return self.fail(RuleErrorHint::MissingSlash),
+                None => break,
+                Some(_) if index > 0 => match self.read_suffix() {
+                    Some(found) => {
+                        suffix = Some(found);
+                        break;
+                    }
+                    None => return self.fail(RuleErrorHint::InvalidChar),
+                },
+                Some(_) => return self.fail(RuleErrorHint::InvalidChar),
+            }
+        }
+        let (Some(birth), Some(survival)) = (birth, survival) else {
+            return self.fail(RuleErrorHint::MissingSection);
+        };
+        let states = states.unwrap_or(2);
+        // B0 is emulated by inverting two-state patterns, which doesn't work with dying states
+        if birth[0] != 0 && states > 2 {
+            return Err(RuleError::new(0, RuleErrorHint::UnsupportedB0));
+        }
+        let neighbourhood = match suffix {
+            None => Neighbourhood::Moore,
+            Some((at, neighbourhood)) => {
+                for (count, &letters) in (0..).zip(birth.iter().chain(&survival)) {
+                    let count = count % 9;
+                    if letters != 0 && count > neighbourhood.max_count() {
+                        return Err(RuleError::new(at, RuleErrorHint::InvalidNeighbourhoodCount));
+                    }
+                    if letters != 0 && letters != hensel::all_letters(count) {
+                        return Err(RuleError::new(at, RuleErrorHint::NeighbourhoodLetters));
+                    }
+                }
+                neighbourhood
+            }
+        };
+        Ok(Rule::from_letters(&birth, &survival, states, neighbourhood))
+    }
+    fn read_ltl(mut self) -> Result<Rule, RuleError> {
+        self.expect(b'r')?;
+        let range_at = self.at;
+        let range = self.read_number()?;
+        if range < 1 || range > u32::from(Ltl::MAX_RANGE) {
+            return Err(RuleError::new(range_at, RuleErrorHint::InvalidRange));
+        }
+        self.expect(b',')?;
+        self.expect(b'c')?;
+        let states_at = self.at;
+        let states = match self.read_number()? {
+            0 => 2,
+            states if states >= 2 && states <= Rule::MAX_STATES.into() => states,
+            _ => return Err(RuleError::new(states_at, RuleErrorHint::InvalidStates)),
+        };
+        self.expect(b',')?;
+        self.expect(b'm')?;
+        let middle = match self.peak() {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return self.fail(RuleErrorHint::InvalidLtl),
+        };
+        self.consume();
+        self.expect(b',')?;
+        self.expect(b's')?;
+        let survival = self.read_bounds()?;
+        self.expect(b',')?;
+        let birth_at = self.at;
+        self.expect(b'b')?;
+        let birth = self.read_bounds()?;
+        self.expect(b',')?;
+        self.expect(b'n')?;
+        let neighbourhood = match self.peak().map(|b| b.to_ascii_lowercase()) {
+            Some(b'm') => LtlNeighbourhood::Moore,
+            Some(b'n') => LtlNeighbourhood::VonNeumann,
+            _ => return self.fail(RuleErrorHint::InvalidLtl),
+        };
+        self.consume();
+        self.consume_spaces();
+        if self.peak().is_some() {
+            return self.fail(RuleErrorHint::InvalidChar);
+        }
+        if birth.0 == 0 {
+            return Err(RuleError::new(birth_at, RuleErrorHint::UnsupportedB0));
+        }
+        #[allow(clippy::cast_possible_truncation)] // both checked
+        Ok(Rule {
+            transition: Transition::LargerThanLife(Ltl {
+                range: range as u16,
+                middle,
+                survival,
+                birth,
+                neighbourhood,
+            }),
+            states: states as u16,
+            grid: None,
+        })
+    }
+    /// reads a bounded grid like `T100,80` after the `:`
+    fn read_grid(mut self) -> Result<BoundedGrid, RuleError> {
+        self.consume_spaces();
+        let at = self.at;
+        let topology = match self.peak().map(|b| b.to_ascii_lowercase()) {
+            Some(b'p') => Topology::Plane,
+            Some(b't') => Topology::Torus,
+            Some(b'k') => Topology::KleinBottle(TwistedEdges::Horizontal),
+            _ => return self.fail(RuleErrorHint::InvalidTopology),
+        };
+        self.consume();
+        let width = self.read_grid_side()?;
+        let width_twist = self.read_twist();
+        if self.peak() != Some(b',') {
+            return self.fail(RuleErrorHint::InvalidTopology);
+        }
+        self.consume();
+        let height = self.read_grid_side()?;
+        let height_twist = self.read_twist();
+        self.consume_spaces();
+        if self.peak().is_some() {
+            return self.fail(RuleErrorHint::InvalidChar);
+        }
+        let topology = match (topology, width_twist, height_twist) {
+            (Topology::KleinBottle(_), Some(_), None) => {
+                Topology::KleinBottle(TwistedEdges::Horizontal)
+            }
+            (Topology::KleinBottle(_), None, Some(_)) => {
+                Topology::KleinBottle(TwistedEdges::Vertical)
+            }
+            (Topology::KleinBottle(_), None, None) => {
+                return Err(RuleError::new(at, RuleErrorHint::InvalidTwist))
+            }
+            (_, Some(twist_at), _) | (_, _, Some(twist_at)) => {
+                return Err(RuleError::new(twist_at, RuleErrorHint::InvalidTwist))
+            }
+            (topology, None, None) => topology,
+        };
+        Ok(BoundedGrid::new(topology, width, height))
+    }
+    fn read_grid_side(&mut self) -> Result<u32, RuleError> {
+        let at = self.at;
+        if !matches!(self.peak(), Some(b'0'..=b'9')) {
+            return self.fail(RuleErrorHint::InvalidTopology);
+        }
+        let mut side = 0_u32;
+        while let Some(b @ b'0'..=b'9') = self.peak() {
+            self.consume();
+            side = side.saturating_mul(10).saturating_add((b - b'0').into());
+        }
+        if side < 1 || side > BoundedGrid::MAX_SIZE {
+            return Err(RuleError::new(at, RuleErrorHint::InvalidGridSize));
+        }
+        if let Some(b'+' | b'-') = self.peak() {
+            return self.fail(RuleErrorHint::UnsupportedShift);
+        }
+        Ok(side)
+    }
+    /// reads an optional `*` that twists the edges of a Klein bottle
+    fn read_twist(&mut self) -> Option<usize> {
+        let at = self.at;
+        (self.peak() == Some(b'*')).then(|| {
+            self.consume();
+            at
+        })
+    }
+    /// reads bounds like `34..58`
+    fn read_bounds(&mut self) -> Result<(u32, u32), RuleError> {
+        let at = self.at;
+        let min = self.read_number()?;
+        self.expect(b'.')?;
+        self.expect(b'.')?;
+        let max = self.read_number()?;
+        if min > max {
+            return Err(RuleError::new(at, RuleErrorHint::InvalidBounds));
+        }
+        Ok((min, max))
+    }
+    fn read_number(&mut self) -> Result<u32, RuleError> {
+        if !matches!(self.peak(), Some(b'0'..=b'9')) {
+            return self.fail(RuleErrorHint::InvalidLtl);
+        }
+        let mut value = 0_u32;
+        while let Some(b @ b'0'..=b'9') = self.peak() {
+            self.consume();
+            value = value.saturating_mul(10).saturating_add((b - b'0').into());
+        }
+        Ok(value)
+    }
+    /// consumes a specific character, ignoring case
+    fn expect(&mut self, expected: u8) -> Result<(), RuleError> {
+        if self.peak().map(|b| b.to_ascii_lowercase()) == Some(expected) {
+            self.consume();
+            Ok(())
+        } else {
+            self.fail(RuleErrorHint::InvalidLtl)
+        }
+    }
+
+    /// reads an optional `B`, `S`, or `C` prefix followed by either neighbour counts and their letters or a number of states
+    fn read_section(&mut self, index: usize) -> Result<Section, RuleError> {
+        self.consume_spaces();
+        let prefix = match self.peak().map(|b| b.to_ascii_lowercase()) {
+            Some(b @ (b'b' | b's' | b'c')) => {
+                self.consume();
+                Some(b)
+            }
+            _ => None,
+        };
+        let section = match prefix {
+            Some(b'c') => Section::States(self.read_states()?),
+            None if index == 2 => Section::States(self.read_states()?),
+            Some(b'b') => Section::Birth(self.read_counts()?),
+            Some(_) => Section::Survival(self.read_counts()?),
+            None => Section::Counts(self.read_counts()?),
+        };
+        self.consume_spaces();
+        Ok(section)
+    }
+    /// reads a `V` or `H` neighbourhood suffix, which must end the rule
+    fn read_suffix(&mut self) -> Option<(usize, Neighbourhood)> {
+        let at = self.at;
+        let neighbourhood = match self.peak().map(|b| b.to_ascii_lowercase()) {
+            Some(b'v') => Neighbourhood::VonNeumann,
+            Some(b'h') => Neighbourhood::Hexagonal,
+            _ => return None,
+        };
+        self.consume();
+        self.consume_spaces();
+        if self.peak().is_some() {
+            self.at = at;
+            return None;
+        }
+        Some((at, neighbourhood))
+    }
+    fn read_counts(&mut self) -> Result<[u16; 9], RuleError> {
+        let mut counts = [0_u16; 9];
+        while let Some(b @ b'0'..=b'9') = self.peak() {
+            let count = b - b'0';
+            if count > Rule::MAX_NEIGHBOURS {
+                return self.fail(RuleErrorHint::InvalidCount);
+            }
+            self.consume();
+            counts[usize::from(count)] |= self.read_letters(count)?;
+        }
+        Ok(counts)
+    }
+    fn read_states(&mut self) -> Result<u16, RuleError> {
+        let at = self.at;
+        let mut states = 0_u16;
+        while let Some(b @ b'0'..=b'9') = self.peak() {
+            self.consume();
+            states = states.saturating_mul(10).saturating_add((b - b'0').into());
+        }
+        if states < 2 || states > Rule::MAX_STATES {
+            return Err(RuleError::new(at, RuleErrorHint::InvalidStates));
+        }
+        Ok(states)
+    }
+    /// reads letters after a neighbour count, all letters are implied if there are none
+    fn read_letters(&mut self, count: u8) -> Result<u16, RuleError> {
+        let negate = self.peak() == Some(b'-');
+        if negate {
+            self.consume();
+        }
+        let mut letters = 0;
+        // `v` and `h` are never letters, so they are left as neighbourhood suffixes
+        while let Some(b @ b'a'..=b'z') = self.peak().filter(|b| !matches!(b, b'v' | b'h')) {
+            match hensel::letters(count)
+                .iter()
+                .position(|&letter| letter == b)
+            {
+                Some(i) => letters |= 1 << i,
+                None => return self.fail(RuleErrorHint::InvalidLetter),
+            }
+            self.consume();
+        }
+        let all = hensel::all_letters(count);
+        match (negate, letters) {
+            (true, 0) => self.fail(RuleErrorHint::MissingLetter),
+            (true, letters) => Ok(all & !letters),
+            (false, 0) => Ok(all),
+            (false, letters) => Ok(letters),
+        }
+    }
+
+    fn consume_spaces(&mut self) {
+        while let Some(b' ' | b'\t') = self.peak() {
+            self.consume();
+        }
+    }
+    fn peak(&self) -> Option<u8> {
+        self.src.get(self.at).copied()
+    }
+    fn consume(&mut self) {
+        self.at += 1;
+    }
+
+    fn fail<T>(&self, hint: RuleErrorHint) -> Result<T, RuleError> {
+        Err(RuleError::new(self.at, hint))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Rule, Topology, TwistedEdges};
+    use crate::{MargolusRule, Pos, Rect};
+
+    #[test]
+    fn life() {
+        assert_eq!("B3/S23".parse::<Rule>(), Ok(Rule::LIFE));
+        assert_eq!("b3/s23".parse::<Rule>(), Ok(Rule::LIFE));
+        assert_eq!("S23/B3".parse::<Rule>(), Ok(Rule::LIFE));
+        assert_eq!("23/3".parse::<Rule>(), Ok(Rule::LIFE));
+        assert_eq!(Rule::LIFE.to_string(), "B3/S23");
+    }
+
+    #[test]
+    fn round_trip() {
+        for rule in [
+            "B36/S23",
+            "B3678/S34678",
+            "B2/S",
+            "B/S012345678",
+            "B0123478/S34678",
+        ] {
+            assert_eq!(rule.parse::<Rule>().unwrap().to_string(), rule);
+        }
+    }
+
+    #[test]
+    fn bounded_grids() {
+        for rule in [
+            "B3/S23:P30,20",
+            "B3/S23:T100,80",
+            "B3/S23:K100*,80",
+            "B2/S34H:K5,7*",
+            "B0123478/S34678:T64,64",
+            "B2/S/C3:T10,10",
+            "R5,C0,M1,S34..58,B34..45,NM:T200,200",
+        ] {
+            assert_eq!(rule.parse::<Rule>().unwrap().to_string(), rule);
+        }
+        let rule = "b3/s23 : t20,10".parse::<Rule>().unwrap();
+        assert_eq!(rule.to_string(), "B3/S23:T20,10");
+        let grid = rule.grid().unwrap();
+        assert_eq!(grid.topology(), Topology::Torus);
+        assert_eq!(
+            grid.rect(),
+            Rect::min_max(Pos::new(-10, -5), Pos::new(9, 4))
+        );
+        // odd sides have the extra cell past the origin
+        let grid = "B3/S23:K5*,3".parse::<Rule>().unwrap().grid().copied();
+        assert_eq!(
+            grid.map(|grid| (grid.topology(), grid.rect())),
+            Some((
+                Topology::KleinBottle(TwistedEdges::Horizontal),
+                Rect::min_max(Pos::new(-2, -1), Pos::new(2, 1))
+            ))
+        );
+        assert_eq!(rule.with_grid(None), Rule::LIFE);
+    }
+
+    #[test]
+    fn hensel() {
+        for rule in ["B2-a/S12", "B3-cnyq/S234c", "B3/S2-i34q", "B2ce3a/S1e2-ka"] {
+            assert_eq!(rule.parse::<Rule>().unwrap().to_string(), rule);
+        }
+        // letters are written in a canonical order
+        let rule = "B3-cnqy/S234c".parse::<Rule>().unwrap();
+        assert_eq!(rule.to_string(), "B3-cnyq/S234c");
+        // all letters is the same as no letters
+        let life = "B3cekainyqjr/S2cekain3-".parse::<Rule>();
+        assert!(life.is_err());
+        let life = "B3cekainyqjr/S2cekain3".parse::<Rule>();
+        assert_eq!(life, Ok(Rule::LIFE));
+        // written with the shorter form
+        let rule = "B2cekai/S".parse::<Rule>().unwrap();
+        assert_eq!(rule.to_string(), "B2-n/S");
+        assert_eq!(Rule::LIFE.to_isotropic().to_string(), "B3/S23");
+    }
+
+    #[test]
+    fn generations() {
+        let brians_brain = "/2/3".parse::<Rule>().unwrap();
+        assert_eq!(brians_brain.to_string(), "B2/S/C3");
+        assert_eq!(brians_brain.states(), 3);
+        assert_eq!(brians_brain.planes(), 2);
+        let star_wars = "345/2/4".parse::<Rule>().unwrap();
+        assert_eq!(star_wars.to_string(), "B2/S345/C4");
+        assert_eq!("C4/S345/B2".parse::<Rule>(), Ok(star_wars));
+        for rule in ["B2/S/C3", "B2a/S34/C5", "B3/S23/C256"] {
+            assert_eq!(rule.parse::<Rule>().unwrap().to_string(), rule);
+        }
+        assert_eq!("B3/S23/C2".parse::<Rule>(), Ok(Rule::LIFE));
+        assert_eq!(Rule::LIFE.states(), 2);
+        assert_eq!(Rule::LIFE.planes(), 1);
+        assert_eq!("B3/S23/C256".parse::<Rule>().unwrap().planes(), 8);
+    }
+
+    #[test]
+    fn next_state() {
+        let star_wars = "345/2/4".parse::<Rule>().unwrap();
+        // 2 live neighbours (n and s) without the center cell
+        let two = 0b010_000_010;
+        // 3 live neighbours (n, s, and e) with the center cell
+        let three = 0b010_011_010;
+        assert_eq!(star_wars.next_state(0, two), 1);
+        assert_eq!(star_wars.next_state(1, two | 0x10), 2);
+        assert_eq!(star_wars.next_state(1, three), 1);
+        assert_eq!(star_wars.next_state(2, three), 3);
+        assert_eq!(star_wars.next_state(3, three), 0);
+        assert_eq!(Rule::LIFE.next_state(1, two | 0x10), 1);
+        assert_eq!(Rule::LIFE.next_state(1, 0x10), 0);
+    }
+
+    #[test]
+    fn neighbourhoods() {
+        for rule in ["B2/S34H", "B1/S1V", "B2/S/C3H", "B/S01234V"] {
+            assert_eq!(rule.parse::<Rule>().unwrap().to_string(), rule);
+        }
+        assert_eq!("b2/s34h".parse::<Rule>(), "B2/S34H".parse::<Rule>());
+        assert_eq!("34/2H".parse::<Rule>(), "B2/S34H".parse::<Rule>());
+        assert_ne!("B3/S23V".parse::<Rule>(), "B3/S23".parse::<Rule>());
+
+        let hex = "B2/S34H".parse::<Rule>().unwrap();
+        // north and north east alive, but north east isn't a neighbour
+        assert_eq!(hex.next_state(0, 0b011_000_000), 0);
+        // north and north west alive
+        assert_eq!(hex.next_state(0, 0b110_000_000), 1);
+        let von_neumann = "B2/S1V".parse::<Rule>().unwrap();
+        assert_eq!(von_neumann.next_state(0, 0b101_000_101), 0);
+        assert_eq!(von_neumann.next_state(0, 0b010_001_000), 1);
+        assert_eq!(von_neumann.next_state(1, 0b000_111_000), 0);
+    }
+
+    #[test]
+    fn margolus() {
+        // the phases of a block rule are written as the rule they step
+        for margolus in [MargolusRule::BILLIARD_BALL_MACHINE, MargolusRule::CRITTERS] {
+            for odd in [false, true] {
+                let rule = Rule::margolus(margolus, false, odd);
+                assert_eq!(rule.to_string().parse(), Ok(margolus));
+                let back = Rule::margolus(margolus, true, odd);
+                assert_eq!(back.to_string().parse().ok(), margolus.inverse());
+            }
+        }
+    }
+
+    #[test]
+    fn larger_than_life() {
+        for rule in ["R5,C0,M1,S34..58,B34..45,NM", "R2,C3,M0,S1..2,B3..3,NN"] {
+            assert_eq!(rule.parse::<Rule>().unwrap().to_string(), rule);
+        }
+        let bosco = "r5,c0,m1,s34..58,b34..45,nm".parse::<Rule>().unwrap();
+        assert_eq!(bosco.to_string(), "R5,C0,M1,S34..58,B34..45,NM");
+        assert_eq!(bosco.range(), 5);
+        assert_eq!(bosco.range_log2(), 3);
+        assert_eq!(bosco.states(), 2);
+        assert_eq!(Rule::LIFE.range(), 1);
+        assert_eq!(Rule::LIFE.range_log2(), 0);
+    }
+
+    #[test]
+    fn invalid() {
+        let column = |s: &str| s.parse::<Rule>().unwrap_err().column();
+        assert_eq!(column(""), 0);
+        assert_eq!(column("B3"), 2);
+        assert_eq!(column("B3/S23/"), 6);
+        assert_eq!(column("B39/S23"), 2);
+        assert_eq!(column("B3/B23"), 3);
+        assert_eq!(column("B3/S2x"), 5);
+        assert_eq!(column("B03/S23/C3"), 0);
+        assert_eq!(column("B1k/S23"), 2);
+        assert_eq!(column("B3/S08e"), 6);
+        assert_eq!(column("B3/S2-"), 6);
+        assert_eq!(column("B3/S23/C1"), 8);
+        assert_eq!(column("B3/S23/C2/"), 9);
+        assert_eq!(column("B3/S23/C2/C3"), 9);
+        assert_eq!(column("B3/S23/C257"), 8);
+        assert_eq!(column("B3/C3"), 5);
+        assert_eq!(column("3/B3"), 2);
+        assert_eq!(column("B5/S1V"), 5);
+        assert_eq!(column("B2a/S34H"), 7);
+        assert_eq!(column("B2/S34HV"), 6);
+        assert_eq!(column("B2/S34H/C3"), 6);
+        assert_eq!(column("B2/S34X"), 6);
+        assert_eq!(column("R0,C0,M1,S1..2,B3..3,NM"), 1);
+        assert_eq!(column("R501,C0,M1,S1..2,B3..3,NM"), 1);
+        assert_eq!(column("R2,C1,M1,S1..2,B3..3,NM"), 4);
+        assert_eq!(column("R2,C0,M2,S1..2,B3..3,NM"), 7);
+        assert_eq!(column("R2,C0,M1,S3..2,B3..3,NM"), 10);
+        assert_eq!(column("R2,C0,M1,S1..2,B0..3,NM"), 15);
+        assert_eq!(column("R2,C0,M1,S1..2,B3..3,NX"), 22);
+        assert_eq!(column("R2,C0,M1,S1..2,B3..3"), 20);
+        assert_eq!(column("B3/S23:X10,10"), 7);
+        assert_eq!(column("B3/S23:T10"), 10);
+        assert_eq!(column("B3/S23:T0,10"), 8);
+        assert_eq!(column("B3/S23:T10+2,10"), 10);
+        assert_eq!(column("B3/S23:K10,10"), 7);
+        assert_eq!(column("B3/S23:K10*,10*"), 10);
+        assert_eq!(column("B3/S23:T10*,10"), 10);
+        assert_eq!(column("B3/S23:P10,10x"), 13);
+        assert_eq!(column("B3/S2x:T10,10"), 5);
+    }
+}
@@ -0,0 +1,1010 @@
+//! Golly `.rule` files, with the transitions in either a `@TABLE` or `@TREE` section
+//!
+//! other sections such as `@COLORS` and `@ICONS` are ignored
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+
+use crate::{LineInfo, Rule};
+
+/// the states of the 3x3 neighbourhood of a cell in reading order
+pub(crate) type Cells = [u8; 9];
+
+/// indices into `Cells` in the order a `@TABLE` lists them, the center then each neighbour clockwise from north
+const TABLE_MOORE: &[usize] = &[4, 1, 2, 5, 8, 7, 6, 3, 0];
+const TABLE_VON_NEUMANN: &[usize] = &[4, 1, 5, 7, 3];
+/// hexagonal grids are emulated without the north east and south west neighbours
+const TABLE_HEXAGONAL: &[usize] = &[4, 1, 5, 8, 7, 3, 0];
+/// indices into `Cells` in the order of the levels of a `@TREE`, starting at the root
+const TREE_MOORE: &[usize] = &[0, 2, 6, 8, 1, 3, 5, 7, 4];
+const TREE_VON_NEUMANN: &[usize] = &[1, 3, 5, 7, 4];
+
+/// the transitions of a rule loaded from a `.rule` file
+pub(crate) struct RuleTable {
+    name: String,
+    lookup: Lookup,
+    /// matching a table can be slow, so each neighbourhood is only looked up once
+    cache: RefCell<HashMap<Cells, u8>>,
+}
+impl RuleTable {
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+    /// the next state of the center cell
+    pub(crate) fn next_state(&self, cells: &Cells) -> u8 {
+        if let Some(&next) = self.cache.borrow().get(cells) {
+            return next;
+        }
+        let next = self.lookup.next_state(cells);
+        self.cache.borrow_mut().insert(*cells, next);
+        next
+    }
+}
+impl PartialEq for RuleTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.lookup == other.lookup
+    }
+}
+impl Eq for RuleTable {}
+impl Hash for RuleTable {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.lookup.hash(state);
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Lookup {
+    /// the first row that matches gives the next state, otherwise the cell doesn't change
+    Table {
+        /// one of the `TABLE_*` orders
+        order: &'static [usize],
+        symmetries: Symmetries,
+        rows: Vec<Row>,
+        variables: usize,
+    },
+    Tree {
+        /// one of the `TREE_*` orders
+        order: &'static [usize],
+        /// children of each node with the root last, level 1 nodes have next states as children
+        nodes: Vec<Vec<u32>>,
+    },
+}
+impl Lookup {
+    fn next_state(&self, cells: &Cells) -> u8 {
+        match self {
+            Lookup::Table {
+                order,
+                symmetries,
+                rows,
+                variables,
+            } => {
+                let ordered: Vec<u8> = order.iter().map(|&i| cells[i]).collect();
+                rows.iter()
+                    .find_map(|row| row.next_state(&ordered, symmetries, *variables))
+                    .unwrap_or(cells[4])
+            }
+            Lookup::Tree { order, nodes } => {
+                let mut node = nodes.len() - 1;
+                for &i in *order {
+                    // states past the number of states in the rule are left alone
+                    let Some(&child) = nodes[node].get(usize::from(cells[i])) else {
+                        return cells[4];
+                    };
+                    node = child as usize;
+                }
+                #[allow(clippy::cast_possible_truncation)] // level 1 children are states
+                let next = node as u8;
+                next
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Symmetries {
+    /// each permutation of the neighbours that a row also matches
+    Permutations(Vec<Vec<usize>>),
+    /// a row matches any order of the neighbours
+    Permute,
+}
+impl Symmetries {
+    /// symmetries named like `rotate4reflect`, for a number of neighbours
+    fn from_name(name: &[u8], neighbours: usize) -> Option<Symmetries> {
+        let (rotations, reflect) = match name {
+            b"permute" => return Some(Symmetries::Permute),
+            b"none" => (1, false),
+            b"reflect_horizontal" => (1, true),
+            _ => {
+                let count = name.strip_prefix(b"rotate")?;
+                let (count, reflect) = match count.strip_suffix(b"reflect") {
+                    Some(count) => (count, true),
+                    None => (count, false),
+                };
+                let count: usize = std::str::from_utf8(count).ok()?.parse().ok()?;
+                (count, reflect)
+            }
+        };
+        if neighbours.checked_rem(rotations) != Some(0) {
+            return None;
+        }
+        let rotate =
+            |by: usize| -> Vec<usize> { (0..neighbours).map(|i| (i + by) % neighbours).collect() };
+        let mut permutations: Vec<Vec<usize>> = (0..rotations)
+            .map(|i| rotate(i * neighbours / rotations))
+            .collect();
+        if reflect {
+            // neighbours are in a ring, so reflecting keeps north where it is
+            let reflected: Vec<Vec<usize>> = permutations
+                .iter()
+                .map(|permutation| {
+                    permutation
+                        .iter()
+                        .map(|&i| (neighbours - i) % neighbours)
+                        .collect()
+                })
+                .collect();
+            permutations.extend(reflected);
+        }
+        Some(Symmetries::Permutations(permutations))
+    }
+}
+
+/// a bit set of states
+type States = [u64; 4];
+fn contains(states: &States, state: u8) -> bool {
+    (states[usize::from(state / 64)] >> (state % 64)) & 1 == 1
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Slot {
+    State(u8),
+    /// every use of the same variable in a row has the same state
+    Variable {
+        index: usize,
+        states: States,
+    },
+}
+impl Slot {
+    /// if the slot can be `state`, binding its variable
+    fn bind(&self, state: u8, bound: &mut [Option<u8>]) -> bool {
+        match self {
+            Slot::State(slot) => *slot == state,
+            Slot::Variable { index, states } => {
+                if !contains(states, state) {
+                    return false;
+                }
+                *bound[*index].get_or_insert(state) == state
+            }
+        }
+    }
+}
+
+/// a single transition, with the center first, then the neighbours
+#[derive(PartialEq, Eq, Hash)]
+struct Row {
+    inputs: Vec<Slot>,
+    output: Slot,
+}
+impl Row {
+    fn next_state(&self, cells: &[u8], symmetries: &Symmetries, variables: usize) -> Option<u8> {
+        let mut bound = vec![None; variables];
+        if !self.inputs[0].bind(cells[0], &mut bound) {
+            return None;
+        }
+        let bound = match symmetries {
+            Symmetries::Permutations(permutations) => permutations.iter().find_map(|permutation| {
+                let mut bound = bound.clone();
+                permutation
+                    .iter()
+                    .zip(&self.inputs[1..])
+                    .all(|(&i, slot)| slot.bind(cells[1 + i], &mut bound))
+                    .then_some(bound)
+            }),
+            Symmetries::Permute => {
+                let mut used = vec![false; cells.len() - 1];
+                self.permute(&cells[1..], 1, &mut used, &bound)
+            }
+        }?;
+        Some(match self.output {
+            Slot::State(state) => state,
+            Slot::Variable { index, .. } => bound[index].expect("outputs are checked to be bound"),
+        })
+    }
+    /// matches the remaining slots with any of the unused neighbours
+    fn permute(
+        &self,
+        neighbours: &[u8],
+        slot: usize,
+        used: &mut [bool],
+        bound: &[Option<u8>],
+    ) -> Option<Vec<Option<u8>>> {
+        let Some(input) = self.inputs.get(slot) else {
+            return Some(bound.to_vec());
+        };
+        let mut tried = Vec::new();
+        for (i, &neighbour) in neighbours.iter().enumerate() {
+            // neighbours with the same state would match the same way
+            if used[i] || tried.contains(&neighbour) {
+                continue;
+            }
+            tried.push(neighbour);
+            let mut next_bound = bound.to_vec();
+            if input.bind(neighbour, &mut next_bound) {
+                used[i] = true;
+                let found = self.permute(neighbours, slot + 1, used, &next_bound);
+                used[i] = false;
+                if found.is_some() {
+                    return found;
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Rule {
+    /// reads a Golly `.rule` file, the rule is named by its `@RULE` line
+    pub fn read_from_bytes(bytes: &[u8]) -> Result<Rule, RuleFileError<&[u8]>> {
+        RuleFileReader::new(bytes).read()
+    }
+    pub fn read_from_string(string: &str) -> Result<Rule, RuleFileError<&[u8]>> {
+        RuleFileReader::new(string.as_bytes()).read()
+    }
+}
+
+// errors
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleFileErrorKind {
+    InvalidHeader,
+    InvalidContent,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RuleFileErrorHint {
+    MissingRule,
+    MissingName,
+    MissingTable,
+    DuplicateTable,
+    MissingField,
+    InvalidNumber,
+    InvalidStates,
+    InvalidNeighbourhood,
+    InvalidSymmetries,
+    InvalidVariable,
+    DuplicateVariable,
+    UnknownVariable,
+    StateTooLarge,
+    WrongInputCount,
+    UnboundOutput,
+    InvalidNeighbourCount,
+    WrongChildCount,
+    InvalidChild,
+    InvalidTreeLevel,
+    WrongNodeCount,
+    UnsupportedB0,
+}
+
+#[derive(Clone, Copy)]
+struct RuleFileErrorData<S> {
+    line: LineInfo<S>,
+    hint: RuleFileErrorHint,
+}
+#[derive(Clone)]
+pub struct RuleFileError<S>(Box<RuleFileErrorData<S>>);
+impl<S> RuleFileError<S> {
+    fn new(line: LineInfo<S>, hint: RuleFileErrorHint) -> Self {
+        Self(Box::new(RuleFileErrorData { line, hint }))
+    }
+    pub fn kind(&self) -> RuleFileErrorKind {
+        match self.0.hint {
+            RuleFileErrorHint::MissingRule | RuleFileErrorHint::MissingName => {
+                RuleFileErrorKind::InvalidHeader
+            }
+            _ => RuleFileErrorKind::InvalidContent,
+        }
+    }
+    pub fn line_src(&self) -> &S {
+        &self.0.line.line_src
+    }
+    /// 0 based line index
+    pub fn line(&self) -> usize {
+        self.0.line.line
+    }
+    /// 0 based column index
+    pub fn column(&self) -> usize {
+        self.0.line.column
+    }
+    fn hint_code(&self) -> &RuleFileErrorHint {
+        &self.0.hint
+    }
+    pub fn hint(&self) -> &'static str {
+        match self.hint_code() {
+            RuleFileErrorHint::MissingRule => "Rule files start with @RULE",
+            RuleFileErrorHint::MissingName => "Expected a name after @RULE",
+            RuleFileErrorHint::MissingTable => "Rule files need a @TABLE or @TREE section",
+            RuleFileErrorHint::DuplicateTable => "Rule files can only have one @TABLE or @TREE",
+            RuleFileErrorHint::MissingField => {
+                "Tables need n_states, neighborhood and symmetries before any transitions, and trees need num_states, num_neighbors and num_nodes"
+            }
+            RuleFileErrorHint::InvalidNumber => "Expected a number",
+            RuleFileErrorHint::InvalidStates => "Number of states must be between 2 and 256",
+            RuleFileErrorHint::InvalidNeighbourhood => {
+                "Neighborhood must be Moore, vonNeumann or hexagonal"
+            }
+            RuleFileErrorHint::InvalidSymmetries => {
+                "Symmetries must be none, permute, reflect_horizontal or rotateN with an optional reflect, where N divides the number of neighbours"
+            }
+            RuleFileErrorHint::InvalidVariable => "Variables are written like var a={0,1,2}",
+            RuleFileErrorHint::DuplicateVariable => "Variable is already defined",
+            RuleFileErrorHint::UnknownVariable => "Not a state or a defined variable",
+            RuleFileErrorHint::StateTooLarge => "State must be less than the number of states",
+            RuleFileErrorHint::WrongInputCount => {
+                "Transitions need the cell, each neighbour and then the new state"
+            }
+            RuleFileErrorHint::UnboundOutput => {
+                "The new state can only be a variable that is used earlier in the transition"
+            }
+            RuleFileErrorHint::InvalidNeighbourCount => "Number of neighbors must be 4 or 8",
+            RuleFileErrorHint::WrongChildCount => "Nodes need a level and a child for each state",
+            RuleFileErrorHint::InvalidChild => {
+                "Children must refer to an earlier node with a level 1 less"
+            }
+            RuleFileErrorHint::InvalidTreeLevel => {
+                "The last node is the root, and its level must be 1 more than the number of neighbors"
+            }
+            RuleFileErrorHint::WrongNodeCount => "Number of nodes doesn't match num_nodes",
+            RuleFileErrorHint::UnsupportedB0 => "Empty cells surrounded by empty cells must stay empty",
+        }
+    }
+}
+impl RuleFileError<&[u8]> {
+    pub fn into_owned(self) -> RuleFileError<Vec<u8>> {
+        RuleFileError(Box::new(RuleFileErrorData {
+            line: self.0.line.into_owned(),
+            hint: self.0.hint,
+        }))
+    }
+}
+impl<S: AsRef<[u8]>> Debug for RuleFileError<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RuleFileError")
+            .field("line", &self.line())
+            .field("column", &self.column())
+            .field(
+                "line_src",
+                &String::from_utf8_lossy(self.line_src().as_ref()),
+            )
+            .field("hint", &self.hint_code())
+            .finish()
+    }
+}
+impl<S: AsRef<[u8]>> Display for RuleFileError<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let line = self.line() + 1;
+        let line_src = String::from_utf8_lossy(self.line_src().as_ref());
+        let column = self.column() + 1;
+        let mark = "^";
+        let hint = self.hint();
+        writeln!(
+            f,
+            "Failed to parse rule file on line {line}:\n{line_src}\n{mark:>column$}\n{hint}\n"
+        )
+    }
+}
+impl<S: AsRef<[u8]>> Error for RuleFileError<S> {}
+
+// parsing
+
+type RuleFileResult<'src, T> = Result<T, RuleFileError<&'src [u8]>>;
+
+/// part of a line, and where it starts in the source
+#[derive(Clone, Copy)]
+struct Span<'src> {
+    at: usize,
+    src: &'src [u8],
+}
+impl<'src> Span<'src> {
+    fn trim(self) -> Self {
+        let start = self
+            .src
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(self.src.len());
+        let end = self
+            .src
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace())
+            .map_or(start, |end| end + 1);
+        Span {
+            at: self.at + start,
+            src: &self.src[start..end],
+        }
+    }
+    fn without_comment(self) -> Self {
+        let end = self
+            .src
+            .iter()
+            .position(|&b| b == b'#')
+            .unwrap_or(self.src.len());
+        Span {
+            at: self.at,
+            src: &self.src[..end],
+        }
+    }
+    /// the trimmed rest of the span if it starts with `key`
+    fn strip_key(self, key: &[u8]) -> Option<Self> {
+        self.src.starts_with(key).then(|| {
+            Span {
+                at: self.at + key.len(),
+                src: &self.src[key.len()..],
+            }
+            .trim()
+        })
+    }
+    fn split(self, separator: impl Fn(u8) -> bool) -> impl Iterator<Item = Span<'src>> {
+        let mut at = self.at;
+        self.src.split(move |&b| separator(b)).map(move |src| {
+            let span = Span { at, src };
+            at += src.len() + 1;
+            span
+        })
+    }
+}
+
+struct RuleFileReader<'src> {
+    src: &'src [u8],
+}
+impl<'src> RuleFileReader<'src> {
+    fn new(src: &'src [u8]) -> Self {
+        Self { src }
+    }
+
+    fn read(&self) -> RuleFileResult<'src, Rule> {
+        let mut lines = Span {
+            at: 0,
+            src: self.src,
+        }
+        .split(|b| b == b'\n')
+        .map(|line| Span {
+            at: line.at,
+            src: line.src.strip_suffix(b"\r").unwrap_or(line.src),
+        })
+        .filter(|line| !line.trim().src.is_empty())
+        .peekable();
+
+        let Some(header) = lines.next() else {
+            return self.fail(self.src.len(), RuleFileErrorHint::MissingRule);
+        };
+        let Some(name) = header.trim().strip_key(b"@RULE") else {
+            return self.fail(header.trim().at, RuleFileErrorHint::MissingRule);
+        };
+        if name.src.is_empty() {
+            return self.fail(name.at, RuleFileErrorHint::MissingName);
+        }
+        let name = String::from_utf8_lossy(name.src).into_owned();
+
+        let mut table = None;
+        while let Some(line) = lines.next() {
+            let line = line.trim();
+            if !line.src.starts_with(b"@") {
+                continue;
+            }
+            let is_table = line.src.starts_with(b"@TABLE");
+            if !is_table && !line.src.starts_with(b"@TREE") {
+                continue;
+            }
+            if table.is_some() {
+                return self.fail(line.at, RuleFileErrorHint::DuplicateTable);
+            }
+            let mut section = Vec::new();
+            while let Some(next) = lines.next_if(|next| !next.trim().src.starts_with(b"@")) {
+                section.push(next.without_comment().trim());
+            }
+            let section = section.into_iter().filter(|line| !line.src.is_empty());
+            table = Some(if is_table {
+                self.read_table(line, section)?
+            } else {
+                self.read_tree(line, section)?
+            });
+        }
+        let Some((lookup, states, header)) = table else {
+            return self.fail(self.src.len(), RuleFileErrorHint::MissingTable);
+        };
+        if lookup.next_state(&[0; 9]) != 0 {
+            return self.fail(header.at, RuleFileErrorHint::UnsupportedB0);
+        }
+        let table = RuleTable {
+            name,
+            lookup,
+            cache: RefCell::new(HashMap::new()),
+        };
+        Ok(Rule::from_table(table, states))
+    }
+
+    fn read_table(
+        &self,
+        header: Span<'src>,
+        lines: impl Iterator<Item = Span<'src>>,
+    ) -> RuleFileResult<'src, (Lookup, u16, Span<'src>)> {
+        let mut states = None;
+        let mut order = None;
+        let mut symmetries = None;
+        let mut variables: HashMap<&[u8], (usize, States)> = HashMap::new();
+        let mut rows = Vec::new();
+        for line in lines {
+            if let Some(value) = line.strip_key(b"n_states:") {
+                states = Some(self.read_states(value)?);
+            } else if let Some(value) = line.strip_key(b"neighborhood:") {
+                order = Some(match value.src {
+                    b"Moore" => TABLE_MOORE,
+                    b"vonNeumann" => TABLE_VON_NEUMANN,
+                    b"hexagonal" => TABLE_HEXAGONAL,
+                    _ => return self.fail(value.at, RuleFileErrorHint::InvalidNeighbourhood),
+                });
+            } else if let Some(value) = line.strip_key(b"symmetries:") {
+                let Some(order) = order else {
+                    return self.fail(line.at, RuleFileErrorHint::MissingField);
+                };
+                symmetries = Some(
+                    Symmetries::from_name(value.src, order.len() - 1).map_or_else(
+                        || self.fail(value.at, RuleFileErrorHint::InvalidSymmetries),
+                        Ok,
+                    )?,
+                );
+            } else if let Some(value) = line.strip_key(b"var ") {
+                let Some(states) = states else {
+                    return self.fail(line.at, RuleFileErrorHint::MissingField);
+                };
+                let (name, values) = self.read_variable(value, states, &variables)?;
+                if variables.contains_key(name.src) {
+                    return self.fail(name.at, RuleFileErrorHint::DuplicateVariable);
+                }
+                variables.insert(name.src, (variables.len(), values));
+            } else {
+                let (Some(states), Some(order), Some(_)) = (states, order, &symmetries) else {
+                    return self.fail(line.at, RuleFileErrorHint::MissingField);
+                };
+                rows.push(self.read_row(line, states, order.len(), &variables)?);
+            }
+        }
+        let (Some(states), Some(order), Some(symmetries)) = (states, order, symmetries) else {
+            return self.fail(header.at, RuleFileErrorHint::MissingField);
+        };
+        let lookup = Lookup::Table {
+            order,
+            symmetries,
+            rows,
+            variables: variables.len(),
+        };
+        Ok((lookup, states, header))
+    }
+    fn read_variable(
+        &self,
+        value: Span<'src>,
+        states: u16,
+        variables: &HashMap<&[u8], (usize, States)>,
+    ) -> RuleFileResult<'src, (Span<'src>, States)> {
+        let mut parts = value.split(|b| b == b'=');
+        let (Some(name), Some(values), None) = (parts.next(), parts.next(), parts.next()) else {
+            return self.fail(value.at, RuleFileErrorHint::InvalidVariable);
+        };
+        let (name, values) = (name.trim(), values.trim());
+        let Some(list) = values
+            .src
+            .strip_prefix(b"{")
+            .and_then(|list| list.strip_suffix(b"}"))
+        else {
+            return self.fail(values.at, RuleFileErrorHint::InvalidVariable);
+        };
+        if name.src.is_empty() || name.src.iter().any(|b| !is_name_char(*b)) {
+            return self.fail(name.at, RuleFileErrorHint::InvalidVariable);
+        }
+        let list = Span {
+            at: values.at + 1,
+            src: list,
+        };
+        let mut set = [0; 4];
+        for item in list.split(|b| b == b',') {
+            match self.read_slot(item.trim(), states, variables)? {
+                Slot::State(state) => set[usize::from(state / 64)] |= 1 << (state % 64),
+                Slot::Variable { states, .. } => {
+                    for (set, states) in set.iter_mut().zip(states) {
+                        *set |= states;
+                    }
+                }
+            }
+        }
+        Ok((name, set))
+    }
+    fn read_row(
+        &self,
+        line: Span<'src>,
+        states: u16,
+        inputs: usize,
+        variables: &HashMap<&[u8], (usize, States)>,
+    ) -> RuleFileResult<'src, Row> {
+        // states can be separated by commas, spaces, or nothing if they are all single digits
+        let items: Vec<Span> = if line.src.contains(&b',') {
+            line.split(|b| b == b',').map(Span::trim).collect()
+        } else if line.src.iter().any(u8::is_ascii_whitespace) {
+            line.split(|b| b.is_ascii_whitespace())
+                .filter(|item| !item.src.is_empty())
+                .collect()
+        } else {
+            (0..line.src.len())
+                .map(|i| Span {
+                    at: line.at + i,
+                    src: &line.src[i..=i],
+                })
+                .collect()
+        };
+        if items.len() != inputs + 1 {
+            return self.fail(line.at, RuleFileErrorHint::WrongInputCount);
+        }
+        let slots = items
+            .iter()
+            .map(|&item| self.read_slot(item, states, variables))
+            .collect::<Result<Vec<Slot>, _>>()?;
+        let (output, inputs) = slots.split_last().expect("at least 1 item");
+        if let Slot::Variable { index, .. } = output {
+            let is_bound = inputs.iter().any(
+                |input| matches!(input, Slot::Variable { index: bound, .. } if bound == index),
+            );
+            if !is_bound {
+                return self.fail(items[items.len() - 1].at, RuleFileErrorHint::UnboundOutput);
+            }
+        }
+        Ok(Row {
+            inputs: inputs.to_vec(),
+            output: output.clone(),
+        })
+    }
+    fn read_slot(
+        &self,
+        item: Span<'src>,
+        states: u16,
+        variables: &HashMap<&[u8], (usize, States)>,
+    ) -> RuleFileResult<'src, Slot> {
+        if item.src.first().is_some_and(u8::is_ascii_digit) {
+            let state = self.read_number(item)?;
+            if state >= usize::from(states) {
+                return self.fail(item.at, RuleFileErrorHint::StateTooLarge);
+            }
+            #[allow(clippy::cast_possible_truncation)] // at most 255
+            Ok(Slot::State(state as u8))
+        } else {
+            match variables.get(item.src) {
+                Some(&(index, states)) => Ok(Slot::Variable { index, states }),
+                None => self.fail(item.at, RuleFileErrorHint::UnknownVariable),
+            }
+        }
+    }
+
+    fn read_tree(
+        &self,
+        header: Span<'src>,
+        lines: impl Iterator<Item = Span<'src>>,
+    ) -> RuleFileResult<'src, (Lookup, u16, Span<'src>)> {
+        let mut states = None;
+        let mut order = None;
+        let mut node_count = None;
+        let mut levels = Vec::new();
+        let mut nodes = Vec::new();
+        let mut last = header;
+        for line in lines {
+            last = line;
+            if let Some(value) = line.strip_key(b"num_states=") {
+                states = Some(self.read_states(value)?);
+            } else if let Some(value) = line.strip_key(b"num_neighbors=") {
+                order = Some(match value.src {
+                    b"4" => TREE_VON_NEUMANN,
+                    b"8" => TREE_MOORE,
+                    _ => return self.fail(value.at, RuleFileErrorHint::InvalidNeighbourCount),
+                });
+            } else if let Some(value) = line.strip_key(b"num_nodes=") {
+                node_count = Some(self.read_number(value)?);
+            } else {
+                let (Some(states), Some(_), Some(_)) = (states, order, node_count) else {
+                    return self.fail(line.at, RuleFileErrorHint::MissingField);
+                };
+                let items: Vec<Span> = line
+                    .split(|b| b.is_ascii_whitespace())
+                    .filter(|item| !item.src.is_empty())
+                    .collect();
+                if items.len() != usize::from(states) + 1 {
+                    return self.fail(line.at, RuleFileErrorHint::WrongChildCount);
+                }
+                let level = self.read_number(items[0])?;
+                let mut children = Vec::with_capacity(items.len() - 1);
+                for &item in &items[1..] {
+                    let child = self.read_number(item)?;
+                    let is_valid = if level == 1 {
+                        child < usize::from(states)
+                    } else {
+                        level > 1 && levels.get(child) == Some(&(level - 1))
+                    };
+                    if !is_valid {
+                        return self.fail(item.at, RuleFileErrorHint::InvalidChild);
+                    }
+                    #[allow(clippy::cast_possible_truncation)] // less than the number of lines
+                    children.push(child as u32);
+                }
+                levels.push(level);
+                nodes.push(children);
+            }
+        }
+        let (Some(states), Some(order), Some(node_count)) = (states, order, node_count) else {
+            return self.fail(header.at, RuleFileErrorHint::MissingField);
+        };
+        if nodes.len() != node_count {
+            return self.fail(last.at, RuleFileErrorHint::WrongNodeCount);
+        }
+        if levels.last() != Some(&order.len()) {
+            return self.fail(last.at, RuleFileErrorHint::InvalidTreeLevel);
+        }
+        Ok((Lookup::Tree { order, nodes }, states, header))
+    }
+
+    fn read_states(&self, value: Span<'src>) -> RuleFileResult<'src, u16> {
+        match self.read_number(value)? {
+            #[allow(clippy::cast_possible_truncation)] // at most 256
+            states @ 2..=256 => Ok(states as u16),
+            _ => self.fail(value.at, RuleFileErrorHint::InvalidStates),
+        }
+    }
+    fn read_number(&self, value: Span<'src>) -> RuleFileResult<'src, usize> {
+        if value.src.is_empty() || !value.src.iter().all(u8::is_ascii_digit) {
+            return self.fail(value.at, RuleFileErrorHint::InvalidNumber);
+        }
+        value
+            .src
+            .iter()
+            .try_fold(0_usize, |number, b| {
+                number.checked_mul(10)?.checked_add(usize::from(b - b'0'))
+            })
+            .map_or_else(|| self.fail(value.at, RuleFileErrorHint::InvalidNumber), Ok)
+    }
+
+    fn fail<T>(&self, at: usize, hint: RuleFileErrorHint) -> RuleFileResult<'src, T> {
+        Err(RuleFileError::new(
+            LineInfo::from_src_offset(self.src, at),
+            hint,
+        ))
+    }
+}
+
+fn is_name_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transition;
+
+    fn table_of(rule: &Rule) -> &RuleTable {
+        match rule.transition() {
+            Transition::Table(table) => table,
+            _ => panic!("expected a table"),
+        }
+    }
+    /// cells from rows of digits
+    fn cells(rows: &str) -> Cells {
+        let digits: Vec<u8> = rows
+            .bytes()
+            .filter(u8::is_ascii_digit)
+            .map(|b| b - b'0')
+            .collect();
+        digits.try_into().unwrap()
+    }
+
+    #[test]
+    fn table() {
+        let rule = Rule::read_from_string(
+            "@RULE Test
+
+            blank lines and other text before a section are skipped
+            @TABLE
+            n_states:3 # trailing comment
+            neighborhood:Moore
+            symmetries:rotate4reflect
+            var a={1,2}
+            var b={0,a}
+            0,1,1,0,0,0,0,0,0,2
+            1 a a b b b b b b 0
+            20000000bb # same as the north west neighbour
+            @COLORS
+            1 255 0 0
+            ",
+        )
+        .unwrap();
+        assert_eq!(rule.to_string(), "Test");
+        assert_eq!(rule.states(), 3);
+        let table = table_of(&rule);
+        // north and north east, in each rotation and reflection
+        assert_eq!(table.next_state(&cells("011 000 000")), 2);
+        assert_eq!(table.next_state(&cells("000 001 001")), 2);
+        assert_eq!(table.next_state(&cells("110 000 000")), 2);
+        assert_eq!(table.next_state(&cells("100 100 000")), 2);
+        assert_eq!(table.next_state(&cells("101 000 000")), 0);
+        // variables used twice must be the same
+        assert_eq!(table.next_state(&cells("002 012 000")), 0);
+        assert_eq!(table.next_state(&cells("202 010 000")), 1);
+        assert_eq!(table.next_state(&cells("120 010 000")), 1);
+        assert_eq!(table.next_state(&cells("000 020 000")), 0);
+        assert_eq!(table.next_state(&cells("001 020 000")), 1);
+    }
+
+    #[test]
+    fn symmetries() {
+        let rule = |symmetries: &str| {
+            let src = format!(
+                "@RULE Test\n@TABLE\nn_states:2\nneighborhood:hexagonal\nsymmetries:{symmetries}\n0,1,1,0,0,0,0,1\n"
+            );
+            let rule = Rule::read_from_string(&src).unwrap();
+            // north and east, north and south east, north and south
+            ["010 001 000", "010 000 001", "010 000 010"]
+                .map(|rows| table_of(&rule).next_state(&cells(rows)))
+        };
+        assert_eq!(rule("none"), [1, 0, 0]);
+        assert_eq!(rule("rotate6"), [1, 0, 0]);
+        assert_eq!(rule("permute"), [1, 1, 1]);
+    }
+
+    #[test]
+    fn tree() {
+        // cells with a live west neighbour are born, and nothing survives
+        let rule = Rule::read_from_string(
+            "@RULE West
+            @TREE
+            num_states=2
+            num_neighbors=4
+            num_nodes=7
+            1 0 0
+            1 1 1
+            2 0 0
+            2 1 1
+            3 2 2
+            3 3 3
+            4 4 5
+            5 6 6
+            ",
+        );
+        let err = rule.unwrap_err();
+        assert_eq!(
+            (err.line(), err.hint_code()),
+            (12, &RuleFileErrorHint::WrongNodeCount)
+        );
+
+        let rule = Rule::read_from_string(
+            "@RULE West
+            @TREE
+            num_states=2
+            num_neighbors=4
+            num_nodes=8
+            1 0 0
+            1 1 1
+            2 0 0
+            2 1 1
+            3 2 2
+            3 3 3
+            4 4 5
+            5 6 6
+            ",
+        )
+        .unwrap();
+        let table = table_of(&rule);
+        assert_eq!(table.next_state(&cells("000 100 000")), 1);
+        assert_eq!(table.next_state(&cells("000 110 000")), 1);
+        assert_eq!(table.next_state(&cells("010 011 010")), 0);
+    }
+
+    #[test]
+    fn invalid() {
+        let header = "@RULE Test\n@TABLE\nn_states:3\nneighborhood:vonNeumann\nsymmetries:none\n";
+        for (src, line, column, hint) in [
+            ("", 0, 0, RuleFileErrorHint::MissingRule),
+            ("@TABLE\n", 0, 0, RuleFileErrorHint::MissingRule),
+            ("@RULE\n", 0, 5, RuleFileErrorHint::MissingName),
+            ("@RULE Test\n", 0, 11, RuleFileErrorHint::MissingTable),
+            (
+                "@RULE Test\n@TABLE\nn_states:1\n",
+                2,
+                9,
+                RuleFileErrorHint::InvalidStates,
+            ),
+            (
+                "@RULE Test\n@TABLE\nn_states:x\n",
+                2,
+                9,
+                RuleFileErrorHint::InvalidNumber,
+            ),
+            (
+                "@RULE Test\n@TABLE\n0,0,0,0,0,0\n",
+                2,
+                0,
+                RuleFileErrorHint::MissingField,
+            ),
+            (
+                "@RULE Test\n@TABLE\nn_states:2\n",
+                1,
+                0,
+                RuleFileErrorHint::MissingField,
+            ),
+            (
+                "@RULE Test\n@TABLE\nneighborhood: Hex\n",
+                2,
+                14,
+                RuleFileErrorHint::InvalidNeighbourhood,
+            ),
+            (
+                "@RULE Test\n@TABLE\nneighborhood:vonNeumann\nsymmetries:rotate8\n",
+                3,
+                11,
+                RuleFileErrorHint::InvalidSymmetries,
+            ),
+            ("var a=1\n", 5, 6, RuleFileErrorHint::InvalidVariable),
+            ("var a={0,3}\n", 5, 9, RuleFileErrorHint::StateTooLarge),
+            ("var a={0,b}\n", 5, 9, RuleFileErrorHint::UnknownVariable),
+            (
+                "var a={0}\nvar a={1}\n",
+                6,
+                4,
+                RuleFileErrorHint::DuplicateVariable,
+            ),
+            ("0,0,0,0,1\n", 5, 0, RuleFileErrorHint::WrongInputCount),
+            ("0 0 0 0 0 3\n", 5, 10, RuleFileErrorHint::StateTooLarge),
+            (
+                "var a={1,2}\n1,0,0,0,0,a\n",
+                6,
+                10,
+                RuleFileErrorHint::UnboundOutput,
+            ),
+            ("00001 1\n", 5, 0, RuleFileErrorHint::WrongInputCount),
+            ("000001\n", 1, 0, RuleFileErrorHint::UnsupportedB0),
+            ("000010\n@TABLE\n", 6, 0, RuleFileErrorHint::DuplicateTable),
+        ] {
+            let src = if src.starts_with('@') || src.is_empty() {
+                src.to_string()
+            } else {
+                format!("{header}{src}")
+            };
+            let err = Rule::read_from_string(&src).unwrap_err();
+            assert_eq!(
+                (err.line(), err.column(), err.hint_code()),
+                (line, column, &hint),
+                "{src}"
+            );
+        }
+
+        let tree = "@RULE Test\n@TREE\nnum_states=2\nnum_neighbors=4\nnum_nodes=2\n";
+        for (src, line, column, hint) in [
+            (
+                "@RULE Test\n@TREE\nnum_neighbors=6\n",
+                2,
+                14,
+                RuleFileErrorHint::InvalidNeighbourCount,
+            ),
+            ("1 0\n", 5, 0, RuleFileErrorHint::WrongChildCount),
+            ("1 0 2\n", 5, 4, RuleFileErrorHint::InvalidChild),
+            ("1 0 1\n2 0 1\n", 6, 4, RuleFileErrorHint::InvalidChild),
+            ("1 0 1\n2 0 0\n", 6, 0, RuleFileErrorHint::InvalidTreeLevel),
+        ] {
+            let src = if src.starts_with('@') {
+                src.to_string()
+            } else {
+                format!("{tree}{src}")
+            };
+            let err = Rule::read_from_string(&src).unwrap_err();
+            assert_eq!(
+                (err.line(), err.column(), err.hint_code()),
+                (line, column, &hint),
+                "{src}"
+            );
+        }
+    }
+}
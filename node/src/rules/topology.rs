@@ -0,0 +1,96 @@
+//! bounded grids, written after a rule in Golly's notation such as `B3/S23:T100,80`
+
+use std::fmt::Display;
+
+use crate::{Pos, Rect};
+
+/// which pair of edges of a Klein bottle is joined with a twist
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TwistedEdges {
+    /// crossing the top or bottom edge mirrors horizontally, written as `K100*,80`
+    Horizontal,
+    /// crossing the left or right edge mirrors vertically, written as `K100,80*`
+    Vertical,
+}
+
+/// how the edges of a bounded grid are joined
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Topology {
+    /// cells outside of the grid are always dead
+    Plane,
+    /// opposite edges are joined
+    Torus,
+    /// opposite edges are joined, but one pair is joined with a twist
+    KleinBottle(TwistedEdges),
+}
+impl Topology {
+    fn letter(self) -> char {
+        match self {
+            Topology::Plane => 'P',
+            Topology::Torus => 'T',
+            Topology::KleinBottle(_) => 'K',
+        }
+    }
+}
+
+/// a grid with a topology, centered on the origin the same way as Golly
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BoundedGrid {
+    topology: Topology,
+    width: u32,
+    height: u32,
+}
+impl BoundedGrid {
+    pub const MAX_SIZE: u32 = 1 << 30;
+
+    /// panics if either side is 0 or larger than `MAX_SIZE`
+    pub fn new(topology: Topology, width: u32, height: u32) -> Self {
+        assert!(
+            (1..=Self::MAX_SIZE).contains(&width) && (1..=Self::MAX_SIZE).contains(&height),
+            "grid sides must be between 1 and {}",
+            Self::MAX_SIZE
+        );
+        Self {
+            topology,
+            width,
+            height,
+        }
+    }
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    /// the cells inside the grid, with the extra row or column past the origin for odd sizes
+    pub fn rect(&self) -> Rect {
+        let west = -i64::from(self.width / 2);
+        let north = -i64::from(self.height / 2);
+        Rect::min_max(
+            Pos::new(west, north),
+            Pos::new(
+                west + i64::from(self.width) - 1,
+                north + i64::from(self.height) - 1,
+            ),
+        )
+    }
+}
+impl Display for BoundedGrid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (width_twist, height_twist) = match self.topology {
+            Topology::KleinBottle(TwistedEdges::Horizontal) => ("*", ""),
+            Topology::KleinBottle(TwistedEdges::Vertical) => ("", "*"),
+            _ => ("", ""),
+        };
+        write!(
+            f,
+            ":{}{}{width_twist},{}{height_twist}",
+            self.topology.letter(),
+            self.width,
+            self.height
+        )
+    }
+}